@@ -0,0 +1,325 @@
+//! Derive macros for `rc_arena`, re-exported by its `derive` feature.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{Data, DeriveInput, Error, Fields, Ident};
+
+/// The named fields of a struct, or an error pointing at whatever else was
+/// given.
+fn named_fields<'a>(input: &'a DeriveInput, derive: &str) -> Result<Vec<&'a syn::Field>, Error> {
+    match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => Ok(fields.named.iter().collect()),
+            _ => Err(Error::new_spanned(&data.fields, format!("{} needs named fields", derive)))
+        },
+        _ => Err(Error::new_spanned(&input.ident, format!("{} can only be derived for structs", derive)))
+    }
+}
+
+/// Derive a struct-of-arrays arena for a struct, named after it with an
+/// `Arena` suffix, which stores each field in an arena of its own. Storing
+/// a value hands out a handle, named with a `Handle` suffix, with an `Rc` to
+/// each of its fields.
+#[proc_macro_derive(SoaArena)]
+pub fn derive_soa_arena(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match soa_arena(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+fn soa_arena(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = named_fields(input, "SoaArena")?;
+    let vis = &input.vis;
+    let name = &input.ident;
+    let arena = Ident::new(&format!("{}Arena", name), Span::call_site());
+    let handle = Ident::new(&format!("{}Handle", name), Span::call_site());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let field_names: Vec<_> = fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+    let field_vis: Vec<_> = fields.iter().map(|field| &field.vis).collect();
+    let field_types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let first = field_names.first();
+    let len = match first {
+        Some(first) => quote!(self.#first.len()),
+        None => quote!(0)
+    };
+    let arena_doc = format!("A struct-of-arrays arena of `{}`s, with an arena per field.", name);
+    let handle_doc = format!("A handle to a `{}` stored in a `{}`.", name, arena);
+
+    Ok(quote! {
+        #[doc = #arena_doc]
+        #vis struct #arena #impl_generics #where_clause {
+            #(#field_vis #field_names: ::rc_arena::Arena<#field_types>,)*
+        }
+
+        #[doc = #handle_doc]
+        #vis struct #handle #impl_generics #where_clause {
+            #(#field_vis #field_names: ::rc_arena::Rc<#field_types>,)*
+        }
+
+        impl #impl_generics #arena #ty_generics #where_clause {
+            /// Create a new arena with an unspecified capacity.
+            #[allow(dead_code)]
+            #vis fn new() -> Self {
+                Self::with_capacity(8)
+            }
+
+            /// Create a new arena with a known initial capacity.
+            #[allow(dead_code)]
+            #vis fn with_capacity(n: usize) -> Self {
+                #arena {
+                    #(#field_names: ::rc_arena::Arena::with_capacity(n),)*
+                }
+            }
+
+            /// Store a value in the arena, splitting it into its fields.
+            #[allow(dead_code)]
+            #vis fn alloc(&self, value: #name #ty_generics) -> #handle #ty_generics {
+                let #name { #(#field_names,)* } = value;
+
+                #handle {
+                    #(#field_names: self.#field_names.alloc(#field_names),)*
+                }
+            }
+
+            /// Get the number of values currently placed in the arena.
+            #[allow(dead_code)]
+            #vis fn len(&self) -> usize {
+                #len
+            }
+
+            /// Returns `true` if no values have been placed in the arena.
+            #[allow(dead_code)]
+            #vis fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+        }
+
+        impl #impl_generics ::std::default::Default for #arena #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl #impl_generics ::std::clone::Clone for #arena #ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                #arena {
+                    #(#field_names: self.#field_names.clone(),)*
+                }
+            }
+        }
+
+        impl #impl_generics ::std::clone::Clone for #handle #ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                #handle {
+                    #(#field_names: self.#field_names.clone(),)*
+                }
+            }
+        }
+    })
+}
+
+/// Convert a variant name like `BinaryOp` to `binary_op`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+
+    snake
+}
+
+/// The parameters of a constructor taking each of `fields`, and the
+/// expression building the value from them.
+fn constructor(path: proc_macro2::TokenStream, fields: &Fields) -> (Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream) {
+    match *fields {
+        Fields::Named(ref fields) => {
+            let names: Vec<_> = fields.named.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+            let params = fields.named.iter().zip(&names).map(|(field, name)| {
+                let ty = &field.ty;
+                quote!(#name: #ty)
+            }).collect();
+
+            (params, quote!(#path { #(#names,)* }))
+        }
+        Fields::Unnamed(ref fields) => {
+            let names: Vec<_> = (0..fields.unnamed.len()).map(|i| Ident::new(&format!("_{}", i), Span::call_site())).collect();
+            let params = fields.unnamed.iter().zip(&names).map(|(field, name)| {
+                let ty = &field.ty;
+                quote!(#name: #ty)
+            }).collect();
+
+            (params, quote!(#path(#(#names,)*)))
+        }
+        Fields::Unit => (vec![], path)
+    }
+}
+
+/// Derive constructors which store a value directly in an arena, for types
+/// like syntax trees whose children are `Rc<Self>`, returning the new `Rc`.
+///
+/// A struct gets a `build_in` function taking the arena and each field. An
+/// enum gets a function per variant, named after the variant in snake case
+/// with an `_in` suffix.
+#[proc_macro_derive(ArenaTree)]
+pub fn derive_arena_tree(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match arena_tree(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+fn arena_tree(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let vis = &input.vis;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let constructors: Vec<_> = match input.data {
+        Data::Struct(ref data) => {
+            let (params, value) = constructor(quote!(#name), &data.fields);
+            let doc = format!("Store a new `{}` in `arena`.", name);
+
+            vec![quote! {
+                #[doc = #doc]
+                #[allow(dead_code)]
+                #vis fn build_in(arena: &::rc_arena::Arena<Self>, #(#params),*) -> ::rc_arena::Rc<Self> {
+                    arena.alloc(#value)
+                }
+            }]
+        }
+        Data::Enum(ref data) => data.variants.iter().map(|variant| {
+            let variant_name = &variant.ident;
+            let function = Ident::new(&format!("{}_in", snake_case(&variant_name.to_string())), variant_name.span());
+            let (params, value) = constructor(quote!(#name::#variant_name), &variant.fields);
+            let doc = format!("Store a new `{}::{}` in `arena`.", name, variant_name);
+
+            quote! {
+                #[doc = #doc]
+                #[allow(dead_code)]
+                #vis fn #function(arena: &::rc_arena::Arena<Self>, #(#params),*) -> ::rc_arena::Rc<Self> {
+                    arena.alloc(#value)
+                }
+            }
+        }).collect(),
+        Data::Union(ref data) => {
+            return Err(Error::new_spanned(data.union_token, "ArenaTree can't be derived for unions"));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#constructors)*
+        }
+    })
+}
+
+/// Derive an `alloc_in` constructor for a struct, which takes the arena and
+/// each field and stores the new value in the arena, returning its `Rc`.
+/// Structs with named fields also get a builder, named after the struct with
+/// a `Builder` suffix, which sets the fields one at a time.
+#[proc_macro_derive(ArenaAlloc)]
+pub fn derive_arena_alloc(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match arena_alloc(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+fn arena_alloc(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let vis = &input.vis;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ => return Err(Error::new_spanned(&input.ident, "ArenaAlloc can only be derived for structs"))
+    };
+    let (params, value) = constructor(quote!(#name), &data.fields);
+    let doc = format!("Store a new `{}` in `arena`.", name);
+
+    let alloc_in = quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[doc = #doc]
+            #[allow(dead_code)]
+            #vis fn alloc_in(arena: &::rc_arena::Arena<Self>, #(#params),*) -> ::rc_arena::Rc<Self> {
+                arena.alloc(#value)
+            }
+        }
+    };
+
+    let fields = match data.fields {
+        Fields::Named(ref fields) => fields,
+        _ => return Ok(alloc_in)
+    };
+
+    let builder = Ident::new(&format!("{}Builder", name), Span::call_site());
+    let field_names: Vec<_> = fields.named.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+    let field_types: Vec<_> = fields.named.iter().map(|field| &field.ty).collect();
+    let missing: Vec<_> = field_names.iter().map(|name| format!("field `{}` wasn't set", name)).collect();
+    let setter_docs: Vec<_> = field_names.iter().map(|name| format!("Set the `{}` field.", name)).collect();
+    let builder_doc = format!("A builder for a `{}`, which is stored in an arena once its fields are set.", name);
+
+    Ok(quote! {
+        #alloc_in
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Start building a value to store in an arena, with none of its
+            /// fields set.
+            #[allow(dead_code)]
+            #vis fn builder() -> #builder #ty_generics {
+                #builder {
+                    #(#field_names: None,)*
+                }
+            }
+        }
+
+        #[doc = #builder_doc]
+        #vis struct #builder #impl_generics #where_clause {
+            #(#field_names: ::std::option::Option<#field_types>,)*
+        }
+
+        impl #impl_generics #builder #ty_generics #where_clause {
+            #(
+                #[doc = #setter_docs]
+                #[allow(dead_code)]
+                #vis fn #field_names(mut self, #field_names: #field_types) -> Self {
+                    self.#field_names = Some(#field_names);
+                    self
+                }
+            )*
+
+            /// Store the value in `arena`.
+            ///
+            /// # Panics
+            ///
+            /// Panics if any of the fields hasn't been set.
+            #[allow(dead_code)]
+            #vis fn alloc_in(self, arena: &::rc_arena::Arena<#name #ty_generics>) -> ::rc_arena::Rc<#name #ty_generics> {
+                arena.alloc(#name {
+                    #(#field_names: self.#field_names.expect(#missing),)*
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,192 @@
+//! Support for storing the objects of an arena in memory from a custom
+//! allocator, using the `Allocator` trait from `allocator-api2`. On nightly,
+//! that crate's `nightly` feature makes this the standard library's trait.
+//!
+//! With the `bumpalo` feature, chunks can also be carved out of a shared
+//! `bumpalo::Bump`.
+
+use std::alloc::Layout;
+use std::ptr::NonNull;
+
+use allocator_api2::alloc::{AllocError, Allocator};
+#[cfg(feature = "bumpalo")]
+use bumpalo::Bump;
+
+use {Arena, ArenaError, Chunk, Chunks, Items};
+
+/// A shared handle to the allocator each chunk of an arena comes from.
+#[derive(Clone)]
+pub struct ChunkAllocator(std::rc::Rc<dyn Allocator>);
+
+impl ChunkAllocator {
+    pub fn new<A: Allocator + 'static>(allocator: A) -> ChunkAllocator {
+        ChunkAllocator(std::rc::Rc::new(allocator))
+    }
+}
+
+unsafe impl Allocator for ChunkAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(&self,
+                   ptr: NonNull<u8>,
+                   old_layout: Layout,
+                   new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(&self,
+                     ptr: NonNull<u8>,
+                     old_layout: Layout,
+                     new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a new arena with an unspecified capacity, whose objects are
+    /// stored in memory from `allocator`.
+    pub fn new_in<A: Allocator + 'static>(allocator: A) -> Arena<T> {
+        Arena::with_capacity_in(8, allocator)
+    }
+
+    /// Create a new arena with a known initial capacity, whose objects are
+    /// stored in memory from `allocator`. The arena's own bookkeeping is
+    /// still allocated from the global allocator.
+    ///
+    /// ```rust
+    /// extern crate allocator_api2;
+    /// extern crate rc_arena;
+    ///
+    /// use allocator_api2::alloc::Global;
+    /// use rc_arena::Arena;
+    ///
+    /// # fn main() {
+    /// let arena = Arena::with_capacity_in(16, Global);
+    /// let foo = arena.alloc(1);
+    ///
+    /// assert_eq!(*foo, 1);
+    /// # }
+    /// ```
+    pub fn with_capacity_in<A: Allocator + 'static>(n: usize, allocator: A) -> Arena<T> {
+        match Arena::try_with_capacity_in(n, allocator) {
+            Ok(arena) => arena,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    /// Like `with_capacity_in`, but fails rather than panicking if the first
+    /// chunk can't be allocated.
+    pub fn try_with_capacity_in<A: Allocator + 'static>(n: usize, allocator: A) -> Result<Arena<T>, ArenaError> {
+        let allocator = ChunkAllocator::new(allocator);
+        let mut first = allocator_api2::vec::Vec::new_in(allocator.clone());
+        first.try_reserve_exact(n).map_err(|_| ArenaError::AllocationFailed)?;
+
+        let mut chunks = Chunks::new(vec![Chunk::new(Items::Allocated(first), n)]);
+        chunks.allocator = Some(allocator);
+
+        Ok(Arena::from_chunks(chunks))
+    }
+}
+
+/// Allocates from a bump region which lives as long as the arena needs it.
+#[cfg(feature = "bumpalo")]
+struct SharedBump(std::rc::Rc<Bump>);
+
+#[cfg(feature = "bumpalo")]
+unsafe impl Allocator for SharedBump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        (&*self.0).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (&*self.0).deallocate(ptr, layout)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<T> Arena<T> {
+    /// Create a new arena with a known initial capacity, whose chunks are
+    /// carved out of `bump`. The region is kept alive until the arena and
+    /// all of its handles have been dropped, so everything allocated from it
+    /// for one task can be freed at once.
+    ///
+    /// ```rust
+    /// extern crate bumpalo;
+    /// extern crate rc_arena;
+    ///
+    /// use std::rc::Rc;
+    /// use bumpalo::Bump;
+    /// use rc_arena::Arena;
+    ///
+    /// # fn main() {
+    /// let bump = Rc::new(Bump::new());
+    /// let strings = Arena::with_capacity_in_bump(16, bump.clone());
+    /// let numbers = Arena::with_capacity_in_bump(16, bump.clone());
+    ///
+    /// strings.alloc("Hello");
+    /// numbers.alloc(1);
+    /// assert!(bump.allocated_bytes() > 0);
+    /// # }
+    /// ```
+    pub fn with_capacity_in_bump(n: usize, bump: std::rc::Rc<Bump>) -> Arena<T> {
+        Arena::with_capacity_in(n, SharedBump(bump))
+    }
+}
+
+#[test]
+fn custom_allocator() {
+    use allocator_api2::alloc::Global;
+    use std::cell::Cell;
+
+    struct Counting<'a> {
+        allocated: &'a Cell<usize>
+    }
+
+    unsafe impl<'a> Allocator for Counting<'a> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.allocated.set(self.allocated.get() + layout.size());
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.allocated.set(self.allocated.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    // The allocator has to be 'static.
+    let allocated: &'static Cell<usize> = Box::leak(Box::new(Cell::new(0)));
+
+    let arena = Arena::with_capacity_in(2, Counting { allocated });
+    assert_eq!(allocated.get(), 2 * 8);
+
+    let handles: Vec<_> = (0..6u64).map(|i| arena.alloc(i)).collect();
+    assert_eq!(allocated.get(), (2 + 4) * 8);
+    assert_eq!(*handles[5], 5);
+
+    drop(handles);
+    drop(arena);
+    assert_eq!(allocated.get(), 0);
+}
+
+#[cfg(feature = "bumpalo")]
+#[test]
+fn bump_allocator() {
+    let bump = std::rc::Rc::new(Bump::new());
+    let arena = Arena::with_capacity_in_bump(4, bump.clone());
+    let before = bump.allocated_bytes();
+
+    let handles: Vec<_> = (0..100u64).map(|i| arena.alloc(i)).collect();
+    assert!(bump.allocated_bytes() > before);
+    assert_eq!(*handles[99], 99);
+
+    drop(arena);
+    drop(handles);
+    assert_eq!(std::rc::Rc::strong_count(&bump), 1);
+}
@@ -0,0 +1,96 @@
+//! Support for generating arenas in property tests with `proptest`.
+
+use std::fmt::Debug;
+
+use proptest::arbitrary::{any, any_with, Arbitrary};
+use proptest::collection::{vec, SizeRange, VecStrategy};
+use proptest::sample::Index;
+use proptest::strategy::{Map, Strategy};
+
+use {Arena, Rc, Weak};
+
+impl<T: Arbitrary> Arbitrary for Arena<T> {
+    type Parameters = (SizeRange, T::Parameters);
+    type Strategy = Map<VecStrategy<T::Strategy>, fn(Vec<T>) -> Arena<T>>;
+
+    /// Generate an arena of arbitrary objects, in a single chunk.
+    fn arbitrary_with((size, parameters): Self::Parameters) -> Self::Strategy {
+        vec(any_with::<T>(parameters), size).prop_map(Arena::from_vec as fn(Vec<T>) -> Arena<T>)
+    }
+}
+
+/// An object in an arena generated by `graph`, along with its edges to other
+/// objects in the same arena.
+#[derive(Debug)]
+pub struct Node<T> {
+    pub value: T,
+    /// Edges only ever point at objects allocated before this one. They are
+    /// weak, so that the arena can still be dropped.
+    pub edges: Vec<Weak<Node<T>>>
+}
+
+/// A strategy generating arenas of `size` nodes, each holding a value from
+/// `values` and up to `max_edges` edges to earlier nodes.
+///
+/// ```rust
+/// # extern crate proptest;
+/// # extern crate rc_arena;
+/// use proptest::prelude::*;
+/// use proptest::test_runner::TestRunner;
+/// use rc_arena::arbitrary::graph;
+///
+/// # fn main() {
+/// TestRunner::default().run(&graph(any::<u32>(), 1..50, 4), |arena| {
+///     for node in arena.iter() {
+///         for edge in &node.edges {
+///             prop_assert!(edge.upgrade().is_some());
+///         }
+///     }
+///     Ok(())
+/// }).unwrap();
+/// # }
+/// ```
+pub fn graph<S>(values: S, size: impl Into<SizeRange>, max_edges: usize) -> impl Strategy<Value = Arena<Node<S::Value>>>
+    where S: Strategy, S::Value: Debug
+{
+    vec((values, vec(any::<Index>(), 0..=max_edges)), size).prop_map(|nodes| {
+        let arena = Arena::with_capacity(nodes.len());
+        let mut handles: Vec<Rc<Node<S::Value>>> = Vec::with_capacity(nodes.len());
+
+        for (value, edges) in nodes {
+            let edges = if handles.is_empty() {
+                vec![]
+            } else {
+                edges.iter().map(|index| Rc::downgrade(index.get(&handles))).collect()
+            };
+
+            handles.push(arena.alloc(Node { value, edges }));
+        }
+
+        arena
+    })
+}
+
+#[test]
+fn generates() {
+    use proptest::test_runner::TestRunner;
+
+    let mut runner = TestRunner::default();
+
+    runner.run(&any::<Arena<u8>>(), |arena| {
+        assert_eq!(arena.iter().count(), arena.len());
+        Ok(())
+    }).unwrap();
+
+    runner.run(&graph(any::<u8>(), 0..20, 3), |arena| {
+        for node in arena.iter() {
+            assert!(node.edges.len() <= 3);
+
+            for edge in &node.edges {
+                let target = edge.upgrade().unwrap();
+                assert!(arena.index_of(&target) < arena.index_of(&node));
+            }
+        }
+        Ok(())
+    }).unwrap();
+}
@@ -0,0 +1,71 @@
+//! Support for archiving arenas with `rkyv`. An arena is archived as an
+//! `ArchivedVec` of its objects in allocation order, so archived arenas can
+//! be validated and read in place with `rkyv::access`.
+
+use rkyv::rancor::Fallible;
+use rkyv::ser::{Allocator, Writer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use rkyv::{Archive, Deserialize, Place, Serialize};
+
+use Arena;
+
+impl<T: Archive> Archive for Arena<T> {
+    type Archived = ArchivedVec<T::Archived>;
+    type Resolver = VecResolver;
+
+    fn resolve(&self, resolver: VecResolver, out: Place<Self::Archived>) {
+        ArchivedVec::resolve_from_len(self.len(), resolver, out);
+    }
+}
+
+impl<T, S> Serialize<S> for Arena<T>
+    where T: Serialize<S>,
+          S: Fallible + Allocator + Writer + ?Sized
+{
+    fn serialize(&self, serializer: &mut S) -> Result<VecResolver, S::Error> {
+        let chunks = self.chunks.borrow();
+        let items: Vec<&T> = chunks.list.iter().flat_map(|chunk| chunk.items.iter()).collect();
+
+        ArchivedVec::<T::Archived>::serialize_from_iter::<T, _, _>(items.iter().cloned(), serializer)
+    }
+}
+
+impl<T, D> Deserialize<Arena<T>, D> for ArchivedVec<T::Archived>
+    where T: Archive,
+          ArchivedVec<T::Archived>: Deserialize<Vec<T>, D>,
+          D: Fallible + ?Sized
+{
+    /// The objects are deserialized into a single chunk.
+    fn deserialize(&self, deserializer: &mut D) -> Result<Arena<T>, D::Error> {
+        let items: Vec<T> = self.deserialize(deserializer)?;
+
+        Ok(Arena::from_vec(items))
+    }
+}
+
+#[test]
+fn archives() {
+    use rkyv::rancor::Error;
+    use rkyv::Archived;
+
+    let arena = Arena::with_capacity(2);
+    for i in 0..10u32 {
+        arena.alloc((i, format!("{}", i)));
+    }
+
+    let bytes = rkyv::to_bytes::<Error>(&arena).unwrap();
+    let archived = rkyv::access::<ArchivedVec<Archived<(u32, String)>>, Error>(&bytes).unwrap();
+    assert_eq!(archived.len(), 10);
+    assert_eq!(archived[3].0, 3);
+    assert_eq!(archived[3].1, "3");
+
+    let loaded: Arena<(u32, String)> = rkyv::deserialize::<_, Error>(archived).unwrap();
+    let mut expected = 0;
+    loaded.each(|item| {
+        assert_eq!(**item, (expected, format!("{}", expected)));
+        expected += 1;
+    });
+    assert_eq!(expected, 10);
+
+    assert!(rkyv::access::<ArchivedVec<Archived<(u32, String)>>, Error>(&bytes[1..]).is_err());
+}
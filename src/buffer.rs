@@ -0,0 +1,221 @@
+//! Arenas whose first chunk lives in memory provided by the caller, such as
+//! part of a slab shared with other subsystems, rather than on the heap.
+
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+use std::{ptr, slice};
+
+use {Arena, Chunk, Chunks, Items, Storage};
+
+/// The objects of a chunk which lives in a buffer owned by someone else. The
+/// objects are dropped along with the chunk, but the buffer is only freed if
+/// it came from a `Storage`, which gets it back.
+pub struct BufferItems<T> {
+    ptr: *mut T,
+    len: usize,
+    capacity: usize,
+    storage: Option<(std::rc::Rc<dyn Storage<T>>, usize)>
+}
+
+impl<T> BufferItems<T> {
+    /// Take the memory for `capacity` objects from `storage`.
+    pub fn allocate(storage: &std::rc::Rc<dyn Storage<T>>, capacity: usize) -> Option<BufferItems<T>> {
+        let ptr = storage.allocate(capacity)?;
+
+        Some(BufferItems {
+            ptr: ptr.as_ptr(),
+            len: 0,
+            capacity: if std::mem::size_of::<T>() == 0 { usize::MAX } else { capacity },
+            storage: Some((storage.clone(), capacity))
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The start of the buffer, including the part past the objects.
+    #[cfg(feature = "poison")]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+
+    pub fn push(&mut self, value: T) {
+        assert!(self.len < self.capacity);
+
+        unsafe { ptr::write(self.ptr.add(self.len), value) };
+        self.len += 1;
+    }
+
+    /// Set the number of objects, all of which must have been written.
+    pub unsafe fn set_len(&mut self, len: usize) {
+        self.len = len;
+    }
+
+    /// Move the objects from `at` onwards out of the buffer.
+    pub fn split_off(&mut self, at: usize) -> Vec<T> {
+        let removed = (at..self.len).map(|index| unsafe { ptr::read(self.ptr.add(index)) }).collect();
+        self.len = std::cmp::min(at, self.len);
+
+        removed
+    }
+
+    /// Drop the objects from `len` onwards in place.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let tail = ptr::slice_from_raw_parts_mut(unsafe { self.ptr.add(len) }, self.len - len);
+            self.len = len;
+            unsafe { ptr::drop_in_place(tail) };
+        }
+    }
+}
+
+impl<T> Deref for BufferItems<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for BufferItems<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for BufferItems<T> {
+    fn drop(&mut self) {
+        // Gives the buffer back even if one of the objects panics while it's
+        // being dropped, like a `Vec` frees its memory. The rest of the
+        // objects are still dropped by `drop_in_place`.
+        struct Deallocate<'a, T: 'a>(&'a BufferItems<T>);
+
+        impl<'a, T> Drop for Deallocate<'a, T> {
+            fn drop(&mut self) {
+                if let Some((ref storage, capacity)) = self.0.storage {
+                    unsafe { storage.deallocate(ptr::NonNull::new_unchecked(self.0.ptr), capacity) };
+                }
+            }
+        }
+
+        let objects = &mut **self as *mut [T];
+        let _deallocate = Deallocate(self);
+        unsafe { ptr::drop_in_place(objects) };
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a new arena whose first chunk is `buffer`, so that nothing is
+    /// allocated on the heap for objects until it's full. Later chunks are
+    /// allocated as usual.
+    ///
+    /// ```rust
+    /// use std::mem::MaybeUninit;
+    /// use rc_arena::Arena;
+    ///
+    /// let slab: &'static mut [MaybeUninit<u64>] = Box::leak(Box::new([MaybeUninit::uninit(); 4]));
+    /// let start = slab.as_ptr() as *const u64;
+    ///
+    /// let arena = Arena::from_buffer(slab);
+    /// let first = arena.alloc(1);
+    /// assert_eq!(&*first as *const u64, start);
+    /// ```
+    pub fn from_buffer(buffer: &'static mut [MaybeUninit<T>]) -> Arena<T> {
+        unsafe { Arena::from_raw_buffer(buffer.as_mut_ptr(), buffer.len()) }
+    }
+
+    /// Create a new arena whose first chunk is the `capacity` objects at
+    /// `ptr`, like `from_buffer`, without requiring that the memory lives
+    /// forever.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for writes, properly aligned, and must not
+    /// be used by anything else until the arena and all of its handles have
+    /// been dropped.
+    pub unsafe fn from_raw_buffer(ptr: *mut MaybeUninit<T>, capacity: usize) -> Arena<T> {
+        let items = BufferItems {
+            ptr: ptr as *mut T,
+            len: 0,
+            // Zero-sized objects don't need room.
+            capacity: if std::mem::size_of::<T>() == 0 { usize::MAX } else { capacity },
+            storage: None
+        };
+        let capacity = std::cmp::max(capacity, 1);
+        let mut chunks = Chunks::new(vec![]);
+        chunks.list.push(Chunk::new(Items::Buffer(items), capacity));
+
+        Arena::from_chunks(chunks)
+    }
+}
+
+#[test]
+fn lives_in_buffer() {
+    let mut buffer: Vec<MaybeUninit<std::rc::Rc<usize>>> = (0..3).map(|_| MaybeUninit::uninit()).collect();
+    let start = buffer.as_ptr() as usize;
+    let token = std::rc::Rc::new(0);
+
+    let arena = unsafe { Arena::from_raw_buffer(buffer.as_mut_ptr(), buffer.len()) };
+    let objects: Vec<_> = (0..5).map(|_| arena.alloc(token.clone())).collect();
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [3, 2]);
+    assert_eq!(&*objects[2] as *const _ as usize, start + 2 * std::mem::size_of::<std::rc::Rc<usize>>());
+
+    // Truncating drops the objects in the buffer, and makes room there again.
+    drop(objects);
+    assert!(arena.truncate(1).is_ok());
+    assert_eq!(std::rc::Rc::strong_count(&token), 2);
+    let again = arena.alloc(token.clone());
+    assert_eq!(&*again as *const _ as usize, start + std::mem::size_of::<std::rc::Rc<usize>>());
+    drop((arena, again));
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+
+    // Plain data is forgotten rather than moved out.
+    let mut buffer: Vec<MaybeUninit<u64>> = (0..4).map(|_| MaybeUninit::uninit()).collect();
+    let arena = unsafe { Arena::from_raw_buffer(buffer.as_mut_ptr(), buffer.len()) };
+    for i in 0..4 {
+        arena.alloc(i);
+    }
+    arena.truncate(2).unwrap();
+    arena.alloc(5);
+    assert_eq!(arena.iter().map(|i| *i).collect::<Vec<_>>(), [0, 1, 5]);
+}
+
+#[test]
+fn survives_panicking_destructors() {
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use storage::Counting;
+
+    struct Bomb(std::rc::Rc<Cell<usize>>, bool);
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+            if self.1 {
+                panic!("bomb");
+            }
+        }
+    }
+
+    let chunks = std::rc::Rc::new(Cell::new(0));
+    let dropped = std::rc::Rc::new(Cell::new(0));
+    let arena = Arena::with_storage(4, Counting(chunks.clone()));
+    for i in 0..10 {
+        arena.alloc(Bomb(dropped.clone(), i == 1));
+    }
+    assert_eq!(chunks.get(), 2);
+
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(arena))).is_err());
+    assert_eq!((chunks.get(), dropped.get()), (0, 10));
+
+    // Chunks on the heap keep going too.
+    let arena = Arena::with_capacity(4);
+    let last = arena.alloc(Bomb(dropped.clone(), false));
+    for i in 0..10 {
+        arena.alloc(Bomb(dropped.clone(), i == 6));
+    }
+    drop(arena);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(last))).is_err());
+    assert_eq!(dropped.get(), 21);
+}
@@ -0,0 +1,188 @@
+//! A builder for arenas which combine several options, rather than a
+//! constructor for every combination of them.
+
+#[cfg(feature = "allocator-api2")]
+use allocator_api2::alloc::Allocator;
+
+#[cfg(feature = "allocator-api2")]
+use allocator::ChunkAllocator;
+use {limit, pool, table, Arena, ArenaError, Chunks, Hooks, Limit, Storage};
+
+/// Options for a new arena, which `build` checks can be combined before
+/// creating it. Each option does the same as the constructor it's named
+/// after, like `Arena::with_limit` or `Arena::pool`.
+///
+/// ```rust
+/// use rc_arena::{ArenaBuilder, Hooks, Limit};
+///
+/// let arena = ArenaBuilder::new()
+///     .capacity(16)
+///     .limit(Limit::Objects(100))
+///     .pool(|buffer: &mut Vec<u8>| buffer.clear())
+///     .hooks(Hooks::new().on_new_chunk(|capacity| println!("new chunk of {}", capacity)))
+///     .build()
+///     .unwrap();
+///
+/// drop(arena.alloc(vec![1, 2, 3]));
+/// assert!(arena.recycle().unwrap().is_empty());
+/// ```
+pub struct ArenaBuilder<T> {
+    capacity: usize,
+    limit: Option<Limit>,
+    hooks: Option<Hooks<T>>,
+    reset: Option<pool::Reset<T>>,
+    indirect: bool,
+    storage: Option<std::rc::Rc<dyn Storage<T>>>,
+    reverse_drop: bool,
+    #[cfg(feature = "allocator-api2")]
+    allocator: Option<ChunkAllocator>
+}
+
+impl<T> ArenaBuilder<T> {
+    /// Start with the options of `Arena::new`.
+    pub fn new() -> ArenaBuilder<T> {
+        ArenaBuilder {
+            capacity: 8,
+            limit: None,
+            hooks: None,
+            reset: None,
+            indirect: false,
+            storage: None,
+            reverse_drop: false,
+            #[cfg(feature = "allocator-api2")]
+            allocator: None
+        }
+    }
+
+    /// Set the capacity of the first chunk. With a limit, it's cut down to
+    /// what the limit allows.
+    pub fn capacity(mut self, n: usize) -> ArenaBuilder<T> {
+        self.capacity = n;
+        self
+    }
+
+    /// Keep the arena from growing past `limit`, like `Arena::with_limit`.
+    pub fn limit(mut self, limit: Limit) -> ArenaBuilder<T> {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Install `hooks`, like `Arena::set_hooks`.
+    pub fn hooks(mut self, hooks: Hooks<T>) -> ArenaBuilder<T> {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Make the arena an object pool which passes objects to `reset` before
+    /// reusing them, like `Arena::pool`.
+    pub fn pool<F: Fn(&mut T) + 'static>(mut self, reset: F) -> ArenaBuilder<T> {
+        self.reset = Some(std::rc::Rc::new(reset));
+        self
+    }
+
+    /// Make the arena indirect, like `Arena::indirect`.
+    pub fn indirect(mut self) -> ArenaBuilder<T> {
+        self.indirect = true;
+        self
+    }
+
+    /// Get the memory for chunks from `storage`, like `Arena::with_storage`.
+    pub fn storage<S: Storage<T> + 'static>(mut self, storage: S) -> ArenaBuilder<T> {
+        self.storage = Some(std::rc::Rc::new(storage));
+        self
+    }
+
+    /// Drop the objects in the reverse of the order they were allocated in,
+    /// like `Arena::set_reverse_drop`.
+    pub fn reverse_drop(mut self) -> ArenaBuilder<T> {
+        self.reverse_drop = true;
+        self
+    }
+
+    /// Allocate chunks with `allocator`, like `Arena::with_capacity_in`.
+    #[cfg(feature = "allocator-api2")]
+    pub fn allocator<A: Allocator + 'static>(mut self, allocator: A) -> ArenaBuilder<T> {
+        self.allocator = Some(ChunkAllocator::new(allocator));
+        self
+    }
+
+    /// Create the arena. This fails with `InvalidConfiguration` if the arena
+    /// would be both a pool and indirect, or if it's given both storage and
+    /// an allocator, and otherwise fails if its first chunk can't be
+    /// allocated.
+    pub fn build(self) -> Result<Arena<T>, ArenaError> {
+        if self.reset.is_some() && self.indirect {
+            return Err(ArenaError::InvalidConfiguration);
+        }
+        #[cfg(feature = "allocator-api2")]
+        {
+            if self.storage.is_some() && self.allocator.is_some() {
+                return Err(ArenaError::InvalidConfiguration);
+            }
+        }
+
+        let mut chunks = Chunks::new(vec![]);
+        chunks.pool = self.reset.map(pool::Pool::new);
+        if self.indirect {
+            chunks.table = Some(table::Table::new());
+        }
+        chunks.hooks = self.hooks.map(std::rc::Rc::new);
+        chunks.reverse_drop = self.reverse_drop;
+        #[cfg(feature = "allocator-api2")]
+        {
+            chunks.allocator = self.allocator;
+        }
+
+        let mut capacity = self.capacity;
+        if self.storage.is_some() {
+            capacity = std::cmp::max(capacity, 1);
+        }
+        chunks.storage = self.storage;
+        if let Some(limit) = self.limit {
+            capacity = limit::chunk_capacity(limit, &chunks, capacity, 0)?;
+            chunks.limit = Some(limit);
+        }
+
+        let first = chunks.try_new_chunk(capacity)?;
+        chunks.list.push(first);
+
+        Ok(Arena::from_chunks(chunks))
+    }
+}
+
+impl<T> Default for ArenaBuilder<T> {
+    fn default() -> ArenaBuilder<T> {
+        ArenaBuilder::new()
+    }
+}
+
+#[test]
+fn builds() {
+    use std::cell::Cell;
+
+    let chunks = std::rc::Rc::new(Cell::new(0));
+    let counter = chunks.clone();
+    let arena = ArenaBuilder::new()
+        .capacity(64)
+        .limit(Limit::Objects(4))
+        .indirect()
+        .hooks(Hooks::new().on_new_chunk(move |_| counter.set(counter.get() + 1)))
+        .build()
+        .unwrap();
+
+    let handles: Vec<_> = (0..4).map(|i| arena.alloc(i)).collect();
+    assert_eq!(arena.try_alloc(4).unwrap_err(), ArenaError::LimitExceeded);
+    assert_eq!((arena.chunks().count(), arena.chunks().next().unwrap().len()), (1, 4));
+    assert_eq!(chunks.get(), 0);
+
+    let kept = handles[0].clone();
+    drop(handles);
+    unsafe { arena.compact() };
+    assert_eq!((arena.len(), *kept), (1, 0));
+
+    let arena = ArenaBuilder::new().storage(::PageStorage).build().unwrap();
+    assert_eq!(&*arena.alloc(1u32) as *const u32 as usize % 4096, 0);
+
+    let conflicting = ArenaBuilder::new().indirect().pool(|_: &mut u8| {}).build();
+    assert_eq!(conflicting.err(), Some(ArenaError::InvalidConfiguration));
+}
@@ -0,0 +1,181 @@
+//! A bounded cache whose values live in an object pool, evicting the least
+//! recently used entries which aren't in use anymore.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use {Arena, Rc};
+
+/// A cache of up to `capacity` values looked up by key, which are stored in
+/// an arena and handed out as `Rc`s.
+///
+/// When the cache is full, inserting evicts the least recently used entry
+/// which nothing outside of the cache holds an `Rc` to, and its place in the
+/// arena is reused by a later insertion. Entries which are still in use are
+/// never evicted, so inserting fails if all of them are.
+///
+/// ```rust
+/// use rc_arena::ArenaCache;
+///
+/// let mut images = ArenaCache::new(2);
+/// let logo = images.insert("logo.png", vec![0u8; 16]).unwrap();
+/// images.insert("banner.png", vec![1; 64]).unwrap();
+///
+/// // The logo is still in use, so the banner is evicted instead.
+/// images.insert("icon.png", vec![2; 8]).unwrap();
+/// assert!(images.get("banner.png").is_none());
+/// assert_eq!(images.get("logo.png").unwrap().len(), logo.len());
+/// ```
+pub struct ArenaCache<K, T> {
+    arena: Arena<T>,
+    capacity: usize,
+    // Each entry's value, along with when it was last used.
+    entries: HashMap<K, (Rc<T>, u64)>,
+    // The key of each entry by when it was last used, oldest first.
+    recency: BTreeMap<u64, K>,
+    tick: u64
+}
+
+impl<K: Hash + Eq + Clone, T> ArenaCache<K, T> {
+    /// Create an empty cache which holds up to `capacity` entries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> ArenaCache<K, T> {
+        assert!(capacity != 0, "a cache needs room for at least one entry");
+
+        ArenaCache {
+            arena: Arena::pool_with_capacity(capacity, |_: &mut T| {}),
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            recency: BTreeMap::new(),
+            tick: 0
+        }
+    }
+
+    /// Get the value for `key`, marking it as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<Rc<T>>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        let tick = self.tick;
+        let (rc, used) = self.entries.get_mut(key)?;
+        let key = self.recency.remove(used).unwrap();
+        *used = tick;
+        self.recency.insert(tick, key);
+        self.tick += 1;
+
+        Some(rc.clone())
+    }
+
+    /// Returns `true` if there is a value for `key`, without marking it as
+    /// used.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Store a value for `key`, replacing any value it had, and evicting an
+    /// entry if the cache is full. This fails, giving the value back, if the
+    /// cache is full and every entry is in use.
+    pub fn insert(&mut self, key: K, value: T) -> Result<Rc<T>, T> {
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity && !self.evict() {
+            return Err(value);
+        }
+
+        // Drop any old value first, so that its place can be reused.
+        self.remove(&key);
+        let rc = self.arena.alloc(value);
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (rc.clone(), self.tick));
+        self.tick += 1;
+
+        Ok(rc)
+    }
+
+    /// Get the value for `key` like `get`, or store the value returned by `f`
+    /// like `insert` if there isn't one.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, f: F) -> Result<Rc<T>, T> {
+        match self.get(&key) {
+            Some(rc) => Ok(rc),
+            None => self.insert(key, f())
+        }
+    }
+
+    /// Remove the entry for `key`, returning its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Rc<T>>
+        where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+    {
+        let (rc, used) = self.entries.remove(key)?;
+        self.recency.remove(&used);
+
+        Some(rc)
+    }
+
+    /// Remove the least recently used entry which isn't in use, returning
+    /// `false` if there isn't one.
+    fn evict(&mut self) -> bool {
+        let entries = &self.entries;
+        let unused = self.recency.values().find(|&key| Rc::strong_count(&entries[key].0) == 1).cloned();
+
+        match unused {
+            Some(key) => {
+                self.remove(&key);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Get the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the number of entries the cache holds before evicting any.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The arena the values live in, which includes the values of evicted
+    /// entries until their places are reused.
+    pub fn arena(&self) -> &Arena<T> {
+        &self.arena
+    }
+}
+
+#[test]
+fn evicts() {
+    let mut cache = ArenaCache::new(3);
+    for i in 0..3 {
+        cache.insert(i, i * 10).unwrap();
+    }
+    let held = cache.get(&0).unwrap();
+    assert_eq!(*cache.get_or_insert_with(1, || unreachable!()).unwrap(), 10);
+
+    // 2 is the least recently used, then 0, which is held, then 1.
+    cache.insert(3, 30).unwrap();
+    assert!(!cache.contains_key(&2));
+    cache.insert(4, 40).unwrap();
+    assert_eq!((cache.contains_key(&0), cache.contains_key(&1)), (true, false));
+    assert_eq!(cache.arena().len(), 3);
+
+    let others = (cache.get(&3).unwrap(), cache.get(&4).unwrap());
+    assert_eq!(cache.insert(5, 50).unwrap_err(), 50);
+    drop(held);
+    assert_eq!(*cache.insert(5, 50).unwrap(), 50);
+    assert!(!cache.contains_key(&0));
+    assert_eq!((cache.len(), cache.arena().len()), (3, 3));
+
+    // Replacing a value which is in use takes a new place.
+    cache.insert(3, 31).unwrap();
+    assert_eq!((*others.0, *cache.get(&3).unwrap()), (30, 31));
+    assert_eq!(cache.arena().len(), 4);
+}
@@ -0,0 +1,129 @@
+//! Shorthands for arenas of objects with interior mutability, which is the
+//! only way to change an object that other handles may be looking at.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+
+use {Arena, Rc};
+
+/// An arena of `Cell`s, for small `Copy` values such as counters and flags
+/// which are updated in place through any of their handles.
+///
+/// ```rust
+/// use rc_arena::ArenaCell;
+///
+/// let counters = ArenaCell::new();
+/// let hits = counters.alloc_cell(0u32);
+/// let also_hits = hits.clone();
+///
+/// hits.set(hits.get() + 1);
+/// also_hits.set(also_hits.get() + 1);
+/// assert_eq!(hits.get(), 2);
+/// ```
+pub type ArenaCell<T> = Arena<Cell<T>>;
+
+impl<T: Copy> Arena<Cell<T>> {
+    /// Store a value in a new `Cell` in the arena.
+    pub fn alloc_cell(&self, value: T) -> Rc<Cell<T>> {
+        self.alloc(Cell::new(value))
+    }
+
+    /// Copy out the value of every cell, in the order they were allocated.
+    pub fn values(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        self.each(|cell| values.push(cell.get()));
+
+        values
+    }
+}
+
+/// An arena of `RefCell`s, whose handles can borrow their object mutably
+/// like an `Rc<RefCell<T>>`, with the borrow flag stored next to the object
+/// rather than in an allocation of its own.
+///
+/// ```rust
+/// use rc_arena::MutArena;
+///
+/// let buffers = MutArena::new();
+/// let buffer = buffers.alloc_mut(String::new());
+/// let writer = buffer.clone();
+///
+/// let reading = buffer.borrow();
+/// assert!(writer.try_borrow_mut().is_err());
+/// drop(reading);
+/// writer.borrow_mut().push_str("done");
+/// assert_eq!(*buffer.borrow(), "done");
+/// ```
+pub type MutArena<T> = Arena<RefCell<T>>;
+
+/// A handle to an object in a `RefCell`, which is how objects which aren't
+/// `Copy` are changed in place.
+///
+/// ```rust
+/// use rc_arena::Arena;
+///
+/// let arena = Arena::new();
+/// let list = arena.alloc_mut(vec![1]);
+/// list.borrow_mut().push(2);
+///
+/// assert_eq!(*list.borrow(), [1, 2]);
+/// ```
+pub type MutRc<T> = Rc<RefCell<T>>;
+
+impl<T> Arena<RefCell<T>> {
+    /// Store a value in a new `RefCell` in the arena.
+    pub fn alloc_mut(&self, value: T) -> MutRc<T> {
+        self.alloc(RefCell::new(value))
+    }
+}
+
+impl<T> Rc<RefCell<T>> {
+    /// Immutably borrow the object, like `RefCell::borrow`. Unlike going
+    /// through `Deref`, this isn't shadowed by `std::borrow::Borrow` when it's
+    /// in scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object is mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        RefCell::borrow(self)
+    }
+
+    /// Mutably borrow the object, like `RefCell::borrow_mut`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object is borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        RefCell::borrow_mut(self)
+    }
+}
+
+#[test]
+fn cells() {
+    let flags: ArenaCell<bool> = Arena::with_capacity(1);
+    let handles: Vec<_> = (0..4).map(|i| flags.alloc_cell(i % 2 == 0)).collect();
+    handles[1].set(true);
+    Rc::arena(&handles[3]).first().unwrap().set(false);
+
+    assert_eq!(flags.values(), [false, true, true, false]);
+}
+
+#[test]
+fn ref_cells() {
+    #[allow(unused_imports)]
+    use std::borrow::Borrow;
+
+    let arena = MutArena::new();
+    let name = arena.alloc_mut(String::from("rc"));
+    name.borrow_mut().push_str("_arena");
+    assert_eq!(name.borrow().len(), 8);
+    assert!(name.try_borrow_mut().is_ok());
+
+    // The flag is stored beside the object, which is still borrowed through
+    // any of its handles.
+    let other = name.clone();
+    let borrowed = name.borrow();
+    assert!(other.try_borrow_mut().is_err());
+    drop(borrowed);
+    assert!(other.try_borrow_mut().is_ok());
+}
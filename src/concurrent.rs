@@ -0,0 +1,662 @@
+//! Arenas which many threads can allocate in at once, for building a large
+//! set of objects in parallel before working with them, or for a pool of
+//! objects which threads recycle.
+//!
+//! A recycling arena reuses the place of an object once its last handle is
+//! gone, but other threads may still be reading the object through `pin`.
+//! Places are reclaimed by epochs: each reader is counted in the epoch it
+//! was pinned in, the epoch only moves on once nobody is pinned in the one
+//! before it, and the object retired in an epoch is only dropped once the
+//! epoch has moved on twice, by which time every reader which could have
+//! seen it is gone.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A chunk of a `ConcurrentArena`, which never grows, so that its objects
+/// never move.
+struct Chunk<T> {
+    items: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Whether each object has been written, and hasn't been retired.
+    ready: Box<[AtomicBool]>,
+    // The number of handles to each object, in a recycling arena.
+    counts: Box<[AtomicUsize]>,
+    // How many positions have been claimed, which goes past the capacity
+    // once the chunk is full.
+    claimed: AtomicUsize,
+    // The chunk before this one, or null for the first.
+    prev: *mut Chunk<T>
+}
+
+impl<T> Chunk<T> {
+    fn new(capacity: usize, prev: *mut Chunk<T>, counted: bool) -> Box<Chunk<T>> {
+        Box::new(Chunk {
+            items: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            ready: (0..capacity).map(|_| AtomicBool::new(false)).collect(),
+            counts: (0..if counted { capacity } else { 0 }).map(|_| AtomicUsize::new(0)).collect(),
+            claimed: AtomicUsize::new(0),
+            prev
+        })
+    }
+
+    /// Write an object in a position claimed for it.
+    fn write(&self, index: usize, value: T) {
+        // Nothing reads the position until it's marked as ready.
+        unsafe { ptr::write(self.items[index].get() as *mut T, value) };
+        if let Some(count) = self.counts.get(index) {
+            count.store(1, Ordering::Relaxed);
+        }
+        self.ready[index].store(true, Ordering::Release);
+    }
+
+    /// The objects which are ready, in order.
+    fn objects(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().zip(self.ready.iter()).filter_map(|(item, ready)| {
+            if ready.load(Ordering::Acquire) {
+                // Objects are never written again while they're ready, or
+                // while a reader who saw them ready is pinned.
+                Some(unsafe { &*(item.get() as *const T) })
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl<T> Drop for Chunk<T> {
+    fn drop(&mut self) {
+        // Goes on dropping the rest of the objects if one of them panics
+        // while it's being dropped, like a `Vec` does.
+        struct Rest<'a, T: 'a>(&'a mut Chunk<T>, usize);
+
+        impl<'a, T> Rest<'a, T> {
+            fn drop_objects(&mut self) {
+                while self.1 < self.0.items.len() {
+                    let index = self.1;
+                    self.1 += 1;
+                    if *self.0.ready[index].get_mut() {
+                        unsafe { ptr::drop_in_place(self.0.items[index].get_mut().as_mut_ptr()) };
+                    }
+                }
+            }
+        }
+
+        impl<'a, T> Drop for Rest<'a, T> {
+            fn drop(&mut self) {
+                self.drop_objects();
+            }
+        }
+
+        Rest(self, 0).drop_objects();
+    }
+}
+
+/// The place of an object, as its chunk and its position in it.
+type Place<T> = (*const Chunk<T>, usize);
+
+/// Keeps track of the places whose objects are no longer referenced in a
+/// recycling arena.
+struct Recycler<T> {
+    epoch: AtomicUsize,
+    // The number of readers pinned in each of the last three epochs.
+    pinned: [AtomicUsize; 3],
+    // Places whose objects have been retired, with the epoch they were
+    // retired in. Readers pinned before then may still see the objects.
+    retired: Mutex<Vec<(Place<T>, usize)>>,
+    // Places whose objects have been dropped, ready to be reused.
+    free: Mutex<Vec<Place<T>>>
+}
+
+impl<T> Recycler<T> {
+    fn new() -> Recycler<T> {
+        Recycler {
+            epoch: AtomicUsize::new(0),
+            pinned: [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)],
+            retired: Mutex::new(vec![]),
+            free: Mutex::new(vec![])
+        }
+    }
+
+    /// Count a reader as pinned, returning its epoch.
+    fn pin(&self) -> usize {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            self.pinned[epoch % 3].fetch_add(1, Ordering::SeqCst);
+
+            // If the epoch moved on in the meantime, an advance may not have
+            // seen this reader.
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                return epoch;
+            }
+            self.pinned[epoch % 3].fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn unpin(&self, epoch: usize) {
+        self.pinned[epoch % 3].fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Move on to the next epoch, unless a reader is still pinned in the
+    /// one before the current one.
+    fn try_advance(&self) {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        if self.pinned[(epoch + 2) % 3].load(Ordering::SeqCst) == 0 {
+            let _ = self.epoch.compare_exchange(epoch, epoch + 1, Ordering::SeqCst, Ordering::SeqCst);
+        }
+    }
+
+    /// Hide the object at `place` from new readers, and drop it once the
+    /// readers who may have seen it are gone.
+    fn retire(&self, place: Place<T>) {
+        let (chunk, index) = place;
+        unsafe { (*chunk).ready[index].store(false, Ordering::SeqCst) };
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        self.retired.lock().unwrap_or_else(PoisonError::into_inner).push((place, epoch));
+
+        self.collect();
+    }
+
+    /// Drop the retired objects which no reader can see anymore, and make
+    /// their places free.
+    fn collect(&self) {
+        self.try_advance();
+        self.try_advance();
+        let epoch = self.epoch.load(Ordering::SeqCst);
+
+        let reclaimed: Vec<Place<T>> = match self.retired.try_lock() {
+            Ok(mut retired) => {
+                let (reclaimed, kept) = retired.drain(..).partition(|&(_, retired)| retired + 2 <= epoch);
+                *retired = kept;
+                reclaimed.into_iter().map(|(place, _)| place).collect()
+            }
+            Err(_) => return
+        };
+
+        // The objects are dropped outside of the lock, since dropping them
+        // may retire others.
+        for &(chunk, index) in &reclaimed {
+            unsafe { ptr::drop_in_place((*chunk).items[index].get() as *mut T) };
+        }
+        self.free.lock().unwrap_or_else(PoisonError::into_inner).extend(reclaimed);
+    }
+
+    /// Take a free place, unless there are none or another thread is busy
+    /// with them.
+    fn reuse(&self) -> Option<Place<T>> {
+        self.collect();
+
+        self.free.try_lock().ok().and_then(|mut free| free.pop())
+    }
+}
+
+struct Shared<T> {
+    // The chunk objects are being stored in, which leads back to the others.
+    current: AtomicPtr<Chunk<T>>,
+    recycler: Option<Recycler<T>>
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Goes on with the rest of the objects and chunks if an object
+        // panics while it's being dropped, as for `Chunk`.
+        struct Rest<'a, T: 'a>(&'a mut Shared<T>);
+
+        impl<'a, T> Rest<'a, T> {
+            fn drop_chunks(&mut self) {
+                // Retired objects aren't ready anymore, so the chunks don't
+                // drop them.
+                if let Some(ref mut recycler) = self.0.recycler {
+                    let retired = recycler.retired.get_mut().unwrap_or_else(PoisonError::into_inner);
+                    while let Some(((chunk, index), _)) = retired.pop() {
+                        unsafe { ptr::drop_in_place((*chunk).items[index].get() as *mut T) };
+                    }
+                }
+
+                // Dropping the chunks one at a time keeps a long chain of
+                // them from overflowing the stack.
+                let current = self.0.current.get_mut();
+                while !current.is_null() {
+                    let chunk = unsafe { Box::from_raw(*current) };
+                    *current = chunk.prev;
+                }
+            }
+        }
+
+        impl<'a, T> Drop for Rest<'a, T> {
+            fn drop(&mut self) {
+                self.drop_chunks();
+            }
+        }
+
+        Rest(self).drop_chunks();
+    }
+}
+
+/// An arena which can be shared between threads, all of which can allocate
+/// in it at the same time. Storing an object doesn't take a lock: it claims
+/// a position in the current chunk with a single atomic addition, and only
+/// the thread which finds the chunk full installs a new one, twice as
+/// large, which threads racing with it retry in.
+///
+/// Objects can't be changed once they're stored, and are dropped along
+/// with the arena once it and every `ConcurrentRc` are gone. `iter` visits
+/// the objects which have been stored so far, so once the threads which
+/// allocate have been joined it visits all of them.
+///
+/// An arena created with `recycling` drops each object once its last
+/// handle is gone instead, and reuses its place. Its objects can only be
+/// visited through `pin`, since they can be dropped at any time otherwise.
+///
+/// ```rust
+/// use rc_arena::ConcurrentArena;
+///
+/// let arena = ConcurrentArena::new();
+/// std::thread::scope(|scope| {
+///     for thread in 0..4 {
+///         let arena = &arena;
+///         scope.spawn(move || {
+///             for i in 0..100 {
+///                 arena.alloc(thread * 100 + i);
+///             }
+///         });
+///     }
+/// });
+///
+/// assert_eq!(arena.iter().count(), 400);
+/// assert_eq!(arena.iter().sum::<usize>(), 79800);
+/// ```
+pub struct ConcurrentArena<T> {
+    shared: Arc<Shared<T>>
+}
+
+// Objects are moved in from any thread, and shared with all of them.
+unsafe impl<T: Send + Sync> Send for ConcurrentArena<T> {}
+
+unsafe impl<T: Send + Sync> Sync for ConcurrentArena<T> {}
+
+impl<T> ConcurrentArena<T> {
+    /// Create a new arena with an unspecified capacity.
+    pub fn new() -> ConcurrentArena<T> {
+        ConcurrentArena::with_capacity(64)
+    }
+
+    /// Create a new arena with a known initial capacity.
+    pub fn with_capacity(n: usize) -> ConcurrentArena<T> {
+        ConcurrentArena::create(n, None)
+    }
+
+    /// Create a new recycling arena with an unspecified capacity. See
+    /// `recycling_with_capacity`.
+    pub fn recycling() -> ConcurrentArena<T> {
+        ConcurrentArena::recycling_with_capacity(64)
+    }
+
+    /// Create a new arena with a known initial capacity, which drops each
+    /// object once its last handle is gone and reuses its place. An object
+    /// may still be read by a thread which pinned the arena before then, so
+    /// it's only dropped once every such thread is done.
+    ///
+    /// ```rust
+    /// use rc_arena::ConcurrentArena;
+    ///
+    /// let arena = ConcurrentArena::recycling_with_capacity(4);
+    /// let first = arena.alloc(String::from("first"));
+    /// let ptr: *const String = &*first;
+    ///
+    /// let guard = arena.pin();
+    /// drop(first);
+    /// // A reader may still be looking at the object, so it's kept.
+    /// let second = arena.alloc(String::from("second"));
+    /// assert_ne!(&*second as *const String, ptr);
+    ///
+    /// drop(guard);
+    /// assert_eq!(&*arena.alloc(String::from("third")) as *const String, ptr);
+    /// ```
+    pub fn recycling_with_capacity(n: usize) -> ConcurrentArena<T> {
+        ConcurrentArena::create(n, Some(Recycler::new()))
+    }
+
+    fn create(n: usize, recycler: Option<Recycler<T>>) -> ConcurrentArena<T> {
+        let first = Chunk::new(std::cmp::max(n, 1), ptr::null_mut(), recycler.is_some());
+
+        ConcurrentArena {
+            shared: Arc::new(Shared {
+                current: AtomicPtr::new(Box::into_raw(first)),
+                recycler
+            })
+        }
+    }
+
+    /// Store an object in the arena, returning a handle to it which can be
+    /// sent to other threads.
+    pub fn alloc(&self, value: T) -> ConcurrentRc<T> {
+        if let Some(place) = self.shared.recycler.as_ref().and_then(Recycler::reuse) {
+            let (chunk, index) = place;
+            // The old object was dropped, and nobody can see its place.
+            unsafe { (*chunk).write(index, value) };
+
+            return self.handle(place);
+        }
+
+        let counted = self.shared.recycler.is_some();
+        let place = loop {
+            let current = self.shared.current.load(Ordering::Acquire);
+            // Chunks are only freed along with the arena.
+            let chunk = unsafe { &*current };
+
+            let index = chunk.claimed.fetch_add(1, Ordering::Relaxed);
+            if index < chunk.items.len() {
+                chunk.write(index, value);
+                break (current as *const Chunk<T>, index);
+            }
+
+            // The new chunk starts out with the object in it, so that it's
+            // stored by whichever thread installs it.
+            let next = Chunk::new(chunk.items.len() * 2, current, counted);
+            next.claimed.store(1, Ordering::Relaxed);
+            let next = Box::into_raw(next);
+            let installed = self.shared.current.compare_exchange(current, next, Ordering::AcqRel, Ordering::Acquire);
+
+            if installed.is_ok() {
+                unsafe { (*next).write(0, value) };
+                break (next as *const Chunk<T>, 0);
+            }
+
+            // Another thread got there first, so try again in its chunk.
+            drop(unsafe { Box::from_raw(next) });
+        };
+
+        self.handle(place)
+    }
+
+    fn handle(&self, (chunk, index): Place<T>) -> ConcurrentRc<T> {
+        ConcurrentRc {
+            shared: self.shared.clone(),
+            chunk,
+            index
+        }
+    }
+
+    /// The objects which are ready, in the order their chunks were created.
+    fn objects(&self) -> impl Iterator<Item = &T> {
+        let mut chunks = vec![];
+        let mut next = self.shared.current.load(Ordering::Acquire);
+        while !next.is_null() {
+            let chunk = unsafe { &*next };
+            chunks.push(chunk);
+            next = chunk.prev;
+        }
+
+        chunks.into_iter().rev().flat_map(Chunk::objects)
+    }
+
+    /// Iterate over the objects which have been stored, in the order their
+    /// chunks were created. Objects which are still being stored by another
+    /// thread are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is recycling, whose objects have to be visited
+    /// through `pin` instead.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        assert!(self.shared.recycler.is_none(), "the objects of a recycling arena can only be visited through `pin`");
+
+        self.objects()
+    }
+
+    /// Pin the arena, so that the objects it holds now aren't dropped until
+    /// the guard is, even if their last handle is. Pinning an arena which
+    /// isn't recycling does nothing.
+    pub fn pin(&self) -> Guard<'_, T> {
+        Guard {
+            arena: self,
+            epoch: self.shared.recycler.as_ref().map(Recycler::pin)
+        }
+    }
+}
+
+impl<T> Default for ConcurrentArena<T> {
+    fn default() -> ConcurrentArena<T> {
+        ConcurrentArena::new()
+    }
+}
+
+impl<T> Clone for ConcurrentArena<T> {
+    fn clone(&self) -> ConcurrentArena<T> {
+        ConcurrentArena {
+            shared: self.shared.clone()
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for ConcurrentArena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_list().entries(self.pin().iter()).finish()
+    }
+}
+
+/// A pinned `ConcurrentArena`, returned by `ConcurrentArena::pin`, through
+/// which its objects can be visited.
+pub struct Guard<'a, T: 'a> {
+    arena: &'a ConcurrentArena<T>,
+    epoch: Option<usize>
+}
+
+impl<'a, T> Guard<'a, T> {
+    /// Iterate over the objects which are stored, like
+    /// `ConcurrentArena::iter`. Objects retired while the guard is held may
+    /// or may not be visited.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.arena.objects()
+    }
+}
+
+impl<'a, T> Drop for Guard<'a, T> {
+    fn drop(&mut self) {
+        if let (Some(epoch), Some(recycler)) = (self.epoch, self.arena.shared.recycler.as_ref()) {
+            recycler.unpin(epoch);
+            recycler.collect();
+        }
+    }
+}
+
+/// A handle to an object of a `ConcurrentArena`, which keeps the arena
+/// alive, and which can be sent to and shared between threads.
+pub struct ConcurrentRc<T> {
+    shared: Arc<Shared<T>>,
+    chunk: *const Chunk<T>,
+    index: usize
+}
+
+unsafe impl<T: Send + Sync> Send for ConcurrentRc<T> {}
+
+unsafe impl<T: Send + Sync> Sync for ConcurrentRc<T> {}
+
+impl<T> ConcurrentRc<T> {
+    /// Returns `true` if both handles point at the same object.
+    pub fn ptr_eq(this: &ConcurrentRc<T>, other: &ConcurrentRc<T>) -> bool {
+        this.chunk == other.chunk && this.index == other.index
+    }
+
+    /// The arena the object lives in.
+    pub fn arena(this: &ConcurrentRc<T>) -> ConcurrentArena<T> {
+        ConcurrentArena {
+            shared: this.shared.clone()
+        }
+    }
+
+    fn chunk(&self) -> &Chunk<T> {
+        // Chunks are only freed along with the arena.
+        unsafe { &*self.chunk }
+    }
+
+    fn count(&self) -> Option<&AtomicUsize> {
+        self.chunk().counts.get(self.index)
+    }
+}
+
+impl<T> Clone for ConcurrentRc<T> {
+    fn clone(&self) -> ConcurrentRc<T> {
+        if let Some(count) = self.count() {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        ConcurrentRc {
+            shared: self.shared.clone(),
+            chunk: self.chunk,
+            index: self.index
+        }
+    }
+}
+
+impl<T> Drop for ConcurrentRc<T> {
+    fn drop(&mut self) {
+        if let Some(count) = self.count() {
+            if count.fetch_sub(1, Ordering::Release) == 1 {
+                fence(Ordering::Acquire);
+                self.shared.recycler.as_ref().unwrap().retire((self.chunk, self.index));
+            }
+        }
+    }
+}
+
+impl<T> Deref for ConcurrentRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The object was written before the handle was made, and isn't
+        // dropped while a handle to it is left.
+        unsafe { &*(self.chunk().items[self.index].get() as *const T) }
+    }
+}
+
+impl<T> std::fmt::Debug for ConcurrentRc<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+/// Counts the objects which have been dropped.
+#[cfg(test)]
+struct Counted(usize, Arc<AtomicUsize>);
+
+#[cfg(test)]
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.1.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn allocates_concurrently() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let arena = ConcurrentArena::with_capacity(1);
+    let handles: Vec<Vec<ConcurrentRc<Counted>>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..8).map(|thread| {
+            let (arena, dropped) = (&arena, &dropped);
+            scope.spawn(move || (0..1000).map(|i| arena.alloc(Counted(thread * 1000 + i, dropped.clone()))).collect())
+        }).collect();
+
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    });
+
+    let mut values: Vec<_> = arena.iter().map(|counted| counted.0).collect();
+    values.sort();
+    assert_eq!(values, (0..8000).collect::<Vec<_>>());
+    assert_eq!(handles[3][7].0, 3007);
+
+    // Each thread's objects are in the order it stored them.
+    let positions: std::collections::HashMap<_, _> = arena.iter().enumerate().map(|(position, counted)| {
+        (counted as *const Counted, position)
+    }).collect();
+    let positions: Vec<_> = handles[5].iter().map(|handle| positions[&(&**handle as *const Counted)]).collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // The objects outlive the arena, and are dropped along with the last
+    // handle.
+    let kept = handles[0][0].clone();
+    drop((arena, handles));
+    assert_eq!((kept.0, dropped.load(Ordering::Relaxed)), (0, 0));
+    assert_eq!(ConcurrentRc::arena(&kept).iter().count(), 8000);
+    drop(kept);
+    assert_eq!(dropped.load(Ordering::Relaxed), 8000);
+}
+
+#[test]
+fn recycles() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let arena = ConcurrentArena::recycling_with_capacity(16);
+    let kept: Vec<_> = (0..4).map(|i| arena.alloc(Counted(i, dropped.clone()))).collect();
+
+    // A reader pinned before the last handle went away keeps the object.
+    let guard = arena.pin();
+    let extra = arena.alloc(Counted(4, dropped.clone()));
+    assert_eq!(guard.iter().count(), 5);
+    drop(extra);
+    assert_eq!((guard.iter().count(), dropped.load(Ordering::Relaxed)), (4, 0));
+    drop(guard);
+    assert_eq!(dropped.load(Ordering::Relaxed), 1);
+
+    // Threads allocating and dropping at once reuse the same places, while
+    // other threads read.
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let (arena, dropped) = (&arena, &dropped);
+            scope.spawn(move || {
+                for i in 0..1000 {
+                    let value = arena.alloc(Counted(thread * 1000 + i, dropped.clone()));
+                    assert_eq!(value.0, thread * 1000 + i);
+                    let guard = arena.pin();
+                    assert!(guard.iter().all(|counted| counted.0 < 4000));
+                }
+            });
+        }
+    });
+    assert_eq!(dropped.load(Ordering::Relaxed), 4001);
+    assert!(arena.pin().iter().count() < 100);
+    assert_eq!(kept.iter().map(|counted| counted.0).collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.iter().count()));
+    assert!(result.is_err());
+    drop((arena, kept));
+    assert_eq!(dropped.load(Ordering::Relaxed), 4005);
+}
+
+#[test]
+fn survives_panicking_destructors() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Panics while object 3 is being dropped, after counting it.
+    struct Bomb(Counted);
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            if self.0 .0 == 3 {
+                panic!("bomb");
+            }
+        }
+    }
+
+    // The rest of the chunk and the chunks before it are still dropped.
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let arena = ConcurrentArena::with_capacity(2);
+    for i in 0..10 {
+        arena.alloc(Bomb(Counted(i, dropped.clone())));
+    }
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(arena))).is_err());
+    assert_eq!(dropped.load(Ordering::Relaxed), 10);
+
+    // So are retired objects, which a reader kept from being dropped.
+    let arena = ConcurrentArena::recycling_with_capacity(4);
+    std::mem::forget(arena.pin());
+    for i in 3..6 {
+        drop(arena.alloc(Bomb(Counted(i, dropped.clone()))));
+    }
+    assert_eq!(dropped.load(Ordering::Relaxed), 10);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(arena))).is_err());
+    assert_eq!(dropped.load(Ordering::Relaxed), 13);
+}
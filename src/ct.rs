@@ -0,0 +1,64 @@
+//! Constant-time comparison of objects with `subtle`, for secrets which
+//! mustn't be compared in a way that leaks where they first differ.
+
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+
+use {Arena, Rc};
+
+impl<T: ConstantTimeEq> ConstantTimeEq for Rc<T> {
+    fn ct_eq(&self, other: &Rc<T>) -> Choice {
+        T::ct_eq(self, other)
+    }
+}
+
+impl<T: ConstantTimeEq> Arena<T> {
+    /// Find the first object equal to `needle`, comparing it with every
+    /// object in the arena in constant time. How long this takes depends
+    /// only on the number of objects, not on which of them matches, but
+    /// whether one does is revealed by the result.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let tokens = Arena::new();
+    /// for token in [0x1f2e_3d4c_u64, 0x5b6a_7988, 0x9786_a5b4].iter() {
+    ///     tokens.alloc(*token);
+    /// }
+    ///
+    /// assert!(tokens.ct_find(&0x5b6a_7988).is_some());
+    /// assert!(tokens.ct_find(&0x5b6a_7989).is_none());
+    /// ```
+    pub fn ct_find(&self, needle: &T) -> Option<Rc<T>> {
+        let mut found = Choice::from(0);
+        let mut index = 0u64;
+        let mut position = 0u64;
+
+        for chunk in self.chunks() {
+            for object in chunk.iter() {
+                let matches = needle.ct_eq(object) & !found;
+                index.conditional_assign(&position, matches);
+                found |= matches;
+                position += 1;
+            }
+        }
+
+        if bool::from(found) {
+            let (ptr, slot) = self.locate(index as usize)?;
+            Some(Rc::new_handle(self.chunks.clone(), ptr, slot))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn compares_in_constant_time() {
+    let arena = Arena::with_capacity(2);
+    let keys: Vec<_> = [5u32, 7, 5, 9].iter().map(|&key| arena.alloc(key)).collect();
+
+    assert!(bool::from(keys[0].ct_eq(&keys[2])));
+    assert!(!bool::from(keys[0].ct_eq(&keys[1])));
+    assert!(Rc::ptr_eq(&arena.ct_find(&5).unwrap(), &keys[0]));
+    assert!(Rc::ptr_eq(&arena.ct_find(&9).unwrap(), &keys[3]));
+    assert!(arena.ct_find(&6).is_none());
+}
@@ -0,0 +1,160 @@
+//! A cursor which walks over the objects of an arena in allocation order,
+//! like `std::collections::linked_list::CursorMut`.
+
+use std::cell::RefMut;
+
+use {Arena, Rc};
+
+/// A cursor over the objects of an arena, returned by `Arena::cursor_mut`.
+///
+/// The cursor is either at an object or at the "ghost" position past the
+/// last one, from which moving wraps around to either end. Objects stored
+/// with `push_back` are visited after the ones already in the arena.
+///
+/// ```rust
+/// use rc_arena::Arena;
+///
+/// let arena = Arena::new();
+/// for word in "a b c".split(' ') {
+///     arena.alloc(String::from(word));
+/// }
+///
+/// let mut cursor = arena.cursor_mut();
+/// while cursor.index().is_some() {
+///     if let Some(mut word) = cursor.current_mut() {
+///         word.make_ascii_uppercase();
+///     }
+///     cursor.move_next();
+/// }
+///
+/// let words: Vec<_> = arena.iter().map(|word| word.clone_inner()).collect();
+/// assert_eq!(words, ["A", "B", "C"]);
+/// ```
+pub struct CursorMut<'a, T: 'a> {
+    arena: &'a Arena<T>,
+    index: Option<usize>
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    /// The position of the current object in allocation order, or `None` at
+    /// the ghost position.
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Move to the next object, or from the last one to the ghost position,
+    /// or from the ghost position to the first one.
+    pub fn move_next(&mut self) {
+        let len = self.arena.len();
+
+        self.index = match self.index {
+            None if len != 0 => Some(0),
+            Some(index) if index + 1 < len => Some(index + 1),
+            _ => None
+        };
+    }
+
+    /// Move to the previous object, or from the first one to the ghost
+    /// position, or from the ghost position to the last one.
+    pub fn move_prev(&mut self) {
+        self.index = match self.index {
+            None => self.arena.len().checked_sub(1),
+            Some(index) => index.checked_sub(1)
+        };
+    }
+
+    /// Get a handle to the current object, unless at the ghost position.
+    pub fn current(&self) -> Option<Rc<T>> {
+        let (ptr, slot) = self.arena.locate(self.index?)?;
+
+        Some(Rc::new_handle(self.arena.chunks.clone(), ptr, slot))
+    }
+
+    /// Borrow the current object mutably. The arena stays borrowed until the
+    /// returned guard is dropped.
+    ///
+    /// This returns `None` at the ghost position, while the arena is in use,
+    /// if the object belongs to a `FrozenArena`, or if any `Rc` or `Weak`
+    /// refers to it, since they could otherwise see it change underneath
+    /// them.
+    pub fn current_mut(&mut self) -> Option<RefMut<'_, T>> {
+        let mut index = self.index?;
+        let chunks = self.arena.chunks.try_borrow_mut().ok()?;
+
+        let mut chunk = 0;
+        while index >= chunks.list.get(chunk)?.items.len() {
+            index -= chunks.list[chunk].items.len();
+            chunk += 1;
+        }
+
+        let referenced = unsafe { (*chunks.list[chunk].handle(index).1).is_referenced() };
+        if referenced || chunks.list[chunk].items.is_frozen() {
+            return None;
+        }
+
+        Some(RefMut::map(chunks, |chunks| &mut chunks.list[chunk].items[index]))
+    }
+
+    /// Store an object at the end of the arena, which the cursor will reach
+    /// after the objects already there.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `Arena::alloc`.
+    pub fn push_back(&mut self, value: T) -> Rc<T> {
+        self.arena.alloc(value)
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a cursor at the first object of the arena, or at the ghost
+    /// position if there are none.
+    pub fn cursor_mut(&self) -> CursorMut<'_, T> {
+        CursorMut {
+            arena: self,
+            index: if self.is_empty() { None } else { Some(0) }
+        }
+    }
+}
+
+#[test]
+fn rewrites() {
+    let arena = Arena::with_capacity(2);
+    for i in 0..3 {
+        arena.alloc(i);
+    }
+    let held = arena.alloc(3);
+
+    // Double everything that isn't held, appending a new object for each odd
+    // number, which gets doubled in turn.
+    let mut cursor = arena.cursor_mut();
+    let mut visited = vec![];
+    while let Some(index) = cursor.index() {
+        visited.push(index);
+        let appended = cursor.current_mut().and_then(|mut value| {
+            *value *= 2;
+            if *value % 4 == 2 && *value < 10 { Some(*value / 2 + 4) } else { None }
+        });
+        if let Some(value) = appended {
+            cursor.push_back(value);
+        }
+        cursor.move_next();
+    }
+
+    assert_eq!(visited, [0, 1, 2, 3, 4]);
+    assert_eq!(arena.iter().map(|value| *value).collect::<Vec<_>>(), [0, 2, 4, 3, 10]);
+    assert_eq!(*held, 3);
+
+    cursor.move_prev();
+    assert_eq!(*cursor.current().unwrap(), 10);
+    assert!(cursor.current_mut().is_some());
+    let first = arena.first();
+    cursor.move_next();
+    cursor.move_next();
+    assert!(cursor.current_mut().is_none());
+    drop(first);
+    let chunk = arena.chunks().next();
+    assert!(cursor.current_mut().is_none());
+    drop(chunk);
+    assert!(cursor.current_mut().is_some());
+}
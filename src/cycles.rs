@@ -0,0 +1,159 @@
+//! Breaking up reference cycles between the objects of an arena, which would
+//! otherwise keep each other, and the arena, alive forever.
+
+use std::collections::HashMap;
+
+use {table, Arena, Rc, Slot};
+
+/// Objects which can tell `Arena::collect_cycles` about the `Rc`s they hold
+/// into their own arena, and let go of them.
+pub trait Trace: Sized {
+    /// Call `visit` with each `Rc` this object holds.
+    fn trace(&self, visit: &mut dyn FnMut(&Rc<Self>));
+
+    /// Drop the `Rc`s this object holds, which are part of a cycle that
+    /// nothing outside of it refers to anymore.
+    fn clear(&self);
+}
+
+impl<T: Trace> Arena<T> {
+    /// Find the objects which are only kept alive by cycles of `Rc`s among
+    /// themselves, and `clear` them so that the cycles are broken. This
+    /// returns the number of objects which were cleared.
+    ///
+    /// Objects referred to by an `Rc` that doesn't live in another object
+    /// of the arena are in use, along with everything they lead to. Tracing
+    /// must not use the arena.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    /// use rc_arena::{Arena, Rc, Trace};
+    ///
+    /// struct Node {
+    ///     next: RefCell<Option<Rc<Node>>>
+    /// }
+    ///
+    /// impl Trace for Node {
+    ///     fn trace(&self, visit: &mut dyn FnMut(&Rc<Node>)) {
+    ///         self.next.borrow().as_ref().map(visit);
+    ///     }
+    ///
+    ///     fn clear(&self) {
+    ///         *self.next.borrow_mut() = None;
+    ///     }
+    /// }
+    ///
+    /// let arena = Arena::new();
+    /// let a = arena.alloc(Node { next: RefCell::new(None) });
+    /// let b = arena.alloc(Node { next: RefCell::new(Some(a.clone())) });
+    /// *a.next.borrow_mut() = Some(b);
+    ///
+    /// assert_eq!(arena.collect_cycles(), 0);
+    /// drop(a);
+    /// assert_eq!(arena.collect_cycles(), 2);
+    /// ```
+    pub fn collect_cycles(&self) -> usize {
+        let garbage = {
+            let chunks = self.chunks.borrow();
+
+            let objects: Vec<(*mut T, *const Slot)> = chunks.list.iter().flat_map(|chunk| {
+                (0..chunk.items.len()).map(move |index| {
+                    let (ptr, slot) = chunk.handle(index);
+                    (table::resolve(ptr, slot), slot)
+                })
+            }).collect();
+            let indices: HashMap<*const Slot, usize> = objects.iter().enumerate().map(|(index, &(_, slot))| (slot, index)).collect();
+
+            let children = |index: usize, f: &mut dyn FnMut(usize)| {
+                let object = unsafe { &*objects[index].0 };
+                object.trace(&mut |child| {
+                    if let Some(&child) = indices.get(&child.slot) {
+                        f(child);
+                    }
+                });
+            };
+
+            // Whatever holds an object beyond the other objects is outside of
+            // the arena, so the object is in use.
+            let mut internal = vec![0; objects.len()];
+            for index in 0..objects.len() {
+                children(index, &mut |child| internal[child] += 1);
+            }
+
+            let strong = |index: usize| unsafe { (*objects[index].1).strong.get() };
+            let mut reachable: Vec<bool> = (0..objects.len()).map(|index| strong(index) > internal[index]).collect();
+            let mut stack: Vec<usize> = (0..objects.len()).filter(|&index| reachable[index]).collect();
+
+            while let Some(index) = stack.pop() {
+                children(index, &mut |child| {
+                    if !reachable[child] {
+                        reachable[child] = true;
+                        stack.push(child);
+                    }
+                });
+            }
+
+            // Unreferenced objects aren't part of any cycle.
+            (0..objects.len()).filter(|&index| !reachable[index] && strong(index) > 0)
+                              .map(|index| Rc::new_handle(self.chunks.clone(), objects[index].0, objects[index].1))
+                              .collect::<Vec<Rc<T>>>()
+        };
+
+        // The handles keep every object alive until all of them are cleared.
+        for rc in garbage.iter() {
+            rc.clear();
+        }
+
+        garbage.len()
+    }
+}
+
+#[test]
+fn collects() {
+    use std::cell::RefCell;
+
+    struct Node {
+        edges: RefCell<Vec<Rc<Node>>>,
+        _token: std::rc::Rc<()>
+    }
+
+    impl Trace for Node {
+        fn trace(&self, visit: &mut dyn FnMut(&Rc<Node>)) {
+            for edge in self.edges.borrow().iter() {
+                visit(edge);
+            }
+        }
+
+        fn clear(&self) {
+            self.edges.borrow_mut().clear();
+        }
+    }
+
+    let token = std::rc::Rc::new(());
+    let arena = Arena::with_capacity(2);
+    let nodes: Vec<_> = (0..5).map(|_| arena.alloc(Node { edges: RefCell::new(vec![]), _token: token.clone() })).collect();
+    let link = |from: usize, to: usize| nodes[from].edges.borrow_mut().push(nodes[to].clone());
+
+    // 0 <-> 1 -> 2 -> 2, and 3 -> 4 -> 3 where 3 is held from outside.
+    link(0, 1);
+    link(1, 0);
+    link(1, 2);
+    link(2, 2);
+    link(3, 4);
+    link(4, 3);
+    let held = nodes[3].clone();
+    drop(nodes);
+
+    assert_eq!(arena.collect_cycles(), 3);
+    assert_eq!(arena.collect_cycles(), 0);
+    assert_eq!(held.edges.borrow().len(), 1);
+
+    // Once the last cycle is gone, nothing keeps the arena alive.
+    drop(arena);
+    assert_eq!(std::rc::Rc::strong_count(&token), 6);
+    let arena = Rc::arena(&held);
+    drop(held);
+    assert_eq!(arena.collect_cycles(), 2);
+    drop(arena);
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
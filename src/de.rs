@@ -0,0 +1,181 @@
+//! Deserializing recursive structures straight into an arena with `serde`,
+//! so that their children come out as `Rc`s to objects of the arena rather
+//! than as `Box`es which would have to be moved into it afterwards.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+
+use {Arena, Rc};
+
+/// Types which deserialize with the help of the arena they are stored in,
+/// which they can allocate their children into through `Arena::seed` and
+/// `Arena::seq_seed`.
+pub trait DeserializeIn<'de>: Sized {
+    /// Deserialize a value whose children are allocated into `arena`.
+    fn deserialize_in<D: Deserializer<'de>>(arena: &Arena<Self>, deserializer: D) -> Result<Self, D::Error>;
+}
+
+/// A `DeserializeSeed` producing an `Rc` to an object which has been
+/// deserialized into an arena, created by `Arena::seed`.
+pub struct ArenaSeed<'a, T: 'a> {
+    arena: &'a Arena<T>
+}
+
+impl<'a, T> Clone for ArenaSeed<'a, T> {
+    fn clone(&self) -> ArenaSeed<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T> Copy for ArenaSeed<'a, T> {}
+
+impl<'a, 'de, T: DeserializeIn<'de>> DeserializeSeed<'de> for ArenaSeed<'a, T> {
+    type Value = Rc<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Rc<T>, D::Error> {
+        T::deserialize_in(self.arena, deserializer).map(|value| self.arena.alloc(value))
+    }
+}
+
+/// A `DeserializeSeed` producing a `Vec` of `Rc`s from a sequence of objects
+/// which have been deserialized into an arena, created by `Arena::seq_seed`.
+pub struct ArenaSeqSeed<'a, T: 'a> {
+    arena: &'a Arena<T>
+}
+
+impl<'a, T> Clone for ArenaSeqSeed<'a, T> {
+    fn clone(&self) -> ArenaSeqSeed<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T> Copy for ArenaSeqSeed<'a, T> {}
+
+impl<'a, 'de, T: DeserializeIn<'de>> DeserializeSeed<'de> for ArenaSeqSeed<'a, T> {
+    type Value = Vec<Rc<T>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Rc<T>>, D::Error> {
+        struct SeqVisitor<'a, 'de, T: 'a> {
+            arena: &'a Arena<T>,
+            marker: PhantomData<&'de ()>
+        }
+
+        impl<'a, 'de, T: DeserializeIn<'de>> Visitor<'de> for SeqVisitor<'a, 'de, T> {
+            type Value = Vec<Rc<T>>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Rc<T>>, A::Error> {
+                let mut rcs = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(rc) = seq.next_element_seed(self.arena.seed())? {
+                    rcs.push(rc);
+                }
+
+                Ok(rcs)
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor {
+            arena: self.arena,
+            marker: PhantomData
+        })
+    }
+}
+
+impl<T> Arena<T> {
+    /// A `DeserializeSeed` which deserializes an object into this arena and
+    /// produces an `Rc` to it.
+    ///
+    /// ```rust
+    /// extern crate rc_arena;
+    /// extern crate serde;
+    /// extern crate serde_json;
+    ///
+    /// use std::fmt;
+    /// use rc_arena::{Arena, DeserializeIn, Rc};
+    /// use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+    ///
+    /// // A tree written as `[value, [child, ...]]`.
+    /// struct Tree {
+    ///     value: u32,
+    ///     children: Vec<Rc<Tree>>
+    /// }
+    ///
+    /// struct TreeVisitor<'a>(&'a Arena<Tree>);
+    ///
+    /// impl<'a, 'de> Visitor<'de> for TreeVisitor<'a> {
+    ///     type Value = Tree;
+    ///
+    ///     fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         f.write_str("a tree")
+    ///     }
+    ///
+    ///     fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tree, A::Error> {
+    ///         Ok(Tree {
+    ///             value: seq.next_element()?.unwrap(),
+    ///             children: seq.next_element_seed(self.0.seq_seed())?.unwrap()
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// impl<'de> DeserializeIn<'de> for Tree {
+    ///     fn deserialize_in<D: Deserializer<'de>>(arena: &Arena<Tree>, deserializer: D) -> Result<Tree, D::Error> {
+    ///         deserializer.deserialize_seq(TreeVisitor(arena))
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let arena = Arena::<Tree>::new();
+    ///     let mut json = serde_json::Deserializer::from_str("[1, [[2, []], [3, [[4, []]]]]]");
+    ///     let root = arena.seed().deserialize(&mut json).unwrap();
+    ///
+    ///     assert_eq!(arena.len(), 4);
+    ///     assert_eq!(root.children[1].children[0].value, 4);
+    /// }
+    /// ```
+    pub fn seed(&self) -> ArenaSeed<'_, T> {
+        ArenaSeed {
+            arena: self
+        }
+    }
+
+    /// A `DeserializeSeed` which deserializes a sequence of objects into this
+    /// arena and produces an `Rc` to each of them.
+    pub fn seq_seed(&self) -> ArenaSeqSeed<'_, T> {
+        ArenaSeqSeed {
+            arena: self
+        }
+    }
+}
+
+#[test]
+fn deserializes_in_arena() {
+    use serde::de::Error;
+
+    struct Leaf(u8);
+
+    impl<'de> DeserializeIn<'de> for Leaf {
+        fn deserialize_in<D: Deserializer<'de>>(_: &Arena<Leaf>, deserializer: D) -> Result<Leaf, D::Error> {
+            let value: u8 = serde::Deserialize::deserialize(deserializer)?;
+            if value == 0 {
+                return Err(D::Error::custom("zero"));
+            }
+
+            Ok(Leaf(value))
+        }
+    }
+
+    let arena = Arena::<Leaf>::new();
+    let leaves = arena.seq_seed().deserialize(&mut serde_json::Deserializer::from_str("[1, 2, 3]")).unwrap();
+    assert_eq!(leaves.iter().map(|leaf| leaf.0).collect::<Vec<_>>(), [1, 2, 3]);
+    assert!(Rc::ptr_eq(&leaves[2], &arena.last().unwrap()));
+
+    // Whatever was allocated before an error stays in the arena.
+    assert!(arena.seq_seed().deserialize(&mut serde_json::Deserializer::from_str("[4, 0]")).is_err());
+    assert!(arena.seed().deserialize(&mut serde_json::Deserializer::from_str("\"5\"")).is_err());
+    assert_eq!(arena.len(), 4);
+}
@@ -0,0 +1,115 @@
+//! Constructing large objects directly in the memory of a chunk, rather than
+//! moving them into the arena.
+
+use std::mem::MaybeUninit;
+
+use {hooks, Arena, Rc, Reservation};
+#[cfg(feature = "futures")]
+use stream;
+#[cfg(feature = "alloc-backtraces")]
+use leaks;
+
+impl<T> Arena<T> {
+    /// Store an object in the arena which `f` writes in place, so that it
+    /// isn't copied on its way in like the value passed to `alloc` may be.
+    /// This matters for large objects, which can be written one field at a
+    /// time through `MaybeUninit::as_mut_ptr`.
+    ///
+    /// Like `alloc_cyclic`, `f` may allocate other objects in the arena. If
+    /// it panics, the arena is left as if nothing had been stored, but
+    /// whatever `f` already wrote is leaked. Objects recycled by a pool
+    /// aren't reused, since they would have to be dropped before `f` runs.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// struct Page {
+    ///     id: u32,
+    ///     data: [u8; 4096]
+    /// }
+    ///
+    /// let arena = Arena::<Page>::new();
+    /// let page = unsafe {
+    ///     arena.emplace(|slot| {
+    ///         let page = slot.as_mut_ptr();
+    ///         std::ptr::addr_of_mut!((*page).id).write(7);
+    ///         std::ptr::addr_of_mut!((*page).data).write_bytes(0xff, 1);
+    ///     })
+    /// };
+    ///
+    /// assert_eq!((page.id, page.data[4095]), (7, 0xff));
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `f` must initialize the object completely unless it panics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has been sealed, or if an arena created with
+    /// `with_limit` has reached its limit.
+    pub unsafe fn emplace<F: FnOnce(&mut MaybeUninit<T>)>(&self, f: F) -> Rc<T> {
+        let (chunk_index, item_index, place, ptr, slot, new_chunk) = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let num_chunks = chunks_borrow.list.len();
+            let chunk_index = chunks_borrow.chunk_with_space();
+            chunks_borrow.list[chunk_index].reserved = true;
+
+            let item_index = chunks_borrow.list[chunk_index].items.len();
+            // Handles to objects of indirect arenas don't point at them.
+            let place = chunks_borrow.list[chunk_index].items.as_mut_ptr().add(item_index);
+            let (ptr, slot) = chunks_borrow.push_slot(chunk_index, 0);
+
+            (chunk_index, item_index, place, ptr, slot, chunks_borrow.new_chunk_since(num_chunks))
+        };
+
+        let reservation = Reservation {
+            chunks: &self.chunks,
+            chunk: chunk_index
+        };
+
+        f(&mut *(place as *mut MaybeUninit<T>));
+        {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let chunk = &mut chunks_borrow.list[chunk_index];
+            debug_assert_eq!(chunk.items.len(), item_index);
+            chunk.items.set_len(item_index + 1);
+            chunk.placed(item_index);
+            #[cfg(feature = "futures")]
+            stream::notify(&mut chunks_borrow);
+        }
+        drop(reservation);
+
+        let rc = Rc::new_handle(self.chunks.clone(), ptr, slot);
+        #[cfg(feature = "alloc-backtraces")]
+        leaks::record(&rc);
+        hooks::run(self.chunks.borrow().hooks.clone(), Some(&rc), new_chunk);
+
+        rc
+    }
+}
+
+#[test]
+fn emplaces() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let arena = Arena::indirect_with_capacity(1);
+    let first = unsafe { arena.emplace(|slot| { slot.write([1u64; 512]); }) };
+
+    // The reservation is released when `f` panics, and other objects can
+    // be allocated while it runs.
+    let inner = std::cell::RefCell::new(None);
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe {
+        arena.emplace(|_| {
+            *inner.borrow_mut() = Some(arena.alloc([2; 512]));
+            panic!("failed to construct");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(arena.len(), 2);
+
+    let last = unsafe { arena.emplace(|slot| { slot.write([3; 512]); }) };
+    arena.assert_invariants();
+    assert_eq!((first[511], inner.borrow().as_ref().unwrap()[0], last[0]), (1, 2, 3));
+    assert_eq!(arena.iter().map(|page| page[0]).collect::<Vec<_>>(), [1, 2, 3]);
+}
@@ -0,0 +1,216 @@
+//! A log of the allocations an arena makes, kept in a ring buffer, which can
+//! be dumped from a program in production and replayed into another arena to
+//! reproduce how it grew, such as in a benchmark.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use {Arena, Chunks, Rc};
+
+/// An allocation recorded by an `EventLog`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// The position of the object in allocation order.
+    pub index: usize,
+    /// The size of the object in bytes.
+    pub size: usize,
+    /// How long after the log was started the object was allocated.
+    pub elapsed: Duration,
+    /// The label the log had when the object was allocated.
+    pub label: Option<std::rc::Rc<str>>
+}
+
+pub struct Ring {
+    events: VecDeque<Event>,
+    capacity: usize,
+    overwritten: usize,
+    label: Option<std::rc::Rc<str>>,
+    started: Instant
+}
+
+/// The allocations of an arena, recorded once `Arena::record_events` is
+/// called. Only the latest `capacity` events are kept, older ones being
+/// overwritten by newer ones.
+///
+/// Objects which an object pool reuses aren't recorded, since the arena
+/// doesn't grow for them.
+///
+/// ```rust
+/// use rc_arena::Arena;
+///
+/// let arena = Arena::new();
+/// let log = arena.record_events(1024);
+/// log.set_label(Some("parse"));
+/// arena.alloc(1u32);
+/// arena.alloc(2u32);
+///
+/// let events = log.events();
+/// assert_eq!((events[1].index, events[1].size), (1, 4));
+/// assert_eq!(events[1].label.as_deref(), Some("parse"));
+///
+/// // Dump the events, and grow another arena the same way.
+/// let mut dump = vec![];
+/// log.dump(&mut dump).unwrap();
+/// let events = rc_arena::EventLog::load(&dump[..]).unwrap();
+/// let replayed = Arena::new().replay(&events, |event| event.index as u32);
+/// assert_eq!(replayed.len(), 2);
+/// ```
+#[derive(Clone)]
+pub struct EventLog {
+    ring: std::rc::Rc<RefCell<Ring>>
+}
+
+impl EventLog {
+    /// Set the label of the events recorded from now on, such as the phase
+    /// of the program they happen in.
+    pub fn set_label(&self, label: Option<&str>) {
+        self.ring.borrow_mut().label = label.map(std::rc::Rc::from);
+    }
+
+    /// Get the events which have been kept, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.ring.borrow().events.iter().cloned().collect()
+    }
+
+    /// Get the number of events which have been overwritten by newer ones.
+    pub fn overwritten(&self) -> usize {
+        self.ring.borrow().overwritten
+    }
+
+    /// Forget every event which has been recorded.
+    pub fn clear(&self) {
+        let mut ring = self.ring.borrow_mut();
+        ring.events.clear();
+        ring.overwritten = 0;
+    }
+
+    /// Write the events which have been kept to `writer`, one per line, as
+    /// their index, size and elapsed nanoseconds, followed by their label if
+    /// they have one.
+    pub fn dump<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for event in self.ring.borrow().events.iter() {
+            write!(writer, "{} {} {}", event.index, event.size, event.elapsed.as_nanos())?;
+            if let Some(ref label) = event.label {
+                write!(writer, " {}", label)?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the events written by `dump`.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Vec<Event>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed event");
+
+        reader.lines().map(|line| {
+            let line = line?;
+            let mut fields = line.splitn(4, ' ');
+            let mut number = || fields.next().and_then(|field| field.parse::<u64>().ok()).ok_or_else(invalid);
+
+            let (index, size, nanos) = (number()?, number()?, number()?);
+            Ok(Event {
+                index: index as usize,
+                size: size as usize,
+                elapsed: Duration::from_nanos(nanos),
+                label: fields.next().map(std::rc::Rc::from)
+            })
+        }).collect()
+    }
+}
+
+impl std::fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let ring = self.ring.borrow();
+        f.debug_struct("EventLog")
+            .field("events", &ring.events.len())
+            .field("capacity", &ring.capacity)
+            .field("overwritten", &ring.overwritten)
+            .finish()
+    }
+}
+
+impl<T> Arena<T> {
+    /// Start recording the arena's allocations, keeping the latest
+    /// `capacity` of them. Recording again replaces the log, which the one
+    /// returned before stops seeing events from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn record_events(&self, capacity: usize) -> EventLog {
+        assert!(capacity != 0, "an event log needs room for at least one event");
+
+        let ring = std::rc::Rc::new(RefCell::new(Ring {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            overwritten: 0,
+            label: None,
+            started: Instant::now()
+        }));
+        self.chunks.borrow_mut().events = Some(ring.clone());
+
+        EventLog {
+            ring
+        }
+    }
+
+    /// Allocate an object for each of `events` in turn, constructing them
+    /// with `f`, so that the arena grows like the one they were recorded in
+    /// did. The objects are handed back so that they can be dropped when the
+    /// replay is done.
+    pub fn replay<F: FnMut(&Event) -> T>(&self, events: &[Event], mut f: F) -> Vec<Rc<T>> {
+        events.iter().map(|event| self.alloc(f(event))).collect()
+    }
+}
+
+/// Record the allocation of the object at position `index` of a chunk.
+pub fn record<T>(chunks: &Chunks<T>, chunk: usize, index: usize) {
+    if let Some(ref ring) = chunks.events {
+        let preceding: usize = chunks.list[..chunk].iter().map(|chunk| chunk.items.len()).sum();
+        let mut ring = ring.borrow_mut();
+
+        if ring.events.len() == ring.capacity {
+            ring.events.pop_front();
+            ring.overwritten += 1;
+        }
+        let event = Event {
+            index: preceding + index,
+            size: std::mem::size_of::<T>(),
+            elapsed: ring.started.elapsed(),
+            label: ring.label.clone()
+        };
+        ring.events.push_back(event);
+    }
+}
+
+#[test]
+fn records_events() {
+    let arena = Arena::with_capacity(2);
+    let log = arena.record_events(4);
+    for i in 0..3 {
+        arena.alloc([i as u8; 3]);
+    }
+    log.set_label(Some("second phase"));
+    arena.alloc_n(3, |i| [i as u8; 3]);
+
+    let events = log.events();
+    assert_eq!(events.iter().map(|event| event.index).collect::<Vec<_>>(), [2, 3, 4, 5]);
+    assert!(events.iter().all(|event| event.size == 3));
+    assert!(events.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+    assert_eq!((events[0].label.as_deref(), events[1].label.as_deref()), (None, Some("second phase")));
+    assert_eq!(log.overwritten(), 2);
+
+    let mut dump = vec![];
+    log.dump(&mut dump).unwrap();
+    assert_eq!(EventLog::load(&dump[..]).unwrap(), events);
+    assert!(EventLog::load(&b"1 two 3\n"[..]).is_err());
+
+    log.clear();
+    let copy = Arena::with_capacity(2);
+    let replayed = copy.replay(&events, |event| event.index);
+    assert_eq!((copy.len(), copy.chunks().count(), *replayed[3]), (4, 2, 5));
+    assert!(log.events().is_empty());
+}
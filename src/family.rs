@@ -0,0 +1,153 @@
+//! Families of arenas which are always allocated into together, so that the
+//! objects at the same position in each of them belong together, like the
+//! components of an entity in an entity-component system.
+
+use {Arena, Rc};
+
+/// Tuples of components which can be stored in an `ArenaFamily`, with an
+/// arena for each component. This is implemented for tuples of up to six
+/// components.
+pub trait Components: Sized {
+    /// A tuple of an arena for each component.
+    type Arenas;
+    /// A tuple of an `Rc` to each component.
+    type Handles;
+
+    /// Create an arena for each component, with a known initial capacity.
+    fn arenas(capacity: usize) -> Self::Arenas;
+
+    /// Store each component in its arena.
+    fn alloc(arenas: &Self::Arenas, components: Self) -> Self::Handles;
+
+    /// Get handles to the components at position `index` of each arena.
+    fn get(arenas: &Self::Arenas, index: usize) -> Option<Self::Handles>;
+
+    /// Get the number of objects in the arenas.
+    fn len(arenas: &Self::Arenas) -> usize;
+}
+
+macro_rules! components {
+    ($($component:ident $index:tt),+) => {
+        impl<$($component),+> Components for ($($component,)+) {
+            type Arenas = ($(Arena<$component>,)+);
+            type Handles = ($(Rc<$component>,)+);
+
+            fn arenas(capacity: usize) -> Self::Arenas {
+                ($(Arena::<$component>::with_capacity(capacity),)+)
+            }
+
+            fn alloc(arenas: &Self::Arenas, components: Self) -> Self::Handles {
+                ($(arenas.$index.alloc(components.$index),)+)
+            }
+
+            fn get(arenas: &Self::Arenas, index: usize) -> Option<Self::Handles> {
+                Some(($({
+                    let (ptr, slot) = arenas.$index.locate(index)?;
+                    Rc::new_handle(arenas.$index.chunks.clone(), ptr, slot)
+                },)+))
+            }
+
+            fn len(arenas: &Self::Arenas) -> usize {
+                arenas.0.len()
+            }
+        }
+    }
+}
+
+components!(A 0);
+components!(A 0, B 1);
+components!(A 0, B 1, C 2);
+components!(A 0, B 1, C 2, D 3);
+components!(A 0, B 1, C 2, D 3, E 4);
+components!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// A family of arenas, one for each of the components in `C`, which are
+/// allocated into together so that they share their positions. Each arena
+/// is a column holding one component of every entity, and can be iterated
+/// over on its own.
+///
+/// The columns can be reached with `columns`, but allocating into one of
+/// them directly puts it out of step with the others.
+///
+/// ```rust
+/// use rc_arena::ArenaFamily;
+///
+/// let entities = ArenaFamily::<([f32; 2], [f32; 2], &str)>::new();
+/// entities.alloc(([0.0, 0.0], [1.0, 0.0], "ship"));
+/// entities.alloc(([5.0, 5.0], [0.0, 0.0], "rock"));
+///
+/// let (positions, velocities, _) = entities.columns();
+/// let moving = velocities.iter().filter(|velocity| **velocity != [0.0, 0.0]).count();
+/// assert_eq!(moving, 1);
+///
+/// let (position, _, name) = entities.get(1).unwrap();
+/// assert_eq!((*position, *name), ([5.0, 5.0], "rock"));
+/// assert_eq!(positions.len(), entities.len());
+/// ```
+pub struct ArenaFamily<C: Components> {
+    arenas: C::Arenas
+}
+
+impl<C: Components> ArenaFamily<C> {
+    /// Create a new family of arenas with an unspecified capacity.
+    pub fn new() -> ArenaFamily<C> {
+        ArenaFamily::with_capacity(8)
+    }
+
+    /// Create a new family of arenas with a known initial capacity.
+    pub fn with_capacity(n: usize) -> ArenaFamily<C> {
+        ArenaFamily {
+            arenas: C::arenas(n)
+        }
+    }
+
+    /// Store each of the components of an entity in its column, returning an
+    /// `Rc` to each of them.
+    pub fn alloc(&self, components: C) -> C::Handles {
+        C::alloc(&self.arenas, components)
+    }
+
+    /// Get an `Rc` to each of the components of the entity at position
+    /// `index`.
+    pub fn get(&self, index: usize) -> Option<C::Handles> {
+        C::get(&self.arenas, index)
+    }
+
+    /// Get the number of entities in the family.
+    pub fn len(&self) -> usize {
+        C::len(&self.arenas)
+    }
+
+    /// Returns `true` if there are no entities in the family.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The arena of each component.
+    pub fn columns(&self) -> &C::Arenas {
+        &self.arenas
+    }
+}
+
+impl<C: Components> Default for ArenaFamily<C> {
+    fn default() -> ArenaFamily<C> {
+        ArenaFamily::new()
+    }
+}
+
+#[test]
+fn shares_indices() {
+    let family = ArenaFamily::<(usize, String)>::with_capacity(2);
+    let handles: Vec<_> = (0..5).map(|i| family.alloc((i, i.to_string()))).collect();
+    assert_eq!(family.len(), 5);
+    assert!(family.get(5).is_none());
+
+    let (number, name) = family.get(3).unwrap();
+    assert!(Rc::ptr_eq(&number, &handles[3].0) && Rc::ptr_eq(&name, &handles[3].1));
+    let (numbers, names) = family.columns();
+    assert_eq!(numbers.index_of(&handles[4].0), names.index_of(&handles[4].1));
+
+    let single = ArenaFamily::<(u8,)>::default();
+    assert!(single.is_empty());
+    assert_eq!(*single.alloc((7,)).0, 7);
+}
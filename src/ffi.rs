@@ -0,0 +1,166 @@
+//! A C interface to arenas of records, which are blocks of bytes copied in
+//! when they are allocated. Arenas and handles are opaque pointers on the C
+//! side, created and freed only through these functions:
+//!
+//! ```c
+//! typedef struct RcArena RcArena;
+//! typedef struct RcHandle RcHandle;
+//!
+//! RcArena *rc_arena_new(void);
+//! void rc_arena_free(RcArena *arena);
+//! size_t rc_arena_len(const RcArena *arena);
+//! RcHandle *rc_arena_alloc(const RcArena *arena, const uint8_t *data, size_t len);
+//! void rc_arena_each(const RcArena *arena, void (*f)(const RcHandle *, void *), void *context);
+//! RcHandle *rc_handle_clone(const RcHandle *handle);
+//! void rc_handle_free(RcHandle *handle);
+//! const uint8_t *rc_handle_data(const RcHandle *handle, size_t *len);
+//! ```
+//!
+//! Records outlive the arena for as long as handles to them do, as with the
+//! Rust interface. Records are never written to after they are allocated.
+//!
+//! Build the crate as a `staticlib` or `cdylib` to link it, for example with
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use std::os::raw::c_void;
+
+use {Arena, Rc};
+
+/// The records of the arenas handed out over the C interface.
+pub type Record = Box<[u8]>;
+
+/// Create a new, empty arena of records.
+#[no_mangle]
+pub extern "C" fn rc_arena_new() -> *mut Arena<Record> {
+    Box::into_raw(Box::new(Arena::new()))
+}
+
+/// Free an arena created by `rc_arena_new`. Its records are freed along
+/// with the last handles to them.
+///
+/// # Safety
+///
+/// `arena` must have been returned by `rc_arena_new`, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rc_arena_free(arena: *mut Arena<Record>) {
+    if !arena.is_null() {
+        drop(Box::from_raw(arena));
+    }
+}
+
+/// Get the number of records placed in the arena.
+///
+/// # Safety
+///
+/// `arena` must be a live arena returned by `rc_arena_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rc_arena_len(arena: *const Arena<Record>) -> usize {
+    (*arena).len()
+}
+
+/// Copy `len` bytes from `data` into a new record, returning a handle to it
+/// which must be freed with `rc_handle_free`.
+///
+/// # Safety
+///
+/// `arena` must be a live arena returned by `rc_arena_new`, and `data` must
+/// point at `len` readable bytes, or may be null if `len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn rc_arena_alloc(arena: *const Arena<Record>, data: *const u8, len: usize) -> *mut Rc<Record> {
+    let bytes = if len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(data, len)
+    };
+
+    Box::into_raw(Box::new((*arena).alloc(bytes.into())))
+}
+
+/// Call `f` with a handle to every record in the arena, in the order they
+/// were allocated, passing `context` along. The handle is only valid during
+/// the call; clone it with `rc_handle_clone` to keep it.
+///
+/// # Safety
+///
+/// `arena` must be a live arena returned by `rc_arena_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rc_arena_each(arena: *const Arena<Record>,
+                                       f: extern "C" fn(*const Rc<Record>, *mut c_void),
+                                       context: *mut c_void) {
+    for rc in (*arena).iter() {
+        f(&rc, context);
+    }
+}
+
+/// Create another handle to the record `handle` refers to.
+///
+/// # Safety
+///
+/// `handle` must be a live handle returned by this interface.
+#[no_mangle]
+pub unsafe extern "C" fn rc_handle_clone(handle: *const Rc<Record>) -> *mut Rc<Record> {
+    Box::into_raw(Box::new((*handle).clone()))
+}
+
+/// Free a handle returned by `rc_arena_alloc` or `rc_handle_clone`.
+///
+/// # Safety
+///
+/// `handle` must have been returned by this interface, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rc_handle_free(handle: *mut Rc<Record>) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Get a pointer to the bytes of the record `handle` refers to, storing
+/// their number in `len` unless it is null. The bytes live as long as the
+/// handle does.
+///
+/// # Safety
+///
+/// `handle` must be a live handle returned by this interface, and `len` must
+/// be null or point at writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn rc_handle_data(handle: *const Rc<Record>, len: *mut usize) -> *const u8 {
+    let record: &Record = &*handle;
+
+    if !len.is_null() {
+        *len = record.len();
+    }
+
+    record.as_ptr()
+}
+
+#[test]
+fn round_trips() {
+    extern "C" fn sum_lengths(handle: *const Rc<Record>, context: *mut c_void) {
+        unsafe {
+            let mut len = 0;
+            rc_handle_data(handle, &mut len);
+            *(context as *mut usize) += len;
+        }
+    }
+
+    unsafe {
+        let arena = rc_arena_new();
+        let hello = rc_arena_alloc(arena, b"hello".as_ptr(), 5);
+        let empty = rc_arena_alloc(arena, std::ptr::null(), 0);
+        assert_eq!(rc_arena_len(arena), 2);
+
+        let mut total = 0usize;
+        rc_arena_each(arena, sum_lengths, &mut total as *mut usize as *mut c_void);
+        assert_eq!(total, 5);
+
+        let copy = rc_handle_clone(hello);
+        rc_handle_free(hello);
+        rc_handle_free(empty);
+        rc_arena_free(arena);
+
+        let mut len = 0;
+        let data = rc_handle_data(copy, &mut len);
+        assert_eq!(std::slice::from_raw_parts(data, len), b"hello");
+        rc_handle_free(copy);
+    }
+}
@@ -0,0 +1,192 @@
+//! Fixed-capacity arenas with their objects stored inline, which never
+//! allocate.
+
+use std::cell::{Cell, UnsafeCell};
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+
+/// Marks the end of the free list.
+const NONE: usize = usize::MAX;
+
+/// An arena of up to `N` objects which lives entirely inside of itself, so it
+/// can be put on the stack or inside another structure without allocating.
+/// Handles are reference counted like `Rc`s, but borrow the arena rather than
+/// keeping it alive, and an object's place is recycled as soon as its last
+/// handle is dropped. This makes it a good fit for short-lived scopes, such
+/// as a function which builds a small structure on the stack.
+///
+/// It isn't `Sync`, so it can't be put in a `static`, and the crate itself
+/// still needs `std`.
+///
+/// ```rust
+/// use rc_arena::StaticArena;
+///
+/// let arena: StaticArena<[u8; 16], 2> = StaticArena::new();
+/// let a = arena.alloc([1; 16]).unwrap();
+/// let b = arena.alloc([2; 16]).unwrap();
+///
+/// // The arena is full, so the value is handed back.
+/// assert_eq!(arena.alloc([3; 16]).unwrap_err(), [3; 16]);
+///
+/// let also_a = a.clone();
+/// drop(a);
+/// drop(b);
+/// assert_eq!(arena.len(), 1);
+/// assert!(arena.alloc([3; 16]).is_ok());
+/// assert_eq!(also_a[0], 1);
+/// ```
+pub struct StaticArena<T, const N: usize> {
+    items: [UnsafeCell<MaybeUninit<T>>; N],
+    // The strong count of each object, or zero if its place is free.
+    counts: [Cell<usize>; N],
+    // The next free place after each free place.
+    next: [Cell<usize>; N],
+    free: Cell<usize>,
+    // Places from here onwards have never been used.
+    high: Cell<usize>,
+    len: Cell<usize>
+}
+
+impl<T, const N: usize> StaticArena<T, N> {
+    /// Create a new, empty arena.
+    pub const fn new() -> StaticArena<T, N> {
+        StaticArena {
+            items: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            counts: [const { Cell::new(0) }; N],
+            next: [const { Cell::new(NONE) }; N],
+            free: Cell::new(NONE),
+            high: Cell::new(0),
+            len: Cell::new(0)
+        }
+    }
+
+    /// Store an object in the arena, returning a handle to it, or the object
+    /// itself if all `N` places are taken.
+    pub fn alloc(&self, value: T) -> Result<StaticRc<'_, T, N>, T> {
+        let index = match self.free.get() {
+            NONE if self.high.get() < N => {
+                self.high.set(self.high.get() + 1);
+                self.high.get() - 1
+            }
+            NONE => return Err(value),
+            index => {
+                self.free.set(self.next[index].get());
+                index
+            }
+        };
+
+        unsafe { (*self.items[index].get()).write(value) };
+        self.counts[index].set(1);
+        self.len.set(self.len.get() + 1);
+
+        Ok(StaticRc {
+            arena: self,
+            index
+        })
+    }
+
+    /// Get the number of objects in the arena which are still referenced.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Returns `true` if there are no objects in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of objects the arena can hold at once.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for StaticArena<T, N> {
+    fn default() -> StaticArena<T, N> {
+        StaticArena::new()
+    }
+}
+
+/// A reference counted handle to an object of a `StaticArena`.
+pub struct StaticRc<'a, T: 'a, const N: usize> {
+    arena: &'a StaticArena<T, N>,
+    index: usize
+}
+
+impl<'a, T, const N: usize> StaticRc<'a, T, N> {
+    /// Get the number of handles to this object.
+    pub fn strong_count(this: &StaticRc<'a, T, N>) -> usize {
+        this.arena.counts[this.index].get()
+    }
+
+    /// Returns `true` if both handles point to the same object.
+    pub fn ptr_eq(this: &StaticRc<'a, T, N>, other: &StaticRc<'a, T, N>) -> bool {
+        std::ptr::eq(this.arena, other.arena) && this.index == other.index
+    }
+}
+
+impl<'a, T, const N: usize> Clone for StaticRc<'a, T, N> {
+    fn clone(&self) -> StaticRc<'a, T, N> {
+        let count = &self.arena.counts[self.index];
+        count.set(count.get() + 1);
+
+        StaticRc {
+            arena: self.arena,
+            index: self.index
+        }
+    }
+}
+
+impl<'a, T, const N: usize> Drop for StaticRc<'a, T, N> {
+    fn drop(&mut self) {
+        let arena = self.arena;
+        let count = &arena.counts[self.index];
+        count.set(count.get() - 1);
+
+        if count.get() == 0 {
+            unsafe { (*arena.items[self.index].get()).assume_init_drop() };
+            arena.next[self.index].set(arena.free.get());
+            arena.free.set(self.index);
+            arena.len.set(arena.len.get() - 1);
+        }
+    }
+}
+
+impl<'a, T, const N: usize> Deref for StaticRc<'a, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // This is okay because the object isn't dropped until its last
+        // handle is, and the handle borrows the arena.
+        unsafe { (*self.arena.items[self.index].get()).assume_init_ref() }
+    }
+}
+
+impl<'a, T, const N: usize> std::fmt::Debug for StaticRc<'a, T, N> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+#[test]
+fn recycles_places() {
+    let token = std::rc::Rc::new(());
+    let arena: StaticArena<std::rc::Rc<()>, 3> = StaticArena::new();
+    let handles: Vec<_> = (0..3).map(|_| arena.alloc(token.clone()).unwrap()).collect();
+    assert!(arena.alloc(token.clone()).is_err());
+    assert_eq!(std::rc::Rc::strong_count(&token), 4);
+
+    // Dropping the last handle drops the object and frees its place.
+    let first = handles[0].clone();
+    assert_eq!(StaticRc::strong_count(&first), 2);
+    drop(handles);
+    assert_eq!((arena.len(), std::rc::Rc::strong_count(&token)), (1, 2));
+
+    let reused = arena.alloc(token.clone()).unwrap();
+    assert!(!StaticRc::ptr_eq(&first, &reused));
+    assert!(arena.alloc(token.clone()).is_ok());
+    assert_eq!(arena.capacity(), 3);
+    drop((first, reused));
+    assert!(arena.is_empty());
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
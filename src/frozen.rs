@@ -0,0 +1,247 @@
+//! Frozen arenas, whose objects can no longer change and so can be shared
+//! between threads.
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use {Arena, Items, Rc};
+
+/// The objects of an arena at the time it was frozen with `Arena::freeze`.
+/// They are shared with the arena's handles, but are never written to,
+/// dropped or moved until the last of them is gone, so a `FrozenArena`, and
+/// `FrozenRc`s to its objects, are `Send` and `Sync` when `T` is.
+pub struct FrozenArena<T> {
+    chunks: Vec<Arc<Vec<T>>>
+}
+
+impl<T> FrozenArena<T> {
+    /// Get the number of objects in the arena.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+
+    /// Returns `true` if there are no objects in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The chunk and position within it of the object at `index`.
+    fn position(&self, index: usize) -> Option<(usize, usize)> {
+        let mut index = index;
+
+        for (chunk_index, chunk) in self.chunks.iter().enumerate() {
+            if index < chunk.len() {
+                return Some((chunk_index, index));
+            }
+            index -= chunk.len();
+        }
+
+        None
+    }
+
+    /// Get a reference to the object at position `index` in allocation
+    /// order.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.position(index).map(|(chunk, index)| &self.chunks[chunk][index])
+    }
+
+    /// Get a `FrozenRc` to the object at position `index` in allocation
+    /// order.
+    pub fn handle(&self, index: usize) -> Option<FrozenRc<T>> {
+        self.position(index).map(|(chunk, index)| FrozenRc {
+            chunk: self.chunks[chunk].clone(),
+            index
+        })
+    }
+
+    /// Get a `FrozenRc` to the object `rc` points at, so that indexes built
+    /// out of the arena's handles before freezing can be carried over by
+    /// mapping them through this. This returns `None` if the object isn't
+    /// part of this `FrozenArena`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let index: Vec<_> = (0..10).map(|i| arena.alloc(i * i)).collect();
+    ///
+    /// let frozen = arena.freeze();
+    /// let index: Vec<_> = index.iter().map(|rc| frozen.promote(rc).unwrap()).collect();
+    /// drop(frozen);
+    ///
+    /// let nine = std::thread::spawn(move || *index[3]).join().unwrap();
+    /// assert_eq!(nine, 9);
+    /// ```
+    pub fn promote(&self, rc: &Rc<T>) -> Option<FrozenRc<T>> {
+        let chunks = rc.chunks.borrow();
+
+        chunks.list.iter().filter_map(|chunk| {
+            match chunk.items {
+                Items::Frozen(ref items) => chunk.index_of(rc.ptr(), rc.slot).map(|index| (items, index)),
+                _ => None
+            }
+        }).next().and_then(|(items, index)| {
+            self.chunks.iter().find(|chunk| Arc::ptr_eq(chunk, items)).map(|chunk| FrozenRc {
+                chunk: chunk.clone(),
+                index
+            })
+        })
+    }
+
+    /// Iterate over the objects in the order they were allocated.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+}
+
+impl<T> Clone for FrozenArena<T> {
+    fn clone(&self) -> FrozenArena<T> {
+        FrozenArena {
+            chunks: self.chunks.clone()
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for FrozenArena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A reference counted pointer to an object of a `FrozenArena`, which keeps
+/// the object's chunk alive.
+pub struct FrozenRc<T> {
+    chunk: Arc<Vec<T>>,
+    index: usize
+}
+
+impl<T> FrozenRc<T> {
+    /// Returns `true` if both handles point at the same object.
+    pub fn ptr_eq(this: &FrozenRc<T>, other: &FrozenRc<T>) -> bool {
+        Arc::ptr_eq(&this.chunk, &other.chunk) && this.index == other.index
+    }
+}
+
+impl<T> Clone for FrozenRc<T> {
+    fn clone(&self) -> FrozenRc<T> {
+        FrozenRc {
+            chunk: self.chunk.clone(),
+            index: self.index
+        }
+    }
+}
+
+impl<T> Deref for FrozenRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.chunk[self.index]
+    }
+}
+
+impl<T> std::fmt::Debug for FrozenRc<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+impl<T> Arena<T> {
+    /// Freeze the objects currently in the arena, so that they can be shared
+    /// with other threads.
+    ///
+    /// Existing handles keep working, but the frozen objects can't be
+    /// rolled back or compacted anymore, and a pool stops recycling objects.
+    /// Objects allocated afterwards, through a clone of the arena or
+    /// `Rc::arena`, aren't part of the `FrozenArena`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// for i in 0..100 {
+    ///     arena.alloc(i);
+    /// }
+    ///
+    /// let frozen = arena.freeze();
+    /// let sum = std::thread::spawn(move || frozen.iter().sum::<usize>()).join().unwrap();
+    /// assert_eq!(sum, 4950);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an object is being constructed by `alloc_cyclic`, or if the
+    /// arena's chunks don't live on the heap.
+    pub fn freeze(self) -> FrozenArena<T> {
+        let mut chunks_borrow = self.chunks.borrow_mut();
+        let chunks = &mut *chunks_borrow;
+
+        assert!(chunks.list.iter().all(|chunk| !chunk.reserved),
+                "can't freeze an arena while an object is being constructed");
+
+        // Recycling writes to the objects. Pooled objects stay referenced by
+        // their old pool forever.
+        std::mem::forget(chunks.pool.take());
+
+        let frozen = chunks.list.iter_mut().map(|chunk| {
+            let items = match std::mem::replace(&mut chunk.items, Items::Heap(vec![])) {
+                Items::Heap(vec) => Arc::new(vec),
+                Items::Frozen(items) => items,
+                #[allow(unreachable_patterns)]
+                _ => panic!("only arenas on the heap can be frozen")
+            };
+            chunk.items = Items::Frozen(items.clone());
+
+            items
+        }).collect();
+
+        FrozenArena {
+            chunks: frozen
+        }
+    }
+}
+
+#[test]
+fn freezes() {
+    let token = Arc::new(());
+    let arena = Arena::pool_with_capacity(2, |_: &mut (usize, Arc<()>)| {});
+    let handles: Vec<_> = (0..5).map(|i| arena.alloc((i, token.clone()))).collect();
+    let checkpoint = arena.checkpoint();
+    let pooled = arena.alloc((5, token.clone()));
+    drop(pooled);
+
+    let frozen = arena.clone().freeze();
+    assert_eq!(frozen.len(), 6);
+    assert_eq!(frozen.get(2).unwrap().0, 2);
+    assert!(frozen.get(6).is_none());
+    let two = frozen.handle(2).unwrap();
+    assert!(FrozenRc::ptr_eq(&two, &frozen.handle(2).unwrap()));
+    assert!(FrozenRc::ptr_eq(&two, &frozen.promote(&handles[2]).unwrap()));
+
+    // Nothing frozen is removed or reused, but the arena carries on.
+    assert!(arena.rollback(checkpoint).is_err());
+    assert!(arena.recycle().is_none());
+    let six = arena.alloc((6, token.clone()));
+    assert_eq!(arena.len(), 7);
+    assert_eq!(*handles[4], (4, token.clone()));
+    assert!(frozen.promote(&six).is_none());
+    let later = Rc::arena(&six).clone().freeze();
+    assert_eq!(later.len(), 7);
+    assert!(FrozenRc::ptr_eq(&later.promote(&handles[2]).unwrap(), &two));
+    assert_eq!(later.promote(&six).unwrap().0, 6);
+    assert!(frozen.promote(&Arena::new().alloc((0, token.clone()))).is_none());
+    drop(later);
+
+    let threads: Vec<_> = (0..2).map(|_| {
+        let frozen = frozen.clone();
+        std::thread::spawn(move || frozen.iter().map(|item| item.0).sum::<usize>())
+    }).collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 15);
+    }
+
+    // The objects are dropped along with the last handle to them.
+    drop((arena, handles, six, frozen));
+    assert_eq!(Arc::strong_count(&token), 5);
+    drop(two);
+    assert_eq!(Arc::strong_count(&token), 1);
+}
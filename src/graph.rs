@@ -0,0 +1,255 @@
+//! Graphs whose nodes and edges live in arenas. Nodes own their outgoing
+//! edges and edges own their targets, while the links back from a node to
+//! its incoming edges, and from an edge to its source, are weak.
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+
+use {Arena, Iter, Rc, Weak};
+
+#[cfg(feature = "petgraph")]
+mod visit;
+
+#[cfg(feature = "petgraph")]
+pub use self::visit::{EdgeIndex, EdgeReference, NodeIndex};
+
+/// A node of a `GraphArena`, holding a value and its links to the edges
+/// around it.
+pub struct Node<N, E> {
+    pub value: N,
+    outgoing: RefCell<Vec<Rc<Edge<N, E>>>>,
+    incoming: RefCell<Vec<Weak<Edge<N, E>>>>
+}
+
+impl<N, E> Node<N, E> {
+    /// The edges leaving this node, in the order they were added.
+    pub fn outgoing(&self) -> Vec<Rc<Edge<N, E>>> {
+        self.outgoing.borrow().clone()
+    }
+
+    /// The edges arriving at this node which are still alive, in the order
+    /// they were added.
+    pub fn incoming(&self) -> Vec<Rc<Edge<N, E>>> {
+        self.incoming.borrow().iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// The targets of the edges leaving this node.
+    pub fn children(&self) -> Vec<Rc<Node<N, E>>> {
+        self.outgoing.borrow().iter().map(|edge| edge.target.clone()).collect()
+    }
+
+    /// The sources of the edges arriving at this node.
+    pub fn parents(&self) -> Vec<Rc<Node<N, E>>> {
+        self.incoming().iter().filter_map(|edge| edge.source()).collect()
+    }
+}
+
+/// An edge of a `GraphArena`, holding a value and leading from one node to
+/// another.
+pub struct Edge<N, E> {
+    pub value: E,
+    source: Weak<Node<N, E>>,
+    target: Rc<Node<N, E>>
+}
+
+impl<N, E> Edge<N, E> {
+    /// The node this edge leaves, unless it has been dropped.
+    pub fn source(&self) -> Option<Rc<Node<N, E>>> {
+        self.source.upgrade()
+    }
+
+    /// The node this edge arrives at.
+    pub fn target(&self) -> &Rc<Node<N, E>> {
+        &self.target
+    }
+}
+
+/// A graph with node values of type `N` and edge values of type `E`, which
+/// stores its nodes and edges in an arena each.
+///
+/// Since nodes and edges keep each other's arenas alive, the edges leaving
+/// each node are cut when the graph is dropped. Nodes which are still
+/// referenced can be used afterwards, but have no outgoing edges.
+///
+/// ```rust
+/// use rc_arena::graph::{self, GraphArena};
+///
+/// let graph = GraphArena::new();
+/// let a = graph.add_node("a");
+/// let b = graph.add_node("b");
+/// let c = graph.add_node("c");
+/// graph.add_edge(&a, &b, 1);
+/// graph.add_edge(&a, &c, 2);
+/// graph.add_edge(&b, &c, 3);
+///
+/// let parents: Vec<_> = c.parents().iter().map(|node| node.value).collect();
+/// assert_eq!(parents, ["a", "b"]);
+///
+/// let visited: Vec<_> = graph::breadth_first(&a).iter().map(|node| node.value).collect();
+/// assert_eq!(visited, ["a", "b", "c"]);
+/// ```
+pub struct GraphArena<N, E> {
+    nodes: Arena<Node<N, E>>,
+    edges: Arena<Edge<N, E>>
+}
+
+impl<N, E> GraphArena<N, E> {
+    /// Create a new graph with an unspecified capacity.
+    pub fn new() -> GraphArena<N, E> {
+        GraphArena::with_capacity(8, 8)
+    }
+
+    /// Create a new graph with a known initial capacity for nodes and for
+    /// edges.
+    pub fn with_capacity(nodes: usize, edges: usize) -> GraphArena<N, E> {
+        GraphArena {
+            nodes: Arena::with_capacity(nodes),
+            edges: Arena::with_capacity(edges)
+        }
+    }
+
+    /// Add a node with no edges to the graph.
+    pub fn add_node(&self, value: N) -> Rc<Node<N, E>> {
+        self.nodes.alloc(Node {
+            value,
+            outgoing: RefCell::new(vec![]),
+            incoming: RefCell::new(vec![])
+        })
+    }
+
+    /// Add an edge from `source` to `target`, which keeps `target` alive for
+    /// as long as `source` is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either node belongs to another graph.
+    pub fn add_edge(&self, source: &Rc<Node<N, E>>, target: &Rc<Node<N, E>>, value: E) -> Rc<Edge<N, E>> {
+        assert!(self.nodes.owns(source) && self.nodes.owns(target), "nodes must belong to this graph");
+
+        let edge = self.edges.alloc(Edge {
+            value,
+            source: Rc::downgrade(source),
+            target: target.clone()
+        });
+        source.outgoing.borrow_mut().push(edge.clone());
+        target.incoming.borrow_mut().push(Rc::downgrade(&edge));
+
+        edge
+    }
+
+    /// Get the number of nodes in the graph.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Get the number of edges in the graph.
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Iterate over handles to the nodes, in the order they were added.
+    pub fn nodes(&self) -> Iter<Node<N, E>> {
+        self.nodes.iter()
+    }
+
+    /// Iterate over handles to the edges, in the order they were added.
+    pub fn edges(&self) -> Iter<Edge<N, E>> {
+        self.edges.iter()
+    }
+}
+
+impl<N, E> Default for GraphArena<N, E> {
+    fn default() -> GraphArena<N, E> {
+        GraphArena::new()
+    }
+}
+
+impl<N, E> Drop for GraphArena<N, E> {
+    fn drop(&mut self) {
+        // The node arena owns edges which own nodes, so unless the links are
+        // cut neither arena would ever be freed.
+        for node in self.nodes.iter() {
+            let outgoing = std::mem::take(&mut *node.outgoing.borrow_mut());
+            drop(outgoing);
+        }
+    }
+}
+
+/// The nodes reachable from `start` by following outgoing edges, in
+/// depth-first order. Each node is visited once, even if the graph has
+/// cycles.
+pub fn depth_first<N, E>(start: &Rc<Node<N, E>>) -> Vec<Rc<Node<N, E>>> {
+    let mut seen = HashSet::new();
+    let mut visited = vec![];
+    let mut stack = vec![start.clone()];
+
+    while let Some(node) = stack.pop() {
+        if !seen.insert(&*node as *const Node<N, E>) {
+            continue;
+        }
+
+        // Pushed in reverse, so that the first child is visited first.
+        stack.extend(node.children().into_iter().rev());
+        visited.push(node);
+    }
+
+    visited
+}
+
+/// The nodes reachable from `start` by following outgoing edges, in
+/// breadth-first order. Each node is visited once, even if the graph has
+/// cycles.
+pub fn breadth_first<N, E>(start: &Rc<Node<N, E>>) -> Vec<Rc<Node<N, E>>> {
+    let mut seen = HashSet::new();
+    let mut visited = vec![];
+    let mut queue = VecDeque::new();
+
+    seen.insert(&**start as *const Node<N, E>);
+    queue.push_back(start.clone());
+
+    while let Some(node) = queue.pop_front() {
+        for child in node.children() {
+            if seen.insert(&*child as *const Node<N, E>) {
+                queue.push_back(child);
+            }
+        }
+        visited.push(node);
+    }
+
+    visited
+}
+
+#[test]
+fn traverses() {
+    let token = std::rc::Rc::new(());
+    let graph = GraphArena::new();
+    let nodes: Vec<_> = (0..5).map(|i| graph.add_node((i, token.clone()))).collect();
+    for &(source, target) in &[(0, 1), (0, 2), (1, 3), (2, 3), (3, 0), (4, 4)] {
+        graph.add_edge(&nodes[source], &nodes[target], source * 10 + target);
+    }
+    assert_eq!(graph.node_count(), 5);
+    assert_eq!(graph.edge_count(), 6);
+
+    fn values<T>(nodes: Vec<Rc<Node<(usize, T), usize>>>) -> Vec<usize> {
+        nodes.iter().map(|node| node.value.0).collect()
+    }
+    assert_eq!(values(depth_first(&nodes[0])), [0, 1, 3, 2]);
+    assert_eq!(values(breadth_first(&nodes[0])), [0, 1, 2, 3]);
+    assert_eq!(values(depth_first(&nodes[4])), [4]);
+    assert_eq!(values(nodes[3].parents()), [1, 2]);
+
+    let edge = nodes[3].outgoing()[0].clone();
+    assert_eq!(edge.value, 30);
+    assert_eq!(edge.source().unwrap().value.0, 3);
+    assert_eq!(edge.target().value.0, 0);
+    drop(edge);
+
+    // Dropping the graph cuts the cycles, so the nodes are freed along with
+    // the last handles to them.
+    let kept = nodes[1].clone();
+    drop((graph, nodes));
+    assert_eq!(kept.value.0, 1);
+    assert!(kept.children().is_empty());
+    drop(kept);
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
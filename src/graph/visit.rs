@@ -0,0 +1,260 @@
+//! The `petgraph` visitation traits for `GraphArena`, so that `petgraph`'s
+//! algorithms can run over a graph without copying it into a `Graph` first.
+//! Nodes and edges are identified by their position in the order they were
+//! added in, which never changes since the graph can't remove them.
+
+use std::collections::HashSet;
+
+use petgraph::visit;
+use petgraph::Direction;
+
+use graph::{GraphArena, Node};
+use Rc;
+
+/// Identifies a node of a `GraphArena` for `petgraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeIndex(usize);
+
+impl NodeIndex {
+    /// The position of the node in the order nodes were added in.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Identifies an edge of a `GraphArena` for `petgraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EdgeIndex(usize);
+
+impl EdgeIndex {
+    /// The position of the edge in the order edges were added in.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// An edge of a `GraphArena` as `petgraph` sees it.
+#[derive(Debug)]
+pub struct EdgeReference<'a, E: 'a> {
+    source: NodeIndex,
+    target: NodeIndex,
+    id: EdgeIndex,
+    weight: &'a E
+}
+
+impl<'a, E> Clone for EdgeReference<'a, E> {
+    fn clone(&self) -> EdgeReference<'a, E> {
+        *self
+    }
+}
+
+impl<'a, E> Copy for EdgeReference<'a, E> {}
+
+impl<'a, E> visit::EdgeRef for EdgeReference<'a, E> {
+    type NodeId = NodeIndex;
+    type EdgeId = EdgeIndex;
+    type Weight = E;
+
+    fn source(&self) -> NodeIndex {
+        self.source
+    }
+
+    fn target(&self) -> NodeIndex {
+        self.target
+    }
+
+    fn weight(&self) -> &E {
+        self.weight
+    }
+
+    fn id(&self) -> EdgeIndex {
+        self.id
+    }
+}
+
+impl<N, E> GraphArena<N, E> {
+    /// The `petgraph` identifier of a node of this graph.
+    ///
+    /// ```rust
+    /// extern crate petgraph;
+    /// extern crate rc_arena;
+    ///
+    /// use petgraph::algo::dijkstra;
+    /// use petgraph::visit::EdgeRef;
+    /// use rc_arena::graph::GraphArena;
+    ///
+    /// fn main() {
+    ///     let graph = GraphArena::new();
+    ///     let a = graph.add_node("a");
+    ///     let b = graph.add_node("b");
+    ///     let c = graph.add_node("c");
+    ///     graph.add_edge(&a, &b, 1);
+    ///     graph.add_edge(&b, &c, 1);
+    ///     graph.add_edge(&a, &c, 5);
+    ///
+    ///     let distances = dijkstra(&graph, graph.node_index(&a), None, |edge| *edge.weight());
+    ///     assert_eq!(distances[&graph.node_index(&c)], 2);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node belongs to another graph.
+    pub fn node_index(&self, node: &Rc<Node<N, E>>) -> NodeIndex {
+        NodeIndex(self.nodes.index_of(node).expect("node must belong to this graph"))
+    }
+
+    /// Get the node identified by `index`.
+    pub fn node(&self, index: NodeIndex) -> Option<Rc<Node<N, E>>> {
+        let (ptr, slot) = self.nodes.locate(index.0)?;
+
+        Some(Rc::new_handle(self.nodes.chunks.clone(), ptr, slot))
+    }
+
+    /// The edges leaving a node, as `petgraph` sees them.
+    fn edge_references_from(&self, index: NodeIndex) -> Vec<EdgeReference<'_, E>> {
+        let node = self.node(index).expect("no such node");
+        let outgoing = node.outgoing.borrow();
+
+        outgoing.iter().map(|edge| EdgeReference {
+            source: index,
+            target: self.node_index(&edge.target),
+            id: EdgeIndex(self.edges.index_of(edge).unwrap()),
+            weight: extend(self, &edge.value)
+        }).collect()
+    }
+}
+
+/// Extend the lifetime of a reference into one of the graph's arenas to
+/// that of the graph. Their objects don't move, and since nothing is pooled
+/// they aren't dropped before the arena is.
+fn extend<'a, N, E, T>(_graph: &'a GraphArena<N, E>, object: &T) -> &'a T {
+    unsafe { &*(object as *const T) }
+}
+
+impl<N, E> visit::GraphBase for GraphArena<N, E> {
+    type NodeId = NodeIndex;
+    type EdgeId = EdgeIndex;
+}
+
+impl<N, E> visit::GraphProp for GraphArena<N, E> {
+    type EdgeType = petgraph::Directed;
+}
+
+impl<N, E> visit::Data for GraphArena<N, E> {
+    type NodeWeight = N;
+    type EdgeWeight = E;
+}
+
+impl<N, E> visit::NodeCount for GraphArena<N, E> {
+    fn node_count(&self) -> usize {
+        GraphArena::node_count(self)
+    }
+}
+
+impl<N, E> visit::NodeIndexable for GraphArena<N, E> {
+    fn node_bound(&self) -> usize {
+        GraphArena::node_count(self)
+    }
+
+    fn to_index(&self, a: NodeIndex) -> usize {
+        a.0
+    }
+
+    fn from_index(&self, i: usize) -> NodeIndex {
+        NodeIndex(i)
+    }
+}
+
+impl<N, E> visit::Visitable for GraphArena<N, E> {
+    type Map = HashSet<NodeIndex>;
+
+    fn visit_map(&self) -> HashSet<NodeIndex> {
+        HashSet::with_capacity(GraphArena::node_count(self))
+    }
+
+    fn reset_map(&self, map: &mut HashSet<NodeIndex>) {
+        map.clear();
+    }
+}
+
+impl<N, E> visit::IntoNodeIdentifiers for &GraphArena<N, E> {
+    type NodeIdentifiers = std::iter::Map<std::ops::Range<usize>, fn(usize) -> NodeIndex>;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        (0..GraphArena::node_count(self)).map(NodeIndex)
+    }
+}
+
+impl<N, E> visit::IntoNeighbors for &GraphArena<N, E> {
+    type Neighbors = std::vec::IntoIter<NodeIndex>;
+
+    fn neighbors(self, a: NodeIndex) -> std::vec::IntoIter<NodeIndex> {
+        visit::IntoNeighborsDirected::neighbors_directed(self, a, Direction::Outgoing)
+    }
+}
+
+impl<N, E> visit::IntoNeighborsDirected for &GraphArena<N, E> {
+    type NeighborsDirected = std::vec::IntoIter<NodeIndex>;
+
+    fn neighbors_directed(self, a: NodeIndex, d: Direction) -> std::vec::IntoIter<NodeIndex> {
+        let node = self.node(a).expect("no such node");
+        let nodes = match d {
+            Direction::Outgoing => node.children(),
+            Direction::Incoming => node.parents()
+        };
+
+        nodes.iter().map(|node| self.node_index(node)).collect::<Vec<_>>().into_iter()
+    }
+}
+
+impl<'a, N, E> visit::IntoEdgeReferences for &'a GraphArena<N, E> {
+    type EdgeRef = EdgeReference<'a, E>;
+    type EdgeReferences = std::vec::IntoIter<EdgeReference<'a, E>>;
+
+    fn edge_references(self) -> std::vec::IntoIter<EdgeReference<'a, E>> {
+        (0..GraphArena::node_count(self)).flat_map(|i| self.edge_references_from(NodeIndex(i)))
+                                         .collect::<Vec<_>>()
+                                         .into_iter()
+    }
+}
+
+impl<'a, N, E> visit::IntoEdges for &'a GraphArena<N, E> {
+    type Edges = std::vec::IntoIter<EdgeReference<'a, E>>;
+
+    fn edges(self, a: NodeIndex) -> std::vec::IntoIter<EdgeReference<'a, E>> {
+        self.edge_references_from(a).into_iter()
+    }
+}
+
+#[test]
+fn runs_algorithms() {
+    use petgraph::algo::{has_path_connecting, toposort};
+    use petgraph::visit::{Dfs, Reversed};
+
+    let graph = GraphArena::new();
+    let nodes: Vec<_> = (0..5).map(|i| graph.add_node(i)).collect();
+    for &(source, target) in &[(0, 1), (0, 2), (1, 3), (2, 3)] {
+        graph.add_edge(&nodes[source], &nodes[target], ());
+    }
+    let id = |i: usize| graph.node_index(&nodes[i]);
+
+    let order: Vec<_> = toposort(&graph, None).unwrap().iter().map(|&id| graph.node(id).unwrap().value).collect();
+    assert_eq!(order.len(), 5);
+    assert!(order.iter().position(|&i| i == 0) < order.iter().position(|&i| i == 3));
+
+    let mut dfs = Dfs::new(Reversed(&graph), id(3));
+    let mut ancestors = vec![];
+    while let Some(node) = dfs.next(Reversed(&graph)) {
+        ancestors.push(node.index());
+    }
+    ancestors.sort();
+    assert_eq!(ancestors, [0, 1, 2, 3]);
+
+    assert!(has_path_connecting(&graph, id(0), id(3), None));
+    assert!(!has_path_connecting(&graph, id(3), id(0), None));
+    assert!(!has_path_connecting(&graph, id(0), id(4), None));
+
+    graph.add_edge(&nodes[3], &nodes[0], ());
+    assert!(toposort(&graph, None).is_err());
+}
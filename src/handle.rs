@@ -0,0 +1,475 @@
+//! Plain handles to objects which are copied without touching any reference
+//! counts, and resolved through the arena they belong to.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {table, Arena, ArenaError, Persist, Rc, Slot};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A number identifying a new arena, which handles remember so that they
+/// aren't resolved through the wrong one.
+pub fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A handle to an object in an arena which is only the object's position,
+/// so copying it is free. It has to be resolved through the arena to get at
+/// the object, with `Arena::get` or `Arena::resolve`.
+///
+/// An object which has been given a `Handle` is never dropped before its
+/// arena is, as though it were allocated with `Arena::alloc_ref`. Rolling
+/// back or truncating the arena past it fails with `LiveHandles`, so a
+/// handle never ends up referring to another object in its place.
+pub struct Handle<T> {
+    arena: usize,
+    index: usize,
+    _marker: PhantomData<fn() -> T>
+}
+
+impl<T> Handle<T> {
+    /// The position of the object in allocation order.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        self.arena == other.arena && self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.arena.hash(state);
+        self.index.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+// How an `Rc32` packs a position into 32 bits: the chunk index goes in the
+// upper bits, and the position within the chunk in the rest.
+const OFFSET_BITS: u32 = 27;
+const MAX_CHUNKS: usize = 1 << (32 - OFFSET_BITS);
+
+/// A handle to an object in an arena which is packed into 32 bits, as the
+/// index of the object's chunk and its position within it. Like a `Handle`,
+/// it keeps its object alive until the arena is dropped, and is resolved
+/// through the arena with `Arena::resolve32`, which doesn't have to search
+/// through the chunks.
+///
+/// There's no room to remember which arena an `Rc32` belongs to, so
+/// resolving it through another arena gives an unrelated object, or panics.
+pub struct Rc32<T> {
+    packed: u32,
+    _marker: PhantomData<fn() -> T>
+}
+
+impl<T> Rc32<T> {
+    fn position(&self) -> (usize, usize) {
+        ((self.packed >> OFFSET_BITS) as usize, (self.packed & ((1 << OFFSET_BITS) - 1)) as usize)
+    }
+}
+
+impl<T> Clone for Rc32<T> {
+    fn clone(&self) -> Rc32<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Rc32<T> {}
+
+impl<T> PartialEq for Rc32<T> {
+    fn eq(&self, other: &Rc32<T>) -> bool {
+        self.packed == other.packed
+    }
+}
+
+impl<T> Eq for Rc32<T> {}
+
+impl<T> std::hash::Hash for Rc32<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.packed.hash(state)
+    }
+}
+
+impl<T> std::fmt::Debug for Rc32<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let (chunk, offset) = self.position();
+        write!(f, "Rc32({}, {})", chunk, offset)
+    }
+}
+
+/// The position of an object in an arena, for objects to refer to each other
+/// by. Unlike an `Rc`, it's independent of where the arena's chunks live, so
+/// it still refers to the same object once the arena has been saved and
+/// loaded again with `serialize_into`, or with an mmap file.
+///
+/// It doesn't keep the object alive, and is resolved through the arena with
+/// `Arena::at`.
+///
+/// ```rust
+/// use rc_arena::{Arena, Offset};
+///
+/// #[derive(Clone, Copy)]
+/// struct Node {
+///     value: u32,
+///     next: Option<Offset<Node>>
+/// }
+///
+/// let arena = Arena::new();
+/// let last = arena.alloc(Node { value: 2, next: None });
+/// let first = arena.alloc(Node { value: 1, next: arena.offset_of(&last) });
+///
+/// let next = arena.at(first.next.unwrap()).unwrap();
+/// assert_eq!(next.value, 2);
+/// ```
+#[repr(transparent)]
+pub struct Offset<T> {
+    index: u64,
+    _marker: PhantomData<fn() -> T>
+}
+
+impl<T> Offset<T> {
+    /// The position of the object in allocation order.
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+}
+
+impl<T> Clone for Offset<T> {
+    fn clone(&self) -> Offset<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Offset<T> {}
+
+impl<T> PartialEq for Offset<T> {
+    fn eq(&self, other: &Offset<T>) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Offset<T> {}
+
+impl<T> std::hash::Hash for Offset<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> std::fmt::Debug for Offset<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Offset({})", self.index)
+    }
+}
+
+impl<T> Persist for Offset<T> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.index.write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Offset<T>> {
+        Ok(Offset {
+            index: u64::read_from(reader)?,
+            _marker: PhantomData
+        })
+    }
+}
+
+impl<T> Arena<T> {
+    /// Get the `Offset` of the object `rc` points at, or `None` if it belongs
+    /// to another arena.
+    pub fn offset_of(&self, rc: &Rc<T>) -> Option<Offset<T>> {
+        self.index_of(rc).map(|index| Offset {
+            index: index as u64,
+            _marker: PhantomData
+        })
+    }
+
+    /// Get an `Rc` to the object at an `Offset`, or `None` if there's no
+    /// object there, which can happen if it has been rolled back. Once other
+    /// objects have been allocated in its place, the offset refers to one of
+    /// them instead, so offsets into a part of the arena which may be rolled
+    /// back shouldn't be kept past the rollback. Offsets don't remember
+    /// which arena they came from either, so one resolved through another
+    /// arena, such as the empty one a `declare_arenas!` type's `clear` puts
+    /// in its place, finds whatever object is at its position there.
+    pub fn at(&self, offset: Offset<T>) -> Option<Rc<T>> {
+        let (ptr, slot) = self.locate(usize::try_from(offset.index).ok()?)?;
+
+        Some(Rc::new_handle(self.chunks.clone(), ptr, slot))
+    }
+
+    /// Get an `Rc32` to the object `rc` points at, which keeps the object
+    /// alive until the arena is dropped.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Rc32};
+    ///
+    /// let arena = Arena::new();
+    /// let adjacency: Vec<Vec<Rc32<&str>>> = vec![
+    ///     vec![arena.rc32(&arena.alloc("a")), arena.rc32(&arena.alloc("b"))]
+    /// ];
+    ///
+    /// assert_eq!(std::mem::size_of::<Rc32<&str>>(), 4);
+    /// assert_eq!(*arena.resolve32(adjacency[0][1]), "b");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rc` belongs to another arena, or if the arena has grown
+    /// too large for its positions to fit in 32 bits.
+    pub fn rc32(&self, rc: &Rc<T>) -> Rc32<T> {
+        assert!(self.owns(rc), "handle belongs to another arena");
+
+        let position = self.chunks.borrow().list.iter().enumerate().filter_map(|(chunk_index, chunk)| {
+            chunk.index_of(rc.ptr(), rc.slot).map(|offset| (chunk_index, offset))
+        }).next();
+        let (chunk, offset) = position.expect("handle belongs to another arena");
+        assert!(chunk < MAX_CHUNKS && offset < 1 << OFFSET_BITS, "arena is too large for Rc32");
+        rc.pin();
+
+        Rc32 {
+            packed: ((chunk as u32) << OFFSET_BITS) | offset as u32,
+            _marker: PhantomData
+        }
+    }
+
+    /// Get an `Rc` to the object an `Rc32` refers to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no object at the `Rc32`'s position, which can only
+    /// happen if it belongs to another arena.
+    pub fn resolve32(&self, rc: Rc32<T>) -> Rc<T> {
+        let (chunk, offset) = rc.position();
+        let (ptr, slot) = {
+            let chunks = self.chunks.borrow();
+
+            match chunks.list.get(chunk) {
+                Some(chunk) if offset < chunk.items.len() => chunk.handle(offset),
+                _ => panic!("Rc32 belongs to another arena")
+            }
+        };
+
+        Rc::new_handle(self.chunks.clone(), ptr, slot)
+    }
+
+    /// Get a `Handle` to the object `rc` points at, which keeps the object
+    /// alive until the arena is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rc` belongs to another arena.
+    pub fn handle(&self, rc: &Rc<T>) -> Handle<T> {
+        let index = self.index_of(rc).expect("handle belongs to another arena");
+        // The object counts as referenced from now on, so that `get` can
+        // hand out plain references to it.
+        rc.pin();
+
+        Handle {
+            arena: self.chunks.borrow().id,
+            index,
+            _marker: PhantomData
+        }
+    }
+
+    /// Store an object in the arena, returning a `Handle` to it.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let handles: Vec<_> = (0..100).map(|i| arena.alloc_handle(i)).collect();
+    ///
+    /// let sum: usize = handles.iter().map(|&handle| *arena.get(handle)).sum();
+    /// assert_eq!(sum, 4950);
+    /// assert_eq!(*arena.resolve(handles[7]), 7);
+    /// ```
+    pub fn alloc_handle(&self, value: T) -> Handle<T> {
+        self.handle(&self.alloc(value))
+    }
+
+    /// The pointer to the object a handle refers to and its slot.
+    fn find(&self, handle: Handle<T>) -> Result<(*mut T, *const Slot), ArenaError> {
+        if handle.arena != self.chunks.borrow().id {
+            return Err(ArenaError::StaleHandle);
+        }
+
+        // Objects with handles can't be rolled back.
+        Ok(self.locate(handle.index).unwrap())
+    }
+
+    /// Get an `Rc` to the object a handle refers to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle belongs to another arena.
+    pub fn resolve(&self, handle: Handle<T>) -> Rc<T> {
+        self.try_resolve(handle).expect("handle belongs to another arena")
+    }
+
+    /// Like `resolve`, but fails with `StaleHandle` rather than panicking if
+    /// the handle belongs to another arena.
+    pub fn try_resolve(&self, handle: Handle<T>) -> Result<Rc<T>, ArenaError> {
+        let (ptr, slot) = self.find(handle)?;
+
+        Ok(Rc::new_handle(self.chunks.clone(), ptr, slot))
+    }
+
+    /// Get a reference to the object a handle refers to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle belongs to another arena.
+    pub fn get(&self, handle: Handle<T>) -> &T {
+        self.try_get(handle).expect("handle belongs to another arena")
+    }
+
+    /// Like `get`, but fails with `StaleHandle` rather than panicking if the
+    /// handle belongs to another arena.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, ArenaError};
+    ///
+    /// let arena = Arena::new();
+    /// let handle = arena.alloc_handle(1);
+    ///
+    /// assert_eq!(arena.try_get(handle), Ok(&1));
+    /// assert_eq!(Arena::new().try_get(handle), Err(ArenaError::StaleHandle));
+    /// ```
+    pub fn try_get(&self, handle: Handle<T>) -> Result<&T, ArenaError> {
+        let (ptr, slot) = self.find(handle)?;
+
+        // The object can't be dropped while we hold the arena, since its
+        // strong count never returns to zero.
+        Ok(unsafe { &*table::resolve(ptr, slot) })
+    }
+}
+
+#[test]
+fn resolves() {
+    let arena = Arena::with_capacity(1);
+    let checkpoint = arena.checkpoint();
+    let handles: Vec<_> = (0..10).map(|i| arena.alloc_handle(i)).collect();
+
+    // The objects are kept alive by their handles alone.
+    assert!(arena.rollback(checkpoint).is_err());
+    assert_eq!(arena.truncate(5), Err(ArenaError::LiveHandles));
+    assert_eq!(*arena.get(handles[7]), 7);
+    let copied = handles[3];
+    assert_eq!(copied, handles[3]);
+    assert_eq!(copied.index(), 3);
+    assert_eq!(*arena.get(copied), 3);
+
+    let rc = arena.resolve(handles[9]);
+    assert_eq!(*rc, 9);
+    assert_eq!(arena.handle(&rc), handles[9]);
+    assert_eq!(arena.clone().get(handles[5]), &5);
+
+    let other = Arena::new();
+    other.alloc(0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *other.get(handles[0])));
+    assert!(result.is_err());
+    assert_eq!(other.try_resolve(handles[0]).err(), Some(ArenaError::StaleHandle));
+
+    // Handles don't keep the arena alive.
+    let token = std::rc::Rc::new(());
+    let arena = Arena::new();
+    arena.alloc_handle(token.clone());
+    arena.alloc(token.clone());
+    arena.assert_invariants();
+    drop(arena);
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
+
+#[test]
+fn relocates() {
+    struct Node {
+        value: u32,
+        next: Option<Offset<Node>>
+    }
+
+    impl Persist for Node {
+        fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            self.value.write_to(writer)?;
+            self.next.write_to(writer)
+        }
+
+        fn read_from<R: Read>(reader: &mut R) -> io::Result<Node> {
+            Ok(Node {
+                value: u32::read_from(reader)?,
+                next: Option::read_from(reader)?
+            })
+        }
+    }
+
+    let arena = Arena::with_capacity(1);
+    let mut next = None;
+    for value in 0..10 {
+        next = arena.offset_of(&arena.alloc(Node { value, next }));
+    }
+
+    let mut bytes = vec![];
+    arena.serialize_into(&mut bytes).unwrap();
+    let loaded: Arena<Node> = Arena::deserialize_from(&bytes[..]).unwrap();
+
+    let mut values = vec![];
+    while let Some(offset) = next {
+        let node = loaded.at(offset).unwrap();
+        values.push(node.value);
+        next = node.next;
+    }
+    assert_eq!(values, [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+
+    let checkpoint = loaded.checkpoint();
+    let last = loaded.offset_of(&loaded.alloc(Node { value: 10, next: None })).unwrap();
+    assert_eq!(last.index(), 10);
+    loaded.rollback(checkpoint).unwrap();
+    assert!(loaded.at(last).is_none());
+}
+
+#[test]
+fn packs() {
+    let arena = Arena::with_capacity(2);
+    let handles: Vec<_> = (0..40).map(|i| arena.alloc(i)).map(|rc| arena.rc32(&rc)).collect();
+
+    assert_eq!(format!("{:?}", handles[0]), "Rc32(0, 0)");
+    assert_eq!(format!("{:?}", handles[39]), "Rc32(4, 9)");
+    for (i, &rc) in handles.iter().enumerate() {
+        assert_eq!(*arena.resolve32(rc), i);
+    }
+    assert_eq!(arena.rc32(&arena.resolve32(handles[20])), handles[20]);
+
+    let result = std::panic::catch_unwind(|| Arena::<usize>::new().resolve32(handles[3]));
+    assert!(result.is_err());
+
+    // An `Rc32` doesn't keep the arena alive.
+    let token = std::rc::Rc::new(());
+    let arena = Arena::new();
+    let packed = arena.rc32(&arena.alloc(token.clone()));
+    arena.assert_invariants();
+    drop((arena, packed));
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
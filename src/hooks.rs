@@ -0,0 +1,143 @@
+//! Callbacks which observe an arena as it grows, for telemetry or to catch
+//! unexpected allocations.
+
+use {Arena, Rc};
+
+type AllocHook<T> = Box<dyn Fn(&T)>;
+
+/// Callbacks run by an arena after it has changed, installed with
+/// `Arena::set_hooks`. The arena isn't borrowed while they run, so they are
+/// free to inspect it.
+pub struct Hooks<T> {
+    alloc: Option<AllocHook<T>>,
+    new_chunk: Option<Box<dyn Fn(usize)>>,
+    out_of_memory: Option<Box<dyn Fn(usize) -> bool>>
+}
+
+impl<T> Hooks<T> {
+    /// Create a set of hooks which don't do anything.
+    pub fn new() -> Hooks<T> {
+        Hooks {
+            alloc: None,
+            new_chunk: None,
+            out_of_memory: None
+        }
+    }
+
+    /// Call `f` with every object stored by `alloc`, `alloc_cyclic` or
+    /// `alloc_ref`.
+    pub fn on_alloc<F: Fn(&T) + 'static>(mut self, f: F) -> Hooks<T> {
+        self.alloc = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the capacity of every chunk created because the arena
+    /// ran out of space.
+    pub fn on_new_chunk<F: Fn(usize) + 'static>(mut self, f: F) -> Hooks<T> {
+        self.new_chunk = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the number of objects `try_alloc` or `try_reserve` was
+    /// making room for when the memory for a new chunk couldn't be
+    /// allocated. If it returns `true`, having freed some memory, the
+    /// allocation is tried once more before the error is returned.
+    pub fn on_out_of_memory<F: Fn(usize) -> bool + 'static>(mut self, f: F) -> Hooks<T> {
+        self.out_of_memory = Some(Box::new(f));
+        self
+    }
+}
+
+impl<T> Default for Hooks<T> {
+    fn default() -> Hooks<T> {
+        Hooks::new()
+    }
+}
+
+/// Run the hooks for an object which has just been stored, and for a new
+/// chunk with the given capacity.
+pub fn run<T>(hooks: Option<std::rc::Rc<Hooks<T>>>, rc: Option<&Rc<T>>, new_chunk: Option<usize>) {
+    let hooks = match hooks {
+        Some(hooks) => hooks,
+        None => return
+    };
+
+    if let (Some(capacity), Some(ref f)) = (new_chunk, hooks.new_chunk.as_ref()) {
+        f(capacity);
+    }
+    if let (Some(rc), Some(ref f)) = (rc, hooks.alloc.as_ref()) {
+        f(rc);
+    }
+}
+
+/// Run the out-of-memory hook for room for `additional` objects, returning
+/// whether the allocation should be tried again.
+pub fn out_of_memory<T>(hooks: Option<std::rc::Rc<Hooks<T>>>, additional: usize) -> bool {
+    match hooks.as_ref().and_then(|hooks| hooks.out_of_memory.as_ref()) {
+        Some(f) => f(additional),
+        None => false
+    }
+}
+
+impl<T> Arena<T> {
+    /// Install hooks which observe the arena from now on, replacing any which
+    /// were installed before.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use rc_arena::{Arena, Hooks};
+    ///
+    /// let chunks = Rc::new(Cell::new(0));
+    /// let counter = chunks.clone();
+    ///
+    /// let arena = Arena::with_capacity(4);
+    /// arena.set_hooks(Hooks::new().on_new_chunk(move |_| counter.set(counter.get() + 1)));
+    ///
+    /// for i in 0..100 {
+    ///     arena.alloc(i);
+    /// }
+    /// assert_eq!(chunks.get(), 4);
+    /// ```
+    pub fn set_hooks(&self, hooks: Hooks<T>) {
+        self.chunks.borrow_mut().hooks = Some(std::rc::Rc::new(hooks));
+    }
+}
+
+#[test]
+fn observes() {
+    use std::cell::RefCell;
+
+    let events = std::rc::Rc::new(RefCell::new(vec![]));
+    let arena = Arena::with_capacity(1);
+
+    let (allocs, chunks) = (events.clone(), events.clone());
+    let observed = arena.clone();
+    arena.set_hooks(Hooks::new().on_alloc(move |value: &usize| {
+        allocs.borrow_mut().push(("alloc", *value, observed.len()));
+    }).on_new_chunk(move |capacity| {
+        chunks.borrow_mut().push(("chunk", capacity, 0));
+    }));
+
+    arena.alloc(1);
+    arena.alloc_cyclic(|_| 2);
+    arena.alloc_ref(3);
+
+    assert_eq!(*events.borrow(), [("alloc", 1, 1),
+                                  ("chunk", 2, 0),
+                                  ("alloc", 2, 2),
+                                  ("alloc", 3, 3)]);
+
+    // The hooks hold the arena, so they have to be removed to free it.
+    arena.set_hooks(Hooks::new());
+
+    let retries = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = retries.clone();
+    arena.set_hooks(Hooks::new().on_out_of_memory(move |additional| {
+        counter.set(counter.get() + additional);
+        true
+    }));
+    assert_eq!(arena.try_reserve(usize::MAX / 2), Err(::ArenaError::AllocationFailed));
+    assert_eq!(retries.get(), usize::MAX / 2);
+    assert!(arena.try_reserve(16).is_ok());
+}
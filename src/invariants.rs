@@ -0,0 +1,123 @@
+//! A check of the internal state of an arena, for fuzz targets and debug
+//! builds to run after every operation.
+
+use {Arena, Slots};
+
+impl<T> Arena<T> {
+    /// Check that the internal state of the arena is consistent, panicking
+    /// with a description of the first problem found otherwise.
+    ///
+    /// Each chunk must hold no more objects than it has room for, with a
+    /// slot for each of them, and nothing may hold a strong reference to an
+    /// object which hasn't been stored yet. The strong and weak counts of
+    /// all objects mustn't add up to more than the handles which hold the
+    /// arena, and the free lists of a pool or an indirect arena must only
+    /// hold objects which nothing else refers to. This visits every object,
+    /// so it's slow for large arenas.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::pool_with_capacity(2, |_: &mut usize| {});
+    /// let mut kept = vec![];
+    /// for i in 0..10 {
+    ///     let handle = arena.alloc(i);
+    ///     if i % 2 == 0 {
+    ///         kept.push(handle);
+    ///     }
+    ///     arena.assert_invariants();
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is inconsistent, or if it's mutably borrowed, as
+    /// it is while a hook runs.
+    pub fn assert_invariants(&self) {
+        let chunks = self.chunks.borrow();
+        assert!(!chunks.list.is_empty(), "arena has no chunks");
+
+        let (mut strong, mut weak) = (0, 0);
+        for (index, chunk) in chunks.list.iter().enumerate() {
+            let len = chunk.items.len();
+            assert!(len <= chunk.capacity(),
+                    "chunk {} holds {} objects but only has room for {}", index, len, chunk.capacity());
+
+            // An object being constructed by `alloc_cyclic` gets its slot
+            // first, which is kept if a `Weak` to it escapes a panic.
+            let slots = chunk.slots.len();
+            assert!(slots == len || (chunk.reserved && slots == len + 1),
+                    "chunk {} has {} slots for {} objects", index, slots, len);
+            assert!(chunks.table.is_some() == matches!(chunk.slots, Slots::Table(_)),
+                    "chunk {} doesn't find its objects the way the arena does", index);
+
+            for (position, slot) in chunk.slots.iter_from(0).enumerate() {
+                assert!(position < len || slot.strong.get() == 0,
+                        "object {} of chunk {} is referenced before it's stored", position, index);
+                strong += slot.strong.get();
+                weak += slot.weak.get();
+            }
+
+            #[cfg(feature = "alloc-backtraces")]
+            assert!(chunk.backtraces.len() <= len, "chunk {} has more backtraces than objects", index);
+        }
+
+        if let Some(ref pool) = chunks.pool {
+            pool.assert_invariants(&chunks.list);
+            strong -= pool.free_slots().len();
+        }
+        if let Some(ref table) = chunks.table {
+            table.assert_invariants(&chunks.list);
+        }
+
+        // Every other strong reference belongs to an `Rc` or a run holding
+        // the arena, besides `self`, or pins an object. Freezing a pool leaves its objects referenced by
+        // nothing, so the count is only known for arenas with no frozen chunks.
+        strong -= chunks.runs.get() + chunks.pinned.get();
+        if chunks.list.iter().all(|chunk| !chunk.items.is_frozen()) {
+            assert!(strong < std::rc::Rc::strong_count(&self.chunks),
+                    "objects have {} strong references, but only {} handles hold the arena",
+                    strong, std::rc::Rc::strong_count(&self.chunks) - 1);
+        }
+        assert!(weak <= std::rc::Rc::weak_count(&self.chunks),
+                "objects have {} weak references, but only {} handles hold the arena",
+                weak, std::rc::Rc::weak_count(&self.chunks));
+    }
+}
+
+#[test]
+fn checks_invariants() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use Rc;
+
+    let pool = Arena::pool_with_capacity(1, |i: &mut usize| *i = 0);
+    let handles: Vec<_> = (0..10).map(|i| pool.alloc(i)).collect();
+    let weak = Rc::downgrade(&handles[3]);
+    let checkpoint = pool.checkpoint();
+    drop(handles);
+    pool.alloc(10);
+    pool.assert_invariants();
+    pool.rollback(checkpoint).unwrap();
+    pool.assert_invariants();
+    drop(weak);
+
+    let indirect = Arena::indirect_with_capacity(2);
+    let kept: Vec<_> = (0..10).map(|i| indirect.alloc(i)).filter(|i| **i % 3 == 0).collect();
+    unsafe { indirect.compact() };
+    indirect.assert_invariants();
+    assert_eq!(kept.len(), 4);
+
+    let arena = Arena::new();
+    let cyclic = arena.alloc_cyclic(|me| {
+        arena.assert_invariants();
+        assert!(me.upgrade().is_none());
+        1
+    });
+    arena.assert_invariants();
+
+    // A strong reference which nothing holds.
+    arena.chunks.borrow().list[0].slots.get(0).strong.set(2);
+    assert!(catch_unwind(AssertUnwindSafe(|| arena.assert_invariants())).is_err());
+    arena.chunks.borrow().list[0].slots.get(0).strong.set(1);
+    drop(cyclic);
+}
@@ -0,0 +1,285 @@
+//! Iteration over the objects of an arena in allocation order.
+
+use std::cell::RefCell;
+use std::iter::FusedIterator;
+
+use {Arena, Checkpoint, Chunks, Rc};
+
+/// An iterator over handles to the objects of an arena, returned by
+/// `Arena::iter`.
+///
+/// Only the objects which were in the arena when the iterator was created
+/// are visited. The arena isn't borrowed in between calls to `next`, so it
+/// may be used while iterating.
+pub struct Iter<T> {
+    chunks: std::rc::Rc<RefCell<Chunks<T>>>,
+    // The (chunk, item) position of the next object from the front, and of
+    // the end of the objects not yet visited from the back.
+    front: (usize, usize),
+    back: (usize, usize),
+    len: usize
+}
+
+impl<T> Iter<T> {
+    /// Create a handle to the object at `position`, or give up on iterating
+    /// if it has been rolled back in the meantime.
+    fn handle(&mut self, position: (usize, usize)) -> Option<Rc<T>> {
+        let (ptr, slot) = {
+            let chunks = self.chunks.borrow();
+
+            match chunks.list.get(position.0) {
+                Some(chunk) if position.1 < chunk.items.len() => chunk.handle(position.1),
+                _ => {
+                    self.len = 0;
+                    return None;
+                }
+            }
+        };
+        self.len -= 1;
+
+        Some(Rc::new_handle(self.chunks.clone(), ptr, slot))
+    }
+}
+
+impl<T> Iterator for Iter<T> {
+    type Item = Rc<T>;
+
+    fn next(&mut self) -> Option<Rc<T>> {
+        if self.len == 0 {
+            return None;
+        }
+
+        {
+            let chunks = self.chunks.borrow();
+
+            while chunks.list.get(self.front.0).is_some_and(|chunk| self.front.1 >= chunk.items.len()) {
+                self.front = (self.front.0 + 1, 0);
+            }
+        }
+
+        let front = self.front;
+        self.front.1 += 1;
+
+        self.handle(front)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<T> {
+    fn next_back(&mut self) -> Option<Rc<T>> {
+        if self.len == 0 {
+            return None;
+        }
+
+        {
+            let chunks = self.chunks.borrow();
+
+            while self.back.1 == 0 && self.back.0 > 0 {
+                self.back.0 -= 1;
+                self.back.1 = chunks.list.get(self.back.0).map_or(0, |chunk| chunk.items.len());
+            }
+        }
+
+        if self.back.1 == 0 {
+            self.len = 0;
+            return None;
+        }
+        self.back.1 -= 1;
+
+        let back = self.back;
+        self.handle(back)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<T> {}
+
+impl<T> FusedIterator for Iter<T> {}
+
+impl<T> Arena<T> {
+    /// Iterate over handles to the objects in the arena, in the order that
+    /// they were allocated.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(1);
+    /// arena.alloc(2);
+    /// arena.alloc(3);
+    ///
+    /// let newest_first: Vec<usize> = arena.iter().rev().map(|n| *n).collect();
+    /// assert_eq!(newest_first, [3, 2, 1]);
+    /// assert_eq!(arena.iter().len(), 3);
+    /// ```
+    pub fn iter(&self) -> Iter<T> {
+        let chunks = self.chunks.borrow();
+        let last = chunks.list.len() - 1;
+
+        Iter {
+            chunks: self.chunks.clone(),
+            front: (0, 0),
+            back: (last, chunks.list[last].items.len()),
+            len: chunks.list.iter().map(|chunk| chunk.items.len()).sum()
+        }
+    }
+
+    /// Iterate over handles to the objects in the arena along with their
+    /// positions in allocation order, as given by `index_of`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc("a");
+    /// arena.alloc("b");
+    ///
+    /// let indexed: Vec<_> = arena.iter_indexed().map(|(i, s)| (i, *s)).collect();
+    /// assert_eq!(indexed, [(0, "a"), (1, "b")]);
+    /// ```
+    pub fn iter_indexed(&self) -> std::iter::Enumerate<Iter<T>> {
+        self.iter().enumerate()
+    }
+
+    /// Iterate over handles to the objects allocated after `from` was taken
+    /// and before `to` was, in the order they were allocated. Objects aren't
+    /// dropped one by one, so this is everything that's new since `from`;
+    /// in a pool, `each_live` tells which objects are still in use.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc("old");
+    ///
+    /// let last_pass = arena.checkpoint();
+    /// arena.alloc("new");
+    /// arena.alloc("newer");
+    /// let this_pass = arena.checkpoint();
+    /// arena.alloc("next");
+    ///
+    /// let new: Vec<_> = arena.between(last_pass, this_pass).map(|s| *s).collect();
+    /// assert_eq!(new, ["new", "newer"]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `to` was taken before `from`, or if the arena has been
+    /// rolled back past `to`.
+    pub fn between(&self, from: Checkpoint, to: Checkpoint) -> Iter<T> {
+        let chunks = self.chunks.borrow();
+
+        let position = |checkpoint: Checkpoint| {
+            assert!(checkpoint.chunk < chunks.list.len() &&
+                    checkpoint.len <= chunks.list[checkpoint.chunk].items.len(),
+                    "checkpoint is past the end of the arena");
+
+            chunks.list[..checkpoint.chunk].iter().map(|chunk| chunk.items.len()).sum::<usize>() + checkpoint.len
+        };
+        let (start, end) = (position(from), position(to));
+        assert!(start <= end, "checkpoints are out of order");
+
+        Iter {
+            chunks: self.chunks.clone(),
+            front: (from.chunk, from.len),
+            back: (to.chunk, to.len),
+            len: end - start
+        }
+    }
+
+    /// Iterate over handles to the objects allocated since `checkpoint` was
+    /// taken, like `between` does up to a checkpoint taken now. A loop which
+    /// takes a new checkpoint each round only visits the new objects.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let mut processed = arena.checkpoint();
+    /// arena.alloc(1);
+    ///
+    /// while arena.len() < 8 {
+    ///     let round = arena.checkpoint();
+    ///     for n in arena.iter_since(processed) {
+    ///         arena.alloc(*n * 2);
+    ///     }
+    ///     processed = round;
+    /// }
+    ///
+    /// assert_eq!(arena.iter().map(|n| *n).collect::<Vec<_>>(), [1, 2, 4, 8, 16, 32, 64, 128]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has been rolled back past `checkpoint`.
+    pub fn iter_since(&self, checkpoint: Checkpoint) -> Iter<T> {
+        self.between(checkpoint, self.checkpoint())
+    }
+}
+
+impl<T> IntoIterator for &Arena<T> {
+    type Item = Rc<T>;
+    type IntoIter = Iter<T>;
+
+    fn into_iter(self) -> Iter<T> {
+        self.iter()
+    }
+}
+
+#[test]
+fn double_ended() {
+    let arena = Arena::with_capacity(1);
+    for i in 0..10 {
+        arena.alloc(i);
+    }
+
+    let mut iter = arena.iter();
+    assert_eq!(iter.len(), 10);
+    assert_eq!(*iter.next().unwrap(), 0);
+    assert_eq!(*iter.next_back().unwrap(), 9);
+
+    // Objects allocated while iterating aren't visited.
+    arena.alloc(10);
+    assert_eq!(iter.len(), 8);
+
+    let mut rest = vec![];
+    while let Some(first) = iter.next() {
+        rest.push(*first);
+        if let Some(last) = iter.next_back() {
+            rest.push(*last);
+        }
+    }
+    assert_eq!(rest, [1, 8, 2, 7, 3, 6, 4, 5]);
+    assert_eq!(iter.len(), 0);
+
+    let empty: Arena<usize> = Arena::new();
+    assert!(empty.iter().next_back().is_none());
+    assert_eq!((&arena).into_iter().rev().count(), 11);
+
+    for (i, rc) in arena.iter_indexed().rev() {
+        assert_eq!(arena.index_of(&rc), Some(i));
+    }
+}
+
+#[test]
+fn between() {
+    let arena = Arena::with_capacity(2);
+    let start = arena.checkpoint();
+    arena.alloc(0);
+    let first = arena.checkpoint();
+    for i in 1..6 {
+        arena.alloc(i);
+    }
+    let second = arena.checkpoint();
+
+    let values: Vec<_> = arena.between(first, second).map(|rc| *rc).collect();
+    assert_eq!(values, [1, 2, 3, 4, 5]);
+    let reversed: Vec<_> = arena.between(start, second).rev().map(|rc| *rc).collect();
+    assert_eq!(reversed, [5, 4, 3, 2, 1, 0]);
+    assert_eq!(arena.between(second, second).len(), 0);
+    assert_eq!(arena.iter_since(first).map(|rc| *rc).collect::<Vec<_>>(), values);
+    assert_eq!(arena.iter_since(second).len(), 0);
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.between(second, first))).is_err());
+}
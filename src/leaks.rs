@@ -0,0 +1,188 @@
+//! Reports of the objects in an arena which are still referenced, for finding
+//! out why an arena's memory isn't released. When the last clone of an arena
+//! is dropped while its objects are still referenced, the report is printed
+//! to standard error.
+//!
+//! With the `alloc-backtraces` feature, a backtrace is also captured every
+//! time an object is allocated, and included in the report.
+
+#[cfg(feature = "alloc-backtraces")]
+use std::backtrace::Backtrace;
+use std::fmt;
+
+use Arena;
+#[cfg(feature = "alloc-backtraces")]
+use Rc;
+
+/// An object which is still referenced, as listed by `Arena::report_live`.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "alloc-backtraces"), derive(Copy, PartialEq, Eq))]
+pub struct LiveObject {
+    /// The position of the object in allocation order.
+    pub index: usize,
+    /// The number of `Rc`s pointing at the object.
+    pub strong: usize,
+    /// The number of `Weak`s pointing at the object.
+    pub weak: usize,
+    /// Where the object was allocated, unless it was loaded or adopted from
+    /// elsewhere.
+    #[cfg(feature = "alloc-backtraces")]
+    pub backtrace: Option<std::rc::Rc<Backtrace>>
+}
+
+/// The objects of an arena which are still referenced.
+#[derive(Clone, Debug)]
+pub struct LeakReport {
+    /// The type of the arena's objects.
+    pub type_name: &'static str,
+    /// The size of each object in bytes.
+    pub size: usize,
+    pub live: Vec<LiveObject>
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{} objects of type `{}` ({} bytes each) are still referenced",
+               self.live.len(), self.type_name, self.size)?;
+
+        for object in &self.live {
+            write!(f, "\n  #{}: {} strong, {} weak", object.index, object.strong, object.weak)?;
+
+            #[cfg(feature = "alloc-backtraces")]
+            {
+                if let Some(ref backtrace) = object.backtrace {
+                    write!(f, ", allocated at:\n{}", backtrace)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Arena<T> {
+    /// List the objects in the arena which are still referenced by an `Rc`
+    /// or a `Weak`. Objects kept for reuse by an object pool aren't listed.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(1);
+    /// let leaked = arena.alloc(2);
+    ///
+    /// let report = arena.report_live();
+    /// assert_eq!(report.live.len(), 1);
+    /// assert_eq!(report.live[0].index, 1);
+    /// ```
+    pub fn report_live(&self) -> LeakReport {
+        let chunks = self.chunks.borrow();
+        let pooled = chunks.pool.as_ref().map(|pool| pool.free_slots()).unwrap_or_default();
+
+        let mut live = vec![];
+        let mut index = 0;
+
+        for chunk in chunks.list.iter() {
+            #[cfg_attr(not(feature = "alloc-backtraces"), allow(unused_variables))]
+            for (position, slot) in chunk.slots.iter_from(0).take(chunk.items.len()).enumerate() {
+                let strong = if pooled.contains(&(slot as *const _)) {
+                    slot.strong.get() - 1
+                } else {
+                    slot.strong.get()
+                };
+
+                if strong != 0 || slot.weak.get() != 0 {
+                    live.push(LiveObject {
+                        index,
+                        strong,
+                        weak: slot.weak.get(),
+                        #[cfg(feature = "alloc-backtraces")]
+                        backtrace: chunk.backtraces.get(position).cloned().and_then(|backtrace| backtrace)
+                    });
+                }
+                index += 1;
+            }
+        }
+
+        LeakReport {
+            type_name: std::any::type_name::<T>(),
+            size: std::mem::size_of::<T>(),
+            live
+        }
+    }
+}
+
+/// Drop an arena without reporting the objects which are still referenced.
+pub fn forget<T>(arena: Arena<T>) {
+    let arena = std::mem::ManuallyDrop::new(arena);
+
+    unsafe {
+        drop(std::ptr::read(&arena.chunks));
+        drop(std::ptr::read(&arena.clones));
+    }
+}
+
+/// Record where the object `rc` points at was allocated.
+#[cfg(feature = "alloc-backtraces")]
+pub fn record<T>(rc: &Rc<T>) {
+    let backtrace = std::rc::Rc::new(Backtrace::force_capture());
+    let mut chunks = rc.chunks.borrow_mut();
+
+    for chunk in chunks.list.iter_mut() {
+        if let Some(index) = chunk.index_of(rc.ptr(), rc.slot) {
+            if chunk.backtraces.len() <= index {
+                chunk.backtraces.resize(index + 1, None);
+            }
+            chunk.backtraces[index] = Some(backtrace);
+
+            return;
+        }
+    }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        // Only the last clone reports, and only if the objects will outlive
+        // it. The arena may be borrowed if it's dropped by an object's
+        // destructor, in which case there's nothing to say.
+        if std::rc::Rc::strong_count(&self.clones) != 1 || self.chunks.try_borrow().is_err() {
+            return;
+        }
+
+        let report = self.report_live();
+        if !report.live.is_empty() {
+            eprintln!("{}", report);
+        }
+    }
+}
+
+#[test]
+fn reports() {
+    use Rc;
+
+    let arena = Arena::pool(|_: &mut usize| {});
+    let a = arena.alloc(0);
+    let b = arena.alloc(1);
+    let weak = Rc::downgrade(&b);
+    drop(b);
+    drop(arena.alloc(2));
+
+    let report = arena.report_live();
+    let live: Vec<_> = report.live.iter().map(|object| (object.index, object.strong, object.weak)).collect();
+    assert_eq!(live, [(0, 1, 0), (1, 0, 1)]);
+    #[cfg(not(feature = "alloc-backtraces"))]
+    assert_eq!(format!("{}", report).lines().count(), 3);
+    #[cfg(feature = "alloc-backtraces")]
+    {
+        assert!(report.live.iter().all(|object| object.backtrace.is_some()));
+        assert!(format!("{}", report).contains("reports"));
+
+        let adopted = Arena::from_vec(vec![0]);
+        let held = adopted.iter().next().unwrap();
+        assert!(adopted.report_live().live[0].backtrace.is_none());
+        drop(held);
+    }
+
+    drop((a, weak));
+    assert!(arena.report_live().live.is_empty());
+}
@@ -19,22 +19,940 @@
 //! assert_eq!(*baz, 1);
 //! ```
 
-use std::cell::RefCell;
-use std::ops::Deref;
+#[cfg(feature = "allocator-api2")]
+extern crate allocator_api2;
+#[cfg(feature = "bumpalo")]
+extern crate bumpalo;
+#[cfg(feature = "bytemuck")]
+extern crate bytemuck;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "futures")]
+extern crate futures_core;
+#[cfg(all(feature = "mlock", unix))]
+extern crate libc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "petgraph")]
+extern crate petgraph;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "derive")]
+extern crate rc_arena_derive;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "stable_deref_trait")]
+extern crate stable_deref_trait;
+#[cfg(feature = "subtle")]
+extern crate subtle;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(all(feature = "mlock", windows))]
+extern crate windows_sys;
+#[cfg(feature = "zeroize")]
+extern crate zeroize;
+
+use std::cell::{Cell, RefCell};
+use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+#[cfg(feature = "allocator-api2")]
+mod allocator;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+#[cfg(feature = "rkyv")]
+mod archive;
+mod buffer;
+mod builder;
+mod cache;
+mod cell;
+mod concurrent;
+#[cfg(feature = "subtle")]
+mod ct;
+mod cursor;
+mod cycles;
+#[cfg(feature = "serde")]
+mod de;
+mod emplace;
+#[cfg(feature = "event-log")]
+mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod family;
+mod fixed;
+mod frozen;
+#[cfg(feature = "graph")]
+pub mod graph;
+mod handle;
+mod hooks;
+mod invariants;
+mod iter;
+mod list;
+#[cfg(feature = "debug-leaks")]
+mod leaks;
+mod limit;
+#[cfg(feature = "mlock")]
+mod locked;
+mod meta;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod persist;
+mod persistent;
+#[cfg(feature = "poison")]
+mod poison;
+#[cfg(feature = "bytemuck")]
+mod pod;
+mod pool;
+mod raw;
+mod report;
+mod sealed;
+mod secondary;
+mod set;
+mod sharded;
+mod slice;
+mod storage;
+#[cfg(feature = "futures")]
+mod stream;
+mod table;
+mod teardown;
+#[cfg(feature = "zeroize")]
+mod wipe;
+
+pub use builder::ArenaBuilder;
+pub use cache::ArenaCache;
+pub use cell::{ArenaCell, MutArena, MutRc};
+pub use concurrent::{ConcurrentArena, ConcurrentRc, Guard};
+pub use cursor::CursorMut;
+pub use cycles::Trace;
+#[cfg(feature = "serde")]
+pub use de::{ArenaSeed, ArenaSeqSeed, DeserializeIn};
+#[cfg(feature = "event-log")]
+pub use events::{Event, EventLog};
+pub use family::{ArenaFamily, Components};
+pub use fixed::{StaticArena, StaticRc};
+pub use frozen::{FrozenArena, FrozenRc};
+pub use handle::{Handle, Offset, Rc32};
+pub use hooks::Hooks;
+pub use iter::Iter;
+pub use list::{List, ListIter, ListNode};
+#[cfg(feature = "debug-leaks")]
+pub use leaks::{LeakReport, LiveObject};
+pub use limit::Limit;
+#[cfg(feature = "mlock")]
+pub use locked::LockedAllocator;
+pub use persist::Persist;
+pub use persistent::PersistentArena;
+pub use report::{ChunkReport, DebugReport};
+pub use sealed::SealedArena;
+pub use secondary::SecondaryMap;
+pub use set::ArenaSet;
+pub use sharded::{Shard, ShardedArena};
+pub use slice::{ArenaWriter, RcArray, RcSlice, RcStr};
+pub use storage::{PageStorage, Storage};
+#[cfg(feature = "futures")]
+pub use stream::Subscription;
+
+/// Derive a struct-of-arrays arena for a struct with named fields, so that
+/// passes over a single field only have to touch the memory of that field.
+///
+/// For a struct `Particle`, this generates a `ParticleArena` with an arena
+/// for each field, and a `ParticleHandle` with an `Rc` to each field of a
+/// stored value. Both have the same visibility as the struct.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// #[derive(SoaArena)]
+/// struct Particle {
+///     position: [f32; 2],
+///     velocity: [f32; 2]
+/// }
+///
+/// fn main() {
+///     let particles = ParticleArena::new();
+///     let particle = particles.alloc(Particle { position: [0.0, 0.0], velocity: [1.0, 2.0] });
+///     particles.alloc(Particle { position: [1.0, 1.0], velocity: [0.0, 0.0] });
+///
+///     assert_eq!(*particle.velocity, [1.0, 2.0]);
+///     assert_eq!(particles.len(), 2);
+///
+///     // Each field lives in its own contiguous chunks.
+///     let moving = particles.velocity.chunks()
+///                                    .flat_map(|chunk| chunk.to_vec())
+///                                    .filter(|velocity| *velocity != [0.0, 0.0])
+///                                    .count();
+///     assert_eq!(moving, 1);
+/// }
+/// ```
+#[cfg(feature = "derive")]
+pub use rc_arena_derive::SoaArena;
+
+/// Derive constructors which store a value straight into an arena, for types
+/// whose children are `Rc<Self>`.
+///
+/// A struct gets a `build_in` function taking the arena and each of its
+/// fields. An enum gets a function for each variant instead, named after the
+/// variant in snake case with an `_in` suffix.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// use rc_arena::{Arena, Rc};
+///
+/// #[derive(ArenaTree)]
+/// enum Expr {
+///     Number(i64),
+///     BinaryOp { op: char, lhs: Rc<Expr>, rhs: Rc<Expr> },
+///     Nil
+/// }
+///
+/// fn eval(expr: &Expr) -> i64 {
+///     match *expr {
+///         Expr::Number(n) => n,
+///         Expr::BinaryOp { op: '+', ref lhs, ref rhs } => eval(lhs) + eval(rhs),
+///         Expr::BinaryOp { ref lhs, ref rhs, .. } => eval(lhs) * eval(rhs),
+///         Expr::Nil => 0
+///     }
+/// }
+///
+/// fn main() {
+///     let arena = Arena::new();
+///     let two = Expr::number_in(&arena, 2);
+///     let sum = Expr::binary_op_in(&arena, '+', two.clone(), Expr::number_in(&arena, 3));
+///     let product = Expr::binary_op_in(&arena, '*', sum, two);
+///     Expr::nil_in(&arena);
+///
+///     assert_eq!(eval(&product), 10);
+///     assert_eq!(arena.len(), 5);
+/// }
+/// ```
+#[cfg(feature = "derive")]
+pub use rc_arena_derive::ArenaTree;
+
+/// Derive an `alloc_in` constructor for a struct, which takes the arena and
+/// each field. Structs with named fields also get a builder, named after the
+/// struct with a `Builder` suffix, whose `alloc_in` panics if a field wasn't
+/// set.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// use rc_arena::{Arena, Rc};
+///
+/// #[derive(ArenaAlloc)]
+/// struct Node {
+///     name: &'static str,
+///     children: Vec<Rc<Node>>
+/// }
+///
+/// fn main() {
+///     let scene = Arena::new();
+///     let camera = Node::alloc_in(&scene, "camera", vec![]);
+///     let root = Node::builder().name("root").children(vec![camera]).alloc_in(&scene);
+///
+///     assert_eq!(root.children[0].name, "camera");
+///     assert_eq!(scene.len(), 2);
+/// }
+/// ```
+#[cfg(feature = "derive")]
+pub use rc_arena_derive::ArenaAlloc;
+
+/// The number of `Rc`s and `Weak`s pointing at a single object.
+///
+/// The counts are plain `Cell`s rather than going through a trait which an
+/// atomic counter could also implement. Atomic counts alone wouldn't make a
+/// thread-safe arena, since the chunks, pool, table and hooks all live in a
+/// single `RefCell` shared by every handle, so a `Sync` variant would need
+/// its own locking throughout rather than a different counter. Handles which
+/// can cross threads come from `Arena::freeze` instead.
+struct Slot {
+    strong: Cell<usize>,
+    weak: Cell<usize>
+}
+
+impl Slot {
+    fn is_referenced(&self) -> bool {
+        self.strong.get() != 0 || self.weak.get() != 0
+    }
+}
+
+/// The slots of a chunk's objects. These either live in the chunk itself, or
+/// for arenas created with `Arena::indirect`, in the arena's indirection
+/// table so that the objects can be moved.
+enum Slots<T> {
+    Inline(Vec<Slot>),
+    Table(Vec<*const table::Entry<T>>)
+}
+
+impl<T> Slots<T> {
+    fn len(&self) -> usize {
+        match *self {
+            Slots::Inline(ref slots) => slots.len(),
+            Slots::Table(ref entries) => entries.len()
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match *self {
+            Slots::Inline(ref slots) => slots.capacity(),
+            Slots::Table(ref entries) => entries.capacity()
+        }
+    }
+
+    fn get(&self, index: usize) -> &Slot {
+        match *self {
+            Slots::Inline(ref slots) => &slots[index],
+            // Entries outlive the chunks which refer to them.
+            Slots::Table(ref entries) => unsafe { &(*entries[index]).slot }
+        }
+    }
+
+    fn iter_from<'a>(&'a self, from: usize) -> impl Iterator<Item = &'a Slot> + 'a {
+        (from..self.len()).map(move |index| self.get(index))
+    }
+}
+
+/// The memory backing the objects of a chunk.
+enum Items<T> {
+    Heap(Vec<T>),
+    // Memory provided by whoever created the arena.
+    Buffer(buffer::BufferItems<T>),
+    #[cfg(feature = "allocator-api2")]
+    Allocated(allocator_api2::vec::Vec<T, allocator::ChunkAllocator>),
+    #[cfg(feature = "mmap")]
+    Mapped(mmap::MappedItems<T>),
+    // Shared with a `FrozenArena`, and never written to again.
+    Frozen(std::sync::Arc<Vec<T>>)
+}
+
+impl<T> Items<T> {
+    fn capacity(&self) -> usize {
+        match *self {
+            Items::Heap(ref vec) => vec.capacity(),
+            Items::Buffer(ref buffer) => buffer.capacity(),
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref vec) => vec.capacity(),
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mapped) => mapped.capacity(),
+            // Frozen chunks are always full.
+            Items::Frozen(ref vec) => vec.len()
+        }
+    }
+
+    fn is_frozen(&self) -> bool {
+        matches!(*self, Items::Frozen(_))
+    }
+
+    /// Push an object. The caller must make sure there is room for it, so
+    /// that the existing objects don't move.
+    fn push(&mut self, value: T) {
+        debug_assert!(self.len() < self.capacity());
+
+        match *self {
+            Items::Heap(ref mut vec) => vec.push(value),
+            Items::Buffer(ref mut buffer) => buffer.push(value),
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref mut vec) => vec.push(value),
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mut mapped) => mapped.push(value),
+            Items::Frozen(_) => unreachable!()
+        }
+    }
+
+    /// Remove the objects from `at` onwards, returning those which need to
+    /// be dropped.
+    fn split_off(&mut self, at: usize) -> Vec<T> {
+        match *self {
+            Items::Heap(ref mut vec) => vec.split_off(at),
+            Items::Buffer(ref mut buffer) => buffer.split_off(at),
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref mut vec) => vec.drain(at..).collect(),
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mut mapped) => {
+                mapped.truncate(at);
+                vec![]
+            }
+            // Frozen objects are never removed.
+            Items::Frozen(ref vec) => {
+                assert_eq!(at, vec.len());
+                vec![]
+            }
+        }
+    }
+
+    /// Count the object after the last one as stored, once it has been
+    /// written in place. The caller must make sure there is room for it.
+    unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity());
+
+        match *self {
+            Items::Heap(ref mut vec) => vec.set_len(len),
+            Items::Buffer(ref mut buffer) => buffer.set_len(len),
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref mut vec) => vec.set_len(len),
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mut mapped) => mapped.set_len(len),
+            Items::Frozen(_) => unreachable!()
+        }
+    }
+
+    /// Like `split_off`, but objects which don't need to be dropped are
+    /// simply forgotten, rather than being moved out one by one.
+    fn truncate(&mut self, len: usize) -> Vec<T> {
+        if std::mem::needs_drop::<T>() {
+            return self.split_off(len);
+        }
+
+        match *self {
+            Items::Heap(ref mut vec) => vec.truncate(len),
+            Items::Buffer(ref mut buffer) => buffer.truncate(len),
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref mut vec) => vec.truncate(len),
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mut mapped) => mapped.truncate(len),
+            Items::Frozen(ref vec) => assert_eq!(len, vec.len())
+        }
+
+        vec![]
+    }
+}
+
+impl<T> Deref for Items<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match *self {
+            Items::Heap(ref vec) => vec,
+            Items::Buffer(ref buffer) => buffer,
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref vec) => vec,
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mapped) => mapped,
+            Items::Frozen(ref vec) => vec
+        }
+    }
+}
+
+impl<T> DerefMut for Items<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match *self {
+            Items::Heap(ref mut vec) => vec,
+            Items::Buffer(ref mut buffer) => buffer,
+            #[cfg(feature = "allocator-api2")]
+            Items::Allocated(ref mut vec) => vec,
+            #[cfg(feature = "mmap")]
+            Items::Mapped(ref mut mapped) => mapped,
+            Items::Frozen(_) => unreachable!("frozen objects are never written to")
+        }
+    }
+}
+
+/// A single contiguous run of objects, along with the `Slot` of each. Neither
+/// is ever grown past its initial capacity, so pointers into them are stable.
+/// `reserved` is set while `alloc_cyclic` is constructing a value for the
+/// next free position, so that no other allocation lands there in the
+/// meantime; the slot for that position already exists.
+struct Chunk<T> {
+    items: Items<T>,
+    slots: Slots<T>,
+    reserved: bool,
+    // Where each object was allocated, for objects which were allocated
+    // rather than loaded or adopted from elsewhere.
+    #[cfg(feature = "alloc-backtraces")]
+    backtraces: Vec<Option<std::rc::Rc<std::backtrace::Backtrace>>>,
+    // The metadata of each object, once any has been set in the chunk.
+    meta: Option<Box<dyn meta::Column>>
+}
+
+// The objects are dropped before the chunk's memory is poisoned and freed.
+#[cfg(feature = "poison")]
+impl<T> Drop for Chunk<T> {
+    fn drop(&mut self) {
+        poison::free(self);
+    }
+}
+
+impl<T> Chunk<T> {
+    fn new(items: Items<T>, capacity: usize) -> Chunk<T> {
+        Chunk {
+            items,
+            slots: Slots::Inline(Vec::with_capacity(capacity)),
+            reserved: false,
+            #[cfg(feature = "alloc-backtraces")]
+            backtraces: vec![],
+            meta: None
+        }
+    }
+
+    fn with_capacity(n: usize) -> Chunk<T> {
+        Chunk::new(Items::Heap(Vec::with_capacity(n)), n)
+    }
+
+    /// Adopt the contents of a vector, which are not referenced by anything.
+    fn from_vec(items: Vec<T>) -> Chunk<T> {
+        let len = items.len();
+        let capacity = items.capacity();
+        let mut chunk = Chunk::new(Items::Heap(items), capacity);
+
+        for _ in 0..len {
+            chunk.push_slot(0);
+        }
+
+        chunk
+    }
+
+    fn capacity(&self) -> usize {
+        std::cmp::min(self.items.capacity(), self.slots.capacity())
+    }
+
+    /// Push a new slot, returning a pointer to it. Chunks whose slots live
+    /// in an indirection table go through `Chunks::push_slot` instead.
+    fn push_slot(&mut self, strong: usize) -> *const Slot {
+        match self.slots {
+            Slots::Inline(ref mut slots) => {
+                slots.push(Slot {
+                    strong: Cell::new(strong),
+                    weak: Cell::new(0)
+                });
+
+                slots.last().unwrap()
+            }
+            Slots::Table(_) => unreachable!()
+        }
+    }
+
+    /// The pointers a handle to the object at `index` is made of.
+    fn handle(&self, index: usize) -> (*mut T, *const Slot) {
+        match self.slots {
+            Slots::Inline(ref slots) => (self.items[index..].as_ptr() as *mut T, &slots[index]),
+            // Indirect handles find the object through the table.
+            Slots::Table(ref entries) => (std::ptr::null_mut(), entries[index] as *const Slot)
+        }
+    }
+
+    /// Make the object at `index` reachable through the table, once it has
+    /// been stored.
+    fn placed(&mut self, index: usize) {
+        if let Slots::Table(ref entries) = self.slots {
+            let ptr: *mut T = &mut self.items[index];
+            unsafe { (*entries[index]).ptr.set(ptr) };
+        }
+    }
+
+    /// The position in this chunk of the object a handle points at, if it
+    /// lives here.
+    fn index_of(&self, ptr: *mut T, slot: *const Slot) -> Option<usize> {
+        match self.slots {
+            // Slots are never zero-sized, unlike the objects themselves.
+            Slots::Inline(ref slots) => {
+                let offset = (slot as usize).wrapping_sub(slots.as_ptr() as usize);
+                let index = offset / std::mem::size_of::<Slot>();
+
+                if index < slots.len() { Some(index) } else { None }
+            }
+            Slots::Table(ref entries) => {
+                if std::mem::size_of::<T>() == 0 {
+                    return entries.iter().position(|&entry| entry as *const Slot == slot);
+                }
+
+                let offset = (ptr as usize).wrapping_sub(self.items.as_ptr() as usize);
+                let index = offset / std::mem::size_of::<T>();
+
+                if index < self.items.len() { Some(index) } else { None }
+            }
+        }
+    }
+}
+
+/// The state of an arena, shared by the arena and all of its handles.
+struct Chunks<T> {
+    list: Vec<Chunk<T>>,
+    pool: Option<pool::Pool<T>>,
+    table: Option<table::Table<T>>,
+    hooks: Option<std::rc::Rc<hooks::Hooks<T>>>,
+    // Tells handles to this arena apart from those to other arenas.
+    id: usize,
+    // Set by `Arena::seal`, after which nothing more can be allocated.
+    sealed: bool,
+    limit: Option<limit::Limit>,
+    // The count of `Arena` clones, which handles can make new clones with.
+    #[cfg(feature = "debug-leaks")]
+    clones: std::rc::Weak<()>,
+    #[cfg(feature = "allocator-api2")]
+    allocator: Option<allocator::ChunkAllocator>,
+    // Where new chunks are created, if not on the heap.
+    #[cfg(feature = "mmap")]
+    file: Option<mmap::MappedFile>,
+    // Where new chunks get their memory from, if not the heap.
+    storage: Option<std::rc::Rc<dyn Storage<T>>>,
+    // Wipes each object before it's dropped, for arenas created with
+    // `zeroizing`.
+    #[cfg(feature = "zeroize")]
+    wipe: Option<fn(&mut T)>,
+    // The tasks waiting on a `Subscription` for something to be allocated.
+    #[cfg(feature = "futures")]
+    wakers: Vec<std::task::Waker>,
+    // Run with the remaining objects before they're dropped, added with
+    // `Arena::on_drop`.
+    finalizers: Vec<teardown::Finalizer<T>>,
+    // The type of the objects' metadata, once any has been set.
+    meta: Option<std::any::TypeId>,
+    // Set by `Arena::set_reverse_drop`.
+    reverse_drop: bool,
+    // The strong counts held by `RcSlice`s and `RcArray`s, beyond one for
+    // each of them, since each holds a count on every object in its run.
+    runs: Cell<usize>,
+    // The strong counts which keep objects alive for `alloc_ref`, `Handle`s
+    // and `Rc32`s, and don't belong to anything holding the arena.
+    pinned: Cell<usize>,
+    // Where allocations are recorded, once `Arena::record_events` is called.
+    #[cfg(feature = "event-log")]
+    events: Option<std::rc::Rc<RefCell<events::Ring>>>
+}
+
+impl<T> Chunks<T> {
+    fn new(list: Vec<Chunk<T>>) -> Chunks<T> {
+        Chunks {
+            list,
+            pool: None,
+            table: None,
+            hooks: None,
+            id: handle::next_id(),
+            sealed: false,
+            limit: None,
+            #[cfg(feature = "debug-leaks")]
+            clones: std::rc::Weak::new(),
+            #[cfg(feature = "allocator-api2")]
+            allocator: None,
+            #[cfg(feature = "mmap")]
+            file: None,
+            storage: None,
+            #[cfg(feature = "zeroize")]
+            wipe: None,
+            #[cfg(feature = "futures")]
+            wakers: vec![],
+            finalizers: vec![],
+            meta: None,
+            reverse_drop: false,
+            runs: Cell::new(0),
+            pinned: Cell::new(0),
+            #[cfg(feature = "event-log")]
+            events: None
+        }
+    }
+
+    fn try_new_items(&mut self, capacity: usize) -> Result<Items<T>, ArenaError> {
+        #[cfg(feature = "allocator-api2")]
+        {
+            if let Some(ref allocator) = self.allocator {
+                let mut vec = allocator_api2::vec::Vec::new_in(allocator.clone());
+                vec.try_reserve_exact(capacity).map_err(|_| ArenaError::AllocationFailed)?;
+                return Ok(Items::Allocated(vec));
+            }
+        }
+
+        #[cfg(feature = "mmap")]
+        {
+            if let Some(ref mut file) = self.file {
+                return file.try_new_chunk(capacity).map(Items::Mapped).map_err(|_| ArenaError::AllocationFailed);
+            }
+        }
+
+        if let Some(ref storage) = self.storage {
+            return buffer::BufferItems::allocate(storage, capacity).map(Items::Buffer).ok_or(ArenaError::AllocationFailed);
+        }
+
+        try_vec(capacity).map(Items::Heap)
+    }
+
+    fn new_chunk(&mut self, capacity: usize) -> Chunk<T> {
+        match self.try_new_chunk(capacity) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn try_new_chunk(&mut self, capacity: usize) -> Result<Chunk<T>, ArenaError> {
+        let items = self.try_new_items(capacity)?;
+        let slots = if self.table.is_some() {
+            Slots::Table(try_vec(capacity)?)
+        } else {
+            Slots::Inline(try_vec(capacity)?)
+        };
+
+        Ok(Chunk {
+            items,
+            slots,
+            reserved: false,
+            #[cfg(feature = "alloc-backtraces")]
+            backtraces: vec![],
+            meta: None
+        })
+    }
+
+    /// The capacity of the last chunk, if it has been created since there
+    /// were `num_chunks` chunks.
+    fn new_chunk_since(&self, num_chunks: usize) -> Option<usize> {
+        if self.list.len() > num_chunks {
+            Some(self.list[self.list.len() - 1].capacity())
+        } else {
+            None
+        }
+    }
+
+    /// Push a new slot for the next object of a chunk, returning the pointers
+    /// a handle to that object is made of.
+    fn push_slot(&mut self, chunk: usize, strong: usize) -> (*mut T, *const Slot) {
+        #[cfg(feature = "event-log")]
+        events::record(self, chunk, self.list[chunk].slots.len());
+
+        let chunk = &mut self.list[chunk];
+        let index = chunk.slots.len();
+
+        if let Some(ref mut table) = self.table {
+            if let Slots::Table(ref mut entries) = chunk.slots {
+                entries.push(table.insert(strong));
+            }
+            if index < chunk.items.len() {
+                chunk.placed(index);
+            }
+
+            return chunk.handle(index);
+        }
+
+        // The chunk never reallocates, so this position is stable even if
+        // nothing has been stored there yet.
+        let ptr = unsafe { chunk.items.as_mut_ptr().add(index) };
+
+        (ptr, chunk.push_slot(strong))
+    }
+
+    /// Store an object with a strong count of one in the last chunk, if
+    /// there's room for it and nothing else needs doing: the arena isn't a
+    /// pool or indirect, and has no hooks or event log. This is the common
+    /// case of `alloc`, so it avoids everything the general case has to take
+    /// care of, and gives the object back otherwise. It still needs the
+    /// chunks borrowed mutably, which is what keeps it from pushing into a
+    /// chunk handed out by `Arena::chunks`.
+    fn try_push(&mut self, value: T) -> Result<(*mut T, *const Slot), T> {
+        if self.sealed || self.limit.is_some() || self.pool.is_some() || self.table.is_some() || self.hooks.is_some() {
+            return Err(value);
+        }
+        #[cfg(feature = "event-log")]
+        {
+            if self.events.is_some() {
+                return Err(value);
+            }
+        }
+
+        let chunk = self.list.last_mut().unwrap();
+        if chunk.reserved || chunk.items.len() == chunk.capacity() {
+            return Err(value);
+        }
+
+        let ptr = unsafe { chunk.items.as_mut_ptr().add(chunk.items.len()) };
+        chunk.items.push(value);
+        let slot = chunk.push_slot(1);
+        #[cfg(feature = "futures")]
+        stream::notify(self);
+
+        Ok((ptr, slot))
+    }
+
+    /// Remove the slots of a chunk from `len` onwards, which nothing may
+    /// refer to.
+    fn truncate_slots(&mut self, chunk: usize, len: usize) {
+        #[cfg(feature = "alloc-backtraces")]
+        self.list[chunk].backtraces.truncate(len);
+        if let Some(ref mut column) = self.list[chunk].meta {
+            column.truncate(len);
+        }
+
+        match self.list[chunk].slots {
+            Slots::Inline(ref mut slots) => slots.truncate(len),
+            Slots::Table(ref mut entries) => {
+                let table = self.table.as_mut().unwrap();
+
+                for entry in entries.drain(len..) {
+                    table.remove(entry);
+                }
+            }
+        }
+    }
+
+    /// Find a chunk with room for one more object, creating one if necessary,
+    /// and return its index.
+    fn chunk_with_space(&mut self) -> usize {
+        self.reserve(1);
+
+        self.list.len() - 1
+    }
+
+    /// Make sure the last chunk has room for `additional` more objects,
+    /// creating a new one if necessary.
+    fn reserve(&mut self, additional: usize) {
+        if let Err(error) = self.try_reserve(additional) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `reserve`, but fails if the room would take the arena past its
+    /// limit, or if the memory for it can't be allocated.
+    fn try_reserve(&mut self, additional: usize) -> Result<(), ArenaError> {
+        assert!(!self.sealed, "can't allocate in an arena which has been sealed");
+
+        if let Some(limit) = self.limit {
+            limit::check(limit, self, additional)?;
+        }
+
+        let (available, last_chunk_capacity) = {
+            let last_chunk = &self.list[self.list.len() - 1];
+            (!last_chunk.reserved && last_chunk.capacity() - last_chunk.items.len() >= additional,
+             last_chunk.capacity())
+        };
+
+        if !available {
+            let doubled = last_chunk_capacity.checked_mul(2).ok_or(ArenaError::CapacityOverflow)?;
+            let mut new_capacity = std::cmp::max(doubled, additional);
+            if let Some(limit) = self.limit {
+                new_capacity = limit::chunk_capacity(limit, self, new_capacity, additional)?;
+            }
+
+            let chunk = self.try_new_chunk(new_capacity)?;
+            self.list.push(chunk);
+            #[cfg(feature = "tracing")]
+            ::tracing::debug!(capacity = new_capacity,
+                              chunks = self.list.len(),
+                              object_size = std::mem::size_of::<T>(),
+                              "arena grew a new chunk");
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for Chunks<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(objects = self.list.iter().map(|chunk| chunk.items.len()).sum::<usize>(),
+                          capacity = self.list.iter().map(|chunk| chunk.capacity()).sum::<usize>(),
+                          chunks = self.list.len(),
+                          object_size = std::mem::size_of::<T>(),
+                          "dropping arena");
+        teardown::finalize(self);
+        #[cfg(feature = "zeroize")]
+        wipe::wipe_all(self);
+        teardown::drop_objects(self);
+    }
+}
+
+/// A chunk which is closed to other allocations while an object is being
+/// constructed in its next position, by `alloc_cyclic` or `emplace`. It's
+/// released when dropped, even if constructing the object panics, so the
+/// chunk doesn't stay closed to future allocations.
+struct Reservation<'a, T: 'a> {
+    chunks: &'a RefCell<Chunks<T>>,
+    chunk: usize
+}
+
+impl<'a, T> Drop for Reservation<'a, T> {
+    fn drop(&mut self) {
+        let mut chunks_borrow = self.chunks.borrow_mut();
+        let len = chunks_borrow.list[self.chunk].items.len();
+
+        if chunks_borrow.list[self.chunk].slots.len() > len {
+            // The object was never constructed. If a `Weak` to it escaped,
+            // the position can never be reused.
+            if chunks_borrow.list[self.chunk].slots.get(len).weak.get() != 0 {
+                return;
+            }
+            chunks_borrow.truncate_slots(self.chunk, len);
+            #[cfg(feature = "poison")]
+            poison::poison_from(&mut chunks_borrow.list[self.chunk], len);
+        }
+
+        chunks_borrow.list[self.chunk].reserved = false;
+    }
+}
+
+/// Create a vector with room for `capacity` elements, failing rather than
+/// aborting if the memory can't be allocated.
+fn try_vec<U>(capacity: usize) -> Result<Vec<U>, ArenaError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity).map_err(|_| ArenaError::AllocationFailed)?;
+
+    Ok(vec)
+}
 
 /// A reference counted pointer to an object that lives in an arena.
 pub struct Rc<T> {
-    chunks: std::rc::Rc<RefCell<Vec<Vec<T>>>>,
+    chunks: std::rc::Rc<RefCell<Chunks<T>>>,
     // Similar to Rc itself, we choose a weird name here because of a privacy check
     // bug in rustc.
-    _ptr: *mut T
+    _ptr: *mut T,
+    slot: *const Slot
+}
+
+impl<T> Rc<T> {
+    /// Create a new handle to an object, incrementing its strong count.
+    fn new_handle(chunks: std::rc::Rc<RefCell<Chunks<T>>>, ptr: *mut T, slot: *const Slot) -> Rc<T> {
+        let rc = Rc {
+            chunks,
+            _ptr: ptr,
+            slot
+        };
+        rc.slot().strong.set(rc.slot().strong.get() + 1);
+
+        rc
+    }
+
+    fn slot(&self) -> &Slot {
+        // Slots live alongside the objects, so this is okay for the same
+        // reason as `deref`.
+        let slot = unsafe { &*self.slot };
+        #[cfg(feature = "poison")]
+        poison::check(slot);
+
+        slot
+    }
+
+    fn ptr(&self) -> *mut T {
+        table::resolve(self._ptr, self.slot)
+    }
+
+    /// Keep the object referenced until the arena is dropped, with a strong
+    /// count which doesn't keep the arena alive itself.
+    fn pin(&self) {
+        let slot = self.slot();
+        slot.strong.set(slot.strong.get() + 1);
+
+        let pinned = &self.chunks.borrow().pinned;
+        pinned.set(pinned.get() + 1);
+    }
 }
 
 impl<T> Clone for Rc<T> {
     fn clone(&self) -> Rc<T> {
-        Rc {
-            chunks: self.chunks.clone(),
-            _ptr: self._ptr
+        Rc::new_handle(self.chunks.clone(), self._ptr, self.slot)
+    }
+}
+
+impl<T> Drop for Rc<T> {
+    fn drop(&mut self) {
+        let slot = self.slot();
+        slot.strong.set(slot.strong.get() - 1);
+
+        if !slot.is_referenced() {
+            pool::release(&self.chunks, self.ptr(), slot);
         }
     }
 }
@@ -45,11 +963,32 @@ impl<T> Deref for Rc<T> {
     fn deref(&self) -> &T {
         // This is okay because the pointer will never outlive the chunks, and
         // the chunks must still exist as this object contains a reference
-        // counted pointer to it.
-        unsafe { &*self._ptr }
+        // counted pointer to it. Looking at the slot checks that it hasn't
+        // been poisoned.
+        #[cfg(feature = "poison")]
+        self.slot();
+
+        unsafe { &*self.ptr() }
     }
 }
 
+// Objects never move while they are referenced, except when an indirect
+// arena is compacted, which is unsafe for as long as anything borrows them.
+#[cfg(feature = "stable_deref_trait")]
+unsafe impl<T> stable_deref_trait::StableDeref for Rc<T> {}
+
+#[cfg(feature = "stable_deref_trait")]
+unsafe impl<T> stable_deref_trait::CloneStableDeref for Rc<T> {}
+
+// A panic never leaves the state of an arena half updated: objects are only
+// counted once they have been stored, and callbacks which run in the middle
+// of an update either run before anything has changed or are undone by a
+// guard. So handles can be used after `catch_unwind` whenever the objects
+// themselves can.
+impl<T: RefUnwindSafe> UnwindSafe for Rc<T> {}
+
+impl<T: RefUnwindSafe> RefUnwindSafe for Rc<T> {}
+
 impl<T> std::fmt::Display for Rc<T> where T: std::fmt::Display {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         self.deref().fmt(f)
@@ -68,112 +1007,1696 @@ impl<T> std::borrow::Borrow<T> for Rc<T> {
     }
 }
 
+impl<T> PartialEq<T> for Rc<T> where T: PartialEq {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+
+// Comparing the other way around can only be implemented for specific types.
+macro_rules! eq_rc {
+    ($($ty:ty),*) => {$(
+        impl PartialEq<Rc<$ty>> for $ty {
+            fn eq(&self, other: &Rc<$ty>) -> bool {
+                *self == **other
+            }
+        }
+    )*}
+}
+
+eq_rc!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char, String);
+
 impl<T> std::fmt::Pointer for Rc<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         std::fmt::Pointer::fmt(&(self.deref() as *const T), f)
     }
 }
 
-/// A typed arena that provides reference-counted pointers to its underlying
-/// objects.
-#[derive(Clone)]
-pub struct Arena<T> {
-    chunks: std::rc::Rc<RefCell<Vec<Vec<T>>>>
-}
+impl<T> Rc<T> {
+    /// Store an object in an arena of its own, like `std::rc::Rc::new`
+    /// does, so that code can switch over to this crate before it has arenas
+    /// to share. Objects allocated through `Rc::arena` later are stored in
+    /// the same arena.
+    ///
+    /// ```rust
+    /// use rc_arena::Rc;
+    ///
+    /// let five = Rc::new(5);
+    /// let six = Rc::arena(&five).alloc(6);
+    /// assert_eq!(*five + 1, *six);
+    /// ```
+    pub fn new(value: T) -> Rc<T> {
+        let arena = Arena::with_capacity(1);
+        let rc = arena.alloc(value);
+        // Nothing else was meant to refer to the object.
+        #[cfg(feature = "debug-leaks")]
+        leaks::forget(arena);
 
-impl<T> Arena<T> {
-    /// Create a new arena with an unspecified capacity.
-    pub fn new() -> Arena<T> {
-        Arena::with_capacity(8)
+        rc
     }
 
-    /// Create a new arena with a known initial capacity.
-    pub fn with_capacity(n: usize) -> Arena<T> {
-        Arena {
-            chunks: std::rc::Rc::new(RefCell::new(vec![Vec::with_capacity(n)]))
-        }
+    /// Create a `Weak` pointer to this object.
+    pub fn downgrade(this: &Rc<T>) -> Weak<T> {
+        Weak::new_handle(std::rc::Rc::downgrade(&this.chunks), this._ptr, this.slot, None)
     }
 
-    /// Store an object in the arena, returning a reference counted
-    /// pointer to it.
+    /// Get a handle to the arena this object lives in, for allocating more
+    /// objects next to it. This works even if the arena was dropped, since
+    /// it's kept alive by every `Rc` to its objects.
     ///
     /// ```rust
-    /// use rc_arena::Arena;
-    /// 
+    /// use rc_arena::{Arena, Rc};
+    ///
+    /// let one = Arena::new().alloc(1);
+    /// let two = Rc::arena(&one).alloc(2);
+    /// assert_eq!(Rc::arena(&two).len(), 2);
+    /// ```
+    pub fn arena(this: &Rc<T>) -> Arena<T> {
+        Arena::from_shared(this.chunks.clone())
+    }
+
+    /// Returns `true` if both handles point at the same object, rather than
+    /// at two objects which happen to be equal.
+    pub fn ptr_eq(this: &Rc<T>, other: &Rc<T>) -> bool {
+        // Indirect objects may move, but their slots don't.
+        this.slot == other.slot
+    }
+
+    /// Get the number of `Rc`s to the object.
+    pub fn strong_count(this: &Rc<T>) -> usize {
+        this.slot().strong.get()
+    }
+
+    /// Get the number of `Weak`s to the object, any of which could upgrade
+    /// it again after its last `Rc` is dropped.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Rc};
+    ///
     /// let arena = Arena::new();
-    /// let foo = arena.alloc([0; 256]);
-    /// let bar = arena.alloc([1; 256]);
-    /// let baz = bar.clone();
-    /// 
-    /// assert_eq!(foo[0], 0);
-    /// assert_eq!(bar[0], 1);
-    /// assert_eq!(baz[0], 1);
+    /// let entry = arena.alloc("cached");
+    /// let weak = Rc::downgrade(&entry);
+    /// assert_eq!((Rc::strong_count(&entry), Rc::weak_count(&entry)), (1, 1));
+    ///
+    /// drop(weak);
+    /// assert_eq!(arena.weak_count(), 0);
     /// ```
-    pub fn alloc(&self, value: T) -> Rc<T> {
-        let mut chunks_borrow = self.chunks.borrow_mut();
-        let next_chunk_index = chunks_borrow.len();
+    pub fn weak_count(this: &Rc<T>) -> usize {
+        this.slot().weak.get()
+    }
 
-        let (last_child_length, last_chunk_capacity) = {
-            let last_chunk = &chunks_borrow[next_chunk_index - 1];
-            (last_chunk.len(), last_chunk.capacity())
-        };
+    /// Get a mutable reference to the object, without checking whether
+    /// other handles to it exist. This is for setting up objects which point
+    /// at each other before anything reads them.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Rc};
+    ///
+    /// struct Node {
+    ///     next: Option<Rc<Node>>
+    /// }
+    ///
+    /// let arena = Arena::new();
+    /// let mut first = arena.alloc(Node { next: None });
+    /// let second = arena.alloc(Node { next: Some(first.clone()) });
+    ///
+    /// // Nothing reads the first node while the cycle is closed.
+    /// unsafe { Rc::get_mut_unchecked(&mut first).next = Some(second) };
+    /// let next = first.next.as_ref().unwrap();
+    /// assert!(Rc::ptr_eq(next.next.as_ref().unwrap(), &first));
+    /// # unsafe { Rc::get_mut_unchecked(&mut first).next = None };
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// No other reference to the object may be used while the returned one
+    /// is, whether it was made through another `Rc`, a `Weak`, the arena
+    /// or a `FrozenArena` it belongs to.
+    pub unsafe fn get_mut_unchecked(this: &mut Rc<T>) -> &mut T {
+        &mut *this.ptr()
+    }
+}
 
-        let (chunk, next_item_index) = if last_child_length < last_chunk_capacity {
-            (&mut chunks_borrow[next_chunk_index - 1], last_child_length)
+impl<T: Clone> Rc<T> {
+    /// Get an owned copy of the object.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let mut name = arena.alloc(String::from("rc")).clone_inner();
+    /// name.push_str("_arena");
+    /// assert_eq!(name, "rc_arena");
+    /// ```
+    pub fn clone_inner(&self) -> T {
+        T::clone(self)
+    }
+
+    /// Copy the object into a standard library `Rc`, which doesn't keep the
+    /// arena alive.
+    pub fn to_std_rc(this: &Rc<T>) -> std::rc::Rc<T> {
+        std::rc::Rc::new(this.clone_inner())
+    }
+
+    /// Copy the object into a `Box`, which doesn't keep the arena alive.
+    pub fn to_box(this: &Rc<T>) -> Box<T> {
+        Box::new(this.clone_inner())
+    }
+}
+
+impl<T: Default> Rc<T> {
+    /// Move the object into a `Box` without copying it, leaving its default
+    /// value behind in the arena. This fails, giving the handle back, unless
+    /// it's the only `Rc` or `Weak` to the object, if the object belongs to
+    /// a `FrozenArena`, or if the arena is borrowed, as it is by `chunks`.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Rc};
+    ///
+    /// let arena = Arena::new();
+    /// let name = arena.alloc(String::from("rc_arena"));
+    /// let other = name.clone();
+    ///
+    /// let name = Rc::take_box(name).unwrap_err();
+    /// drop(other);
+    /// assert_eq!(*Rc::take_box(name).unwrap(), "rc_arena");
+    /// assert_eq!(*arena.first().unwrap(), "");
+    /// ```
+    pub fn take_box(this: Rc<T>) -> Result<Box<T>, Rc<T>> {
+        if this.slot().strong.get() != 1 || this.slot().weak.get() != 0 {
+            return Err(this);
+        }
+
+        // The default value is made first, in case making it uses the arena.
+        let value = T::default();
+
+        // A borrow of the arena may be handing out references to the object,
+        // so the arena stays borrowed until it has been moved out.
+        let taken = match this.chunks.try_borrow_mut() {
+            Ok(ref chunks) if !chunks.list.iter().any(|chunk| {
+                chunk.items.is_frozen() && chunk.index_of(this.ptr(), this.slot).is_some()
+            }) => {
+                // Nothing else can reach the object while this is the only
+                // handle and the arena isn't borrowed.
+                Some(std::mem::replace(unsafe { &mut *this.ptr() }, value))
+            }
+            _ => None
+        };
+
+        match taken {
+            Some(taken) => Ok(Box::new(taken)),
+            None => Err(this)
+        }
+    }
+}
+
+/// A wrapper around an `Rc` whose `PartialEq` and `Hash` consider which
+/// object it points at, rather than the object's value.
+///
+/// ```rust
+/// use std::collections::HashSet;
+/// use rc_arena::{Arena, ByAddress};
+///
+/// let arena = Arena::new();
+/// let a = arena.alloc(vec![0; 1024]);
+/// let b = arena.alloc(vec![0; 1024]);
+///
+/// let mut seen = HashSet::new();
+/// assert!(seen.insert(ByAddress(a.clone())));
+/// assert!(seen.insert(ByAddress(b)));
+/// assert!(!seen.insert(ByAddress(a)));
+/// ```
+pub struct ByAddress<T>(pub Rc<T>);
+
+impl<T> Clone for ByAddress<T> {
+    fn clone(&self) -> ByAddress<T> {
+        ByAddress(self.0.clone())
+    }
+}
+
+impl<T> PartialEq for ByAddress<T> {
+    fn eq(&self, other: &ByAddress<T>) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for ByAddress<T> {}
+
+impl<T> std::hash::Hash for ByAddress<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.slot.hash(state)
+    }
+}
+
+impl<T> Deref for ByAddress<T> {
+    type Target = Rc<T>;
+
+    fn deref(&self) -> &Rc<T> {
+        &self.0
+    }
+}
+
+impl<T> std::fmt::Debug for ByAddress<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+/// A weak pointer to an object that lives in an arena. It does not keep the
+/// arena alive, and must be upgraded to an `Rc` before the object can be
+/// accessed.
+pub struct Weak<T> {
+    chunks: std::rc::Weak<RefCell<Chunks<T>>>,
+    _ptr: *mut T,
+    slot: *const Slot,
+    // The (chunk, item) position of the object if it was handed out by
+    // `alloc_cyclic`, in which case it may not have been constructed yet.
+    pending: Option<(usize, usize)>
+}
+
+impl<T> Weak<T> {
+    /// Create a new weak handle to an object whose arena is alive,
+    /// incrementing its weak count.
+    fn new_handle(chunks: std::rc::Weak<RefCell<Chunks<T>>>,
+                  ptr: *mut T,
+                  slot: *const Slot,
+                  pending: Option<(usize, usize)>) -> Weak<T> {
+        // The caller guarantees the arena is alive, so the slot is too.
+        let slot_ref = unsafe { &*slot };
+        slot_ref.weak.set(slot_ref.weak.get() + 1);
+
+        Weak {
+            chunks,
+            _ptr: ptr,
+            slot,
+            pending
+        }
+    }
+
+    /// Attempt to upgrade to an `Rc`. This returns `None` if the arena and
+    /// all of its `Rc`s have been dropped, or if the object is still being
+    /// constructed by `Arena::alloc_cyclic`.
+    pub fn upgrade(&self) -> Option<Rc<T>> {
+        let chunks = self.chunks.upgrade()?;
+
+        if !self.constructed(&chunks) {
+            return None;
+        }
+
+        Some(Rc::new_handle(chunks, self._ptr, self.slot))
+    }
+
+    /// Whether the object has been constructed, which is only in doubt if it
+    /// was handed out by `Arena::alloc_cyclic`.
+    fn constructed(&self, chunks: &RefCell<Chunks<T>>) -> bool {
+        match self.pending {
+            None => true,
+            // Indirect objects may have been moved since, but can't be
+            // reached through the table until they're constructed.
+            Some(_) if self._ptr.is_null() => !table::resolve(self._ptr, self.slot).is_null(),
+            Some((chunk, item)) => chunks.borrow().list[chunk].items.len() > item
+        }
+    }
+
+    fn arena_alive(&self) -> bool {
+        self.chunks.strong_count() != 0
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    fn clone(&self) -> Weak<T> {
+        if self.arena_alive() {
+            Weak::new_handle(self.chunks.clone(), self._ptr, self.slot, self.pending)
         } else {
-            let new_capacity = last_chunk_capacity.checked_mul(2).unwrap();
-            chunks_borrow.push(Vec::with_capacity(new_capacity));
-            (&mut chunks_borrow[next_chunk_index], 0)
+            Weak {
+                chunks: self.chunks.clone(),
+                _ptr: self._ptr,
+                slot: self.slot,
+                pending: self.pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Weak<T> {
+    fn drop(&mut self) {
+        // Once the arena is gone the slot has been freed, and nobody is
+        // interested in its count anymore.
+        if let Some(chunks) = self.chunks.upgrade() {
+            let slot = unsafe { &*self.slot };
+            slot.weak.set(slot.weak.get() - 1);
+
+            if !slot.is_referenced() && self.constructed(&chunks) {
+                pool::release(&chunks, table::resolve(self._ptr, self.slot), slot);
+            }
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "(Weak)")
+    }
+}
+
+/// A typed arena that provides reference-counted pointers to its underlying
+/// objects.
+///
+/// Objects of zero-sized types take up no space in the chunks, which only
+/// count them. Each still has reference counts of its own, since handles to
+/// different objects are told apart, so an `Arena<()>` costs the size of a
+/// pair of counts per object.
+///
+/// Objects are always dropped while the arena isn't borrowed, so their
+/// destructors may use handles into the same arena. Once the last handle to
+/// an arena is gone and its objects are being dropped for good, `Weak`s into
+/// it can still be cloned and dropped, but no longer upgraded. Objects
+/// holding `Rc`s into their own arena keep it alive, so links back up a tree
+/// should be `Weak`s.
+#[derive(Clone)]
+pub struct Arena<T> {
+    chunks: std::rc::Rc<RefCell<Chunks<T>>>,
+    // Shared by the clones of this arena, to tell when the last one is
+    // dropped.
+    #[cfg(feature = "debug-leaks")]
+    clones: std::rc::Rc<()>
+}
+
+/// A weak handle to an arena, returned by `Arena::downgrade`. It doesn't
+/// keep the arena or its objects alive, and must be upgraded to an `Arena`
+/// before it can be used.
+pub struct ArenaWeak<T> {
+    chunks: std::rc::Weak<RefCell<Chunks<T>>>
+}
+
+impl<T> ArenaWeak<T> {
+    /// Attempt to upgrade to an `Arena`. This returns `None` once the arena
+    /// and all of the handles to its objects have been dropped.
+    pub fn upgrade(&self) -> Option<Arena<T>> {
+        self.chunks.upgrade().map(Arena::from_shared)
+    }
+}
+
+impl<T> Clone for ArenaWeak<T> {
+    fn clone(&self) -> ArenaWeak<T> {
+        ArenaWeak {
+            chunks: self.chunks.clone()
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for ArenaWeak<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "(ArenaWeak)")
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena::new()
+    }
+}
+
+// See the implementations for `Rc`.
+impl<T: RefUnwindSafe> UnwindSafe for Arena<T> {}
+
+impl<T: RefUnwindSafe> RefUnwindSafe for Arena<T> {}
+
+impl<T> std::fmt::Debug for Arena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Arenas are equal if they hold equal objects in the same order, however
+// they're split into chunks.
+impl<T> PartialEq for Arena<T> where T: PartialEq {
+    fn eq(&self, other: &Arena<T>) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a == *b)
+    }
+}
+
+impl<T> Eq for Arena<T> where T: Eq {}
+
+impl<T> Arena<T> {
+    fn from_chunks(chunks: Chunks<T>) -> Arena<T> {
+        Arena::from_shared(std::rc::Rc::new(RefCell::new(chunks)))
+    }
+
+    /// Create another handle to the arena whose state is `chunks`.
+    fn from_shared(chunks: std::rc::Rc<RefCell<Chunks<T>>>) -> Arena<T> {
+        // If every clone of the arena is gone, this one starts counting
+        // afresh.
+        #[cfg(feature = "debug-leaks")]
+        let clones = {
+            let mut chunks_borrow = chunks.borrow_mut();
+            chunks_borrow.clones.upgrade().unwrap_or_else(|| {
+                let clones = std::rc::Rc::new(());
+                chunks_borrow.clones = std::rc::Rc::downgrade(&clones);
+                clones
+            })
+        };
+
+        Arena {
+            chunks,
+            #[cfg(feature = "debug-leaks")]
+            clones
+        }
+    }
+
+    /// Create a new arena with an unspecified capacity.
+    ///
+    /// This can't be a `const fn`: the arena's state is shared through a
+    /// `std::rc::Rc`, which can only be created at runtime. Arenas aren't
+    /// `Sync` either, so they can't live in a `static`; a thread-local arena
+    /// declared with `thread_local_arena!` is the closest thing.
+    ///
+    /// For the same reason, even a small arena's objects live on the heap:
+    /// handles keep the arena's state alive after the `Arena` itself is
+    /// gone, so it can't be stored inline wherever the `Arena` is. A
+    /// `StaticArena` keeps its objects inline, for handles which borrow it.
+    pub fn new() -> Arena<T> {
+        Arena::with_capacity(8)
+    }
+
+    /// Create a new arena with a known initial capacity.
+    pub fn with_capacity(n: usize) -> Arena<T> {
+        Arena::from_chunks(Chunks::new(vec![Chunk::with_capacity(n)]))
+    }
+
+    /// Create a new arena which takes over the storage of a vector, so that
+    /// its objects become the first chunk of the arena without being moved.
+    /// Its spare capacity is used for the objects allocated next.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let mut numbers = Vec::with_capacity(4);
+    /// numbers.extend(1..4);
+    /// let first: *const usize = &numbers[0];
+    ///
+    /// let arena = Arena::from_vec(numbers);
+    /// let four = arena.alloc(4);
+    /// let one = arena.iter().next().unwrap();
+    ///
+    /// assert_eq!(&*one as *const usize, first);
+    /// assert_eq!(arena.len(), 4);
+    /// assert_eq!(*four, 4);
+    /// ```
+    pub fn from_vec(items: Vec<T>) -> Arena<T> {
+        Arena::from_chunks(Chunks::new(vec![Chunk::from_vec(items)]))
+    }
+
+    /// Take over the storage of a vector as a new chunk at the end of the
+    /// arena, like `from_vec` does for the first chunk, and return the
+    /// positions of its objects in allocation order. Nothing refers to them
+    /// yet. The vector's spare capacity is used for the objects allocated
+    /// next, and later chunks grow from its capacity.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(0);
+    /// let batch: Vec<u32> = (1..4).collect();
+    ///
+    /// assert_eq!(arena.append_vec(batch), 1..4);
+    /// assert_eq!(arena.iter().map(|i| *i).collect::<Vec<_>>(), [0, 1, 2, 3]);
+    /// assert_eq!(arena.chunks().count(), 2);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has been sealed, or if the vector would take an
+    /// arena created with `with_limit` past its limit.
+    pub fn append_vec(&self, items: Vec<T>) -> std::ops::Range<usize> {
+        let start = self.len();
+        if items.is_empty() {
+            return start..start;
+        }
+
+        let (hooks, new_chunk) = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let chunks = &mut *chunks_borrow;
+            assert!(!chunks.sealed, "can't allocate in an arena which has been sealed");
+
+            let len = items.len();
+            // Zero-sized objects would get a slot for every possible one.
+            let capacity = if std::mem::size_of::<T>() == 0 { len } else { items.capacity() };
+            if let Some(limit) = chunks.limit {
+                let needed = match limit {
+                    Limit::Objects(_) => len,
+                    Limit::Bytes(_) => capacity
+                };
+                if let Err(error) = limit::chunk_capacity(limit, chunks, capacity, needed) {
+                    panic!("{}", error);
+                }
+            }
+
+            let mut chunk = Chunk::new(Items::Heap(items), capacity);
+            if chunks.table.is_some() {
+                chunk.slots = Slots::Table(Vec::with_capacity(capacity));
+            }
+            chunks.list.push(chunk);
+
+            let index = chunks.list.len() - 1;
+            for _ in 0..len {
+                chunks.push_slot(index, 0);
+            }
+            #[cfg(feature = "futures")]
+            stream::notify(chunks);
+
+            (chunks.hooks.clone(), Some(capacity))
+        };
+        hooks::run(hooks, None, new_chunk);
+
+        start..self.len()
+    }
+
+    /// Store an object in the arena, returning a reference counted
+    /// pointer to it.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    /// 
+    /// let arena = Arena::new();
+    /// let foo = arena.alloc([0; 256]);
+    /// let bar = arena.alloc([1; 256]);
+    /// let baz = bar.clone();
+    /// 
+    /// assert_eq!(foo[0], 0);
+    /// assert_eq!(bar[0], 1);
+    /// assert_eq!(baz[0], 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has been sealed, or if an arena created with
+    /// `with_limit` has reached its limit.
+    pub fn alloc(&self, value: T) -> Rc<T> {
+        match self.try_alloc(value) {
+            Ok(rc) => rc,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    /// Store an object in the arena like `alloc`, unless that would take an
+    /// arena created with `with_limit` past its limit, or the memory for a
+    /// new chunk can't be allocated.
+    pub fn try_alloc(&self, value: T) -> Result<Rc<T>, ArenaError> {
+        let pushed = self.chunks.borrow_mut().try_push(value);
+        let value = match pushed {
+            Ok((ptr, slot)) => {
+                let rc = Rc {
+                    chunks: self.chunks.clone(),
+                    _ptr: ptr,
+                    slot
+                };
+                #[cfg(feature = "alloc-backtraces")]
+                leaks::record(&rc);
+
+                return Ok(rc);
+            }
+            Err(value) => value
+        };
+
+        if let Some(rc) = self.recycle() {
+            // The old object is dropped once the arena is no longer borrowed.
+            let _old = std::mem::replace(unsafe { &mut *rc.ptr() }, value);
+            #[cfg(feature = "alloc-backtraces")]
+            leaks::record(&rc);
+            hooks::run(self.chunks.borrow().hooks.clone(), Some(&rc), None);
+            return Ok(rc);
+        }
+
+        let num_chunks = self.chunks.borrow().list.len();
+        self.try_make_room(1)?;
+
+        let (rc, hooks, new_chunk) = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let chunk_index = chunks_borrow.chunk_with_space();
+
+            chunks_borrow.list[chunk_index].items.push(value);
+            let (ptr, slot) = chunks_borrow.push_slot(chunk_index, 1);
+            #[cfg(feature = "futures")]
+            stream::notify(&mut chunks_borrow);
+
+            let rc = Rc {
+                chunks: self.chunks.clone(),
+                _ptr: ptr,
+                slot
+            };
+
+            (rc, chunks_borrow.hooks.clone(), chunks_borrow.new_chunk_since(num_chunks))
+        };
+        #[cfg(feature = "alloc-backtraces")]
+        leaks::record(&rc);
+        hooks::run(hooks, Some(&rc), new_chunk);
+
+        Ok(rc)
+    }
+
+    /// Store `n` objects in the arena, constructing each from its position in
+    /// the batch. Space for all of them is made at once, so they end up next
+    /// to each other unless `f` allocates into the arena too.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(1);
+    /// let grid = arena.alloc_n(64, |i| (i % 8, i / 8));
+    ///
+    /// assert_eq!(*grid[10], (2, 1));
+    /// assert_eq!(arena.chunks().count(), 2);
+    /// ```
+    pub fn alloc_n<F: FnMut(usize) -> T>(&self, n: usize, mut f: F) -> Vec<Rc<T>> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("alloc_n", count = n).entered();
+
+        let (hooks, new_chunk) = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let num_chunks = chunks_borrow.list.len();
+            chunks_borrow.reserve(n);
+
+            (chunks_borrow.hooks.clone(), chunks_borrow.new_chunk_since(num_chunks))
+        };
+        hooks::run(hooks, None, new_chunk);
+
+        (0..n).map(|i| self.alloc(f(i))).collect()
+    }
+
+    /// Make room for at least `additional` more objects next to each other,
+    /// so that allocating them won't need any more memory. Unlike the rest
+    /// of the arena, this fails rather than aborting if the memory can't be
+    /// allocated, after giving the out-of-memory hook a chance to free some.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, ArenaError};
+    ///
+    /// let arena = Arena::<u64>::new();
+    /// assert_eq!(arena.try_reserve(usize::MAX), Err(ArenaError::AllocationFailed));
+    ///
+    /// arena.try_reserve(100).unwrap();
+    /// let first: *const u64 = &*arena.alloc(0);
+    /// let last: *const u64 = &*arena.alloc_n(99, |i| i as u64)[98];
+    /// assert_eq!(last, first.wrapping_add(99));
+    /// ```
+    pub fn try_reserve(&self, additional: usize) -> Result<(), ArenaError> {
+        let num_chunks = self.chunks.borrow().list.len();
+        self.try_make_room(additional)?;
+
+        let (hooks, new_chunk) = {
+            let chunks = self.chunks.borrow();
+            (chunks.hooks.clone(), chunks.new_chunk_since(num_chunks))
         };
-        chunk.push(value);
-        let new_item_ref = &mut chunk[next_item_index];
+        hooks::run(hooks, None, new_chunk);
+
+        Ok(())
+    }
+
+    /// Free the chunks at the end of the arena which don't hold any objects,
+    /// such as one made by `try_reserve` which was never used. The
+    /// first chunk is always kept. Unlike `Vec::shrink_to_fit`, this can't
+    /// give back the room after the last object of a chunk, since shrinking
+    /// the chunk's memory could move the objects in it.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(4);
+    /// arena.alloc(1);
+    /// arena.try_reserve(1000).unwrap();
+    /// assert_eq!(arena.chunks().count(), 2);
+    ///
+    /// arena.free_unused_chunks();
+    /// assert_eq!(arena.chunks().count(), 1);
+    /// ```
+    pub fn free_unused_chunks(&self) {
+        let mut chunks = self.chunks.borrow_mut();
+        let unused = |chunk: &Chunk<T>| chunk.items.is_empty() && chunk.slots.len() == 0 && !chunk.reserved;
+
+        while chunks.list.len() > 1 && unused(chunks.list.last().unwrap()) {
+            chunks.list.pop();
+        }
+
+        let last = chunks.list.len() - 1;
+        let len = chunks.list[last].items.len();
+        if let Some(ref pool) = chunks.pool {
+            pool.truncate(last, len);
+        }
+    }
+
+    /// Make room for `additional` more objects in the last chunk, running
+    /// the out-of-memory hook if that fails.
+    fn try_make_room(&self, additional: usize) -> Result<(), ArenaError> {
+        let reserved = self.chunks.borrow_mut().try_reserve(additional);
+        if reserved != Err(ArenaError::AllocationFailed) {
+            return reserved;
+        }
+
+        let hooks = self.chunks.borrow().hooks.clone();
+        if hooks::out_of_memory(hooks, additional) {
+            self.chunks.borrow_mut().try_reserve(additional)
+        } else {
+            reserved
+        }
+    }
+
+    /// Store each object produced by `iter` as it's pulled from the returned
+    /// iterator, which yields the handles.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let mut words = arena.alloc_iter("a b c".split(' '));
+    ///
+    /// assert_eq!(*words.next().unwrap(), "a");
+    /// assert_eq!(arena.len(), 1);
+    /// assert_eq!(words.count(), 2);
+    /// ```
+    pub fn alloc_iter<'a, I>(&'a self, iter: I) -> impl Iterator<Item = Rc<T>> + 'a
+        where I: IntoIterator<Item = T>, I::IntoIter: 'a
+    {
+        iter.into_iter().map(move |value| self.alloc(value))
+    }
+
+    /// Store an object in the arena, returning a plain reference to it which
+    /// lives as long as the borrow of the arena, like `typed_arena` does.
+    ///
+    /// The object counts as referenced until the arena is dropped, so it
+    /// will never be removed by `rollback`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let foo: &usize = arena.alloc_ref(1);
+    /// let bar = arena.alloc(2);
+    ///
+    /// assert_eq!(*foo + *bar, 3);
+    /// ```
+    pub fn alloc_ref(&self, value: T) -> &T {
+        let rc = self.alloc(value);
+        rc.pin();
+
+        // The object can't be dropped while we hold the arena, since its
+        // strong count never returns to zero.
+        unsafe { &*rc.ptr() }
+    }
+
+    /// Store an object in the arena which is constructed by a closure
+    /// that is given a `Weak` pointer to the object itself, mirroring
+    /// `std::rc::Rc::new_cyclic`. The `Weak` cannot be upgraded until the
+    /// closure returns, but it may be cloned and stored in the object.
+    ///
+    /// The closure may allocate other objects in the arena; they will be
+    /// visited after this one by `each`.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Weak};
+    ///
+    /// struct Node {
+    ///     me: Weak<Node>
+    /// }
+    ///
+    /// let arena = Arena::new();
+    /// let node = arena.alloc_cyclic(|me| {
+    ///     assert!(me.upgrade().is_none());
+    ///     Node { me: me.clone() }
+    /// });
+    ///
+    /// assert!(node.me.upgrade().is_some());
+    /// ```
+    pub fn alloc_cyclic<F: FnOnce(&Weak<T>) -> T>(&self, f: F) -> Rc<T> {
+        let (chunk_index, item_index, ptr, slot, new_chunk) = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let num_chunks = chunks_borrow.list.len();
+            let chunk_index = chunks_borrow.chunk_with_space();
+            chunks_borrow.list[chunk_index].reserved = true;
+
+            let item_index = chunks_borrow.list[chunk_index].items.len();
+            let (ptr, slot) = chunks_borrow.push_slot(chunk_index, 0);
+
+            (chunk_index, item_index, ptr, slot, chunks_borrow.new_chunk_since(num_chunks))
+        };
+
+        let reservation = Reservation {
+            chunks: &self.chunks,
+            chunk: chunk_index
+        };
+
+        let weak = Weak::new_handle(std::rc::Rc::downgrade(&self.chunks),
+                                    ptr,
+                                    slot,
+                                    Some((chunk_index, item_index)));
+
+        let value = f(&weak);
+        {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let chunk = &mut chunks_borrow.list[chunk_index];
+            debug_assert_eq!(chunk.items.len(), item_index);
+            chunk.items.push(value);
+            chunk.placed(item_index);
+            #[cfg(feature = "futures")]
+            stream::notify(&mut chunks_borrow);
+        }
+        drop(reservation);
+
+        let rc = Rc::new_handle(self.chunks.clone(), ptr, slot);
+        #[cfg(feature = "alloc-backtraces")]
+        leaks::record(&rc);
+        hooks::run(self.chunks.borrow().hooks.clone(), Some(&rc), new_chunk);
+
+        rc
+    }
+
+    /// Get the number of objects currently placed in the arena.
+    pub fn len(&self) -> usize {
+        let chunks = self.chunks.borrow();
+
+        chunks.list.iter().map(|a| a.items.len()).sum()
+    }
+
+    /// Returns `true` if no objects have been placed in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of `Weak`s to all of the objects in the arena, not
+    /// counting weak handles to the arena itself.
+    pub fn weak_count(&self) -> usize {
+        let chunks = self.chunks.borrow();
+
+        chunks.list.iter().flat_map(|chunk| chunk.slots.iter_from(0)).map(|slot| slot.weak.get()).sum()
+    }
+
+    /// Get a handle to the object which was allocated first, if there is one.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let log = Arena::with_capacity(1);
+    /// assert!(log.first().is_none());
+    ///
+    /// for record in ["started", "processed", "stopped"].iter() {
+    ///     log.alloc(*record);
+    /// }
+    /// assert_eq!(*log.first().unwrap(), "started");
+    /// assert_eq!(*log.last().unwrap(), "stopped");
+    /// ```
+    pub fn first(&self) -> Option<Rc<T>> {
+        let chunks = self.chunks.borrow();
+        let chunk = chunks.list.iter().find(|chunk| !chunk.items.is_empty())?;
+        let (ptr, slot) = chunk.handle(0);
+
+        Some(Rc::new_handle(self.chunks.clone(), ptr, slot))
+    }
+
+    /// Get a handle to the object which was allocated last, if there is one.
+    pub fn last(&self) -> Option<Rc<T>> {
+        let chunks = self.chunks.borrow();
+        let chunk = chunks.list.iter().rev().find(|chunk| !chunk.items.is_empty())?;
+        let (ptr, slot) = chunk.handle(chunk.items.len() - 1);
+
+        Some(Rc::new_handle(self.chunks.clone(), ptr, slot))
+    }
+
+    /// Returns `true` if `rc` points at an object in this arena, rather than
+    /// in some other one.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let other = Arena::new();
+    /// let foo = arena.alloc(1);
+    ///
+    /// assert!(arena.owns(&foo));
+    /// assert!(arena.clone().owns(&foo));
+    /// assert!(!other.owns(&foo));
+    /// ```
+    pub fn owns(&self, rc: &Rc<T>) -> bool {
+        std::rc::Rc::ptr_eq(&self.chunks, &rc.chunks)
+    }
+
+    /// Create a weak handle to this arena, for registries which shouldn't
+    /// keep the arena alive.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let weak = arena.downgrade();
+    /// weak.upgrade().unwrap().alloc(1);
+    /// assert_eq!(arena.len(), 1);
+    ///
+    /// drop(arena);
+    /// assert!(weak.upgrade().is_none());
+    /// ```
+    pub fn downgrade(&self) -> ArenaWeak<T> {
+        ArenaWeak {
+            chunks: std::rc::Rc::downgrade(&self.chunks)
+        }
+    }
+
+    /// The pointer to the object at position `index` in allocation order and
+    /// its slot, if there is one.
+    fn locate(&self, index: usize) -> Option<(*mut T, *const Slot)> {
+        let chunks = self.chunks.borrow();
+
+        let mut index = index;
+        for chunk in chunks.list.iter() {
+            if index < chunk.items.len() {
+                return Some(chunk.handle(index));
+            }
+            index -= chunk.items.len();
+        }
+
+        None
+    }
+
+    /// Get the position of an object in the order the arena's objects were
+    /// allocated in, which is the order they are visited by `each`. This
+    /// returns `None` if the handle belongs to another arena.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(1);
+    /// let handles: Vec<_> = (0..10).map(|i| arena.alloc(i)).collect();
+    ///
+    /// assert_eq!(arena.index_of(&handles[7]), Some(7));
+    /// assert_eq!(Arena::new().index_of(&handles[7]), None);
+    /// ```
+    pub fn index_of(&self, rc: &Rc<T>) -> Option<usize> {
+        if !self.owns(rc) {
+            return None;
+        }
+
+        let chunks = self.chunks.borrow();
+        let mut preceding = 0;
+
+        for chunk in chunks.list.iter() {
+            if let Some(index) = chunk.index_of(rc.ptr(), rc.slot) {
+                return Some(preceding + index);
+            }
+            preceding += chunk.items.len();
+        }
+
+        None
+    }
+
+    /// Get a reference to the object at position `index` in allocation
+    /// order, without checking that there is one or borrowing the arena.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(2);
+    /// let ops: Vec<_> = (0..5).map(|i| arena.alloc(i * 10)).collect();
+    ///
+    /// assert_eq!(unsafe { *arena.get_unchecked(3) }, 30);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `index` must be less than `len()`, and the object must not be dropped
+    /// or changed while the reference is alive, whether by `rollback`,
+    /// `compact`, an object pool recycling it or a `CursorMut`. It is enough
+    /// to hold an `Rc` to the object.
+    pub unsafe fn get_unchecked(&self, index: usize) -> &T {
+        let chunks = &*self.chunks.as_ptr();
+        let mut index = index;
+
+        for chunk in chunks.list.iter() {
+            let len = chunk.items.len();
+            if index < len {
+                return &*chunk.items.as_ptr().add(index);
+            }
+            index -= len;
+        }
+
+        std::hint::unreachable_unchecked()
+    }
+
+    /// Iterate over the objects in the arena, accepting a closure which
+    /// will be passed a reference to the Rc of the object. This may be
+    /// deprecated in favor of a (safe) iterator API in the future.
+    ///
+    /// This will always iterate in the order that the objects were
+    /// allocated.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    /// 
+    /// let arena = Arena::new();
+    /// arena.alloc("Hello,");
+    /// arena.alloc(" ");
+    /// arena.alloc("world!\n");
+    /// 
+    /// arena.each(|obj| {
+    ///     print!("{}", obj);
+    /// });
+    /// ```
+    pub fn each<F: for<'a> FnMut(&'a Rc<T>)>(&self, mut f: F) {
+        let chunks = self.chunks.borrow();
+
+        for chunk in chunks.list.iter() {
+            for index in 0..chunk.items.len() {
+                let (ptr, slot) = chunk.handle(index);
+                let rc = Rc::new_handle(self.chunks.clone(), ptr, slot);
+
+                f(&rc);
+            }
+        }
+    }
+
+    /// Like `each`, but stops at the first object for which `f` returns an
+    /// error, and returns that error. A search can hand back what it found
+    /// this way, as with `ControlFlow::Break` in `Iterator::try_for_each`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// for word in "the quick brown fox".split(' ') {
+    ///     arena.alloc(word);
+    /// }
+    ///
+    /// let mut visited = 0;
+    /// let found = arena.try_each(|word| {
+    ///     visited += 1;
+    ///     if word.starts_with('b') { Err(word.clone()) } else { Ok(()) }
+    /// });
+    ///
+    /// assert_eq!(*found.unwrap_err(), "brown");
+    /// assert_eq!(visited, 3);
+    /// ```
+    pub fn try_each<E, F: for<'a> FnMut(&'a Rc<T>) -> Result<(), E>>(&self, mut f: F) -> Result<(), E> {
+        let chunks = self.chunks.borrow();
+
+        for chunk in chunks.list.iter() {
+            for index in 0..chunk.items.len() {
+                let (ptr, slot) = chunk.handle(index);
+                let rc = Rc::new_handle(self.chunks.clone(), ptr, slot);
+
+                f(&rc)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over handles to the objects in the arena in the order given
+    /// by `cmp`. Objects which compare equal are visited in the order they
+    /// were allocated.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc("banana");
+    /// arena.alloc("apple");
+    /// arena.alloc("cherry");
+    ///
+    /// let sorted: Vec<_> = arena.iter_sorted_by(|a, b| a.cmp(b)).map(|s| *s).collect();
+    /// assert_eq!(sorted, ["apple", "banana", "cherry"]);
+    /// ```
+    pub fn iter_sorted_by<F>(&self, mut cmp: F) -> impl Iterator<Item = Rc<T>>
+        where F: FnMut(&T, &T) -> std::cmp::Ordering
+    {
+        let mut handles = Vec::with_capacity(self.len());
+        self.each(|rc| handles.push(Some(rc.clone())));
+
+        // The arena isn't borrowed anymore, so `cmp` is free to use it.
+        let mut indices: Vec<usize> = (0..handles.len()).collect();
+        indices.sort_by(|&a, &b| cmp(handles[a].as_ref().unwrap(), handles[b].as_ref().unwrap()));
+
+        indices.into_iter().map(move |index| handles[index].take().unwrap())
+    }
+
+    /// Iterate over the chunks of the arena, each of which holds a contiguous
+    /// run of objects in allocation order.
+    ///
+    /// The arena is borrowed while a slice is held, so allocating into it
+    /// or rolling it back in the meantime panics.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(2);
+    /// for i in 0..5 {
+    ///     arena.alloc(i);
+    /// }
+    ///
+    /// let sums: Vec<usize> = arena.chunks().map(|chunk| chunk.iter().sum()).collect();
+    /// assert_eq!(sums, [0 + 1, 2 + 3 + 4]);
+    /// ```
+    pub fn chunks<'a>(&'a self) -> impl Iterator<Item = std::cell::Ref<'a, [T]>> + 'a {
+        (0..).map_while(move |index| {
+            std::cell::Ref::filter_map(self.chunks.borrow(), |chunks| {
+                chunks.list.get(index).map(|chunk| &chunk.items[..])
+            }).ok()
+        })
+    }
+
+    /// Get every object in the arena as a single slice, if they all live in
+    /// the same chunk, as they do until the arena grows past its initial
+    /// capacity. Like `chunks`, this borrows the arena.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(3);
+    /// arena.alloc(1);
+    /// arena.alloc(2);
+    /// arena.alloc(3);
+    /// assert_eq!(&*arena.as_single_slice().unwrap(), &[1, 2, 3]);
+    ///
+    /// arena.alloc(4);
+    /// assert!(arena.as_single_slice().is_none());
+    /// ```
+    pub fn as_single_slice(&self) -> Option<std::cell::Ref<'_, [T]>> {
+        std::cell::Ref::filter_map(self.chunks.borrow(), |chunks| {
+            let mut occupied = chunks.list.iter().filter(|chunk| !chunk.items.is_empty());
+
+            match (occupied.next(), occupied.next()) {
+                (Some(chunk), None) => Some(&chunk.items[..]),
+                (None, _) => Some(&chunks.list[0].items[..]),
+                _ => None
+            }
+        }).ok()
+    }
+
+    /// Get a pointer to the first object of each chunk and the number of
+    /// objects in it, for handing the objects to foreign code or vectored IO
+    /// without copying them.
+    ///
+    /// The pointers stay valid for as long as the objects do: objects are
+    /// never moved, but may be dropped by `rollback`, or moved by `compact`.
+    /// The objects are shared with every handle to them, so they must not be
+    /// written through the pointers.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(2);
+    /// let handles: Vec<_> = (0..5u32).map(|i| arena.alloc(i)).collect();
+    ///
+    /// let parts = arena.as_raw_parts();
+    /// assert_eq!(parts.iter().map(|&(_, len)| len).collect::<Vec<_>>(), [2, 3]);
+    /// let second = unsafe { std::slice::from_raw_parts(parts[1].0, parts[1].1) };
+    /// assert_eq!(second, [2, 3, 4]);
+    /// ```
+    pub fn as_raw_parts(&self) -> Vec<(*const T, usize)> {
+        let chunks = self.chunks.borrow();
+
+        chunks.list.iter().map(|chunk| (chunk.items.as_ptr(), chunk.items.len())).collect()
+    }
+
+    /// Record the current end of the arena, so that everything allocated
+    /// after this point can later be dropped with `rollback`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let chunks = self.chunks.borrow();
+
+        Checkpoint {
+            chunk: chunks.list.len() - 1,
+            len: chunks.list[chunks.list.len() - 1].items.len()
+        }
+    }
+
+    /// Drop every object allocated since `checkpoint` was taken, making room
+    /// for new objects in their place. This fails, leaving the arena
+    /// untouched, if any `Rc` or `Weak` still points at one of those objects.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(1);
+    ///
+    /// let checkpoint = arena.checkpoint();
+    /// let speculative = arena.alloc(2);
+    /// assert!(arena.rollback(checkpoint).is_err());
+    ///
+    /// drop(speculative);
+    /// arena.rollback(checkpoint).unwrap();
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has already been rolled back past `checkpoint`.
+    pub fn rollback(&self, checkpoint: Checkpoint) -> Result<(), ArenaError> {
+        // The objects are only dropped once the borrow is released, in case
+        // their destructors want to use the arena.
+        self.remove_since(checkpoint, false).map(drop)
+    }
+
+    /// Remove every object allocated since `checkpoint`, unless any of them
+    /// is still in use, and return them. Unless they're kept, objects which
+    /// don't need to be dropped are forgotten instead.
+    fn remove_since(&self, checkpoint: Checkpoint, keep: bool) -> Result<(Vec<T>, Vec<Vec<T>>), ArenaError> {
+        let removed = {
+            let mut chunks_borrow = self.chunks.borrow_mut();
+            let chunks_borrow = &mut *chunks_borrow;
+            let chunks = &mut chunks_borrow.list;
+
+            assert!(checkpoint.chunk < chunks.len() &&
+                    checkpoint.len <= chunks[checkpoint.chunk].items.len(),
+                    "checkpoint is past the end of the arena");
+
+            let pooled = chunks_borrow.pool.as_ref().map(|pool| pool.free_slots()).unwrap_or_default();
+            let mut removed_pooled = std::collections::HashSet::new();
+            {
+                let (kept, removed) = chunks.split_at(checkpoint.chunk + 1);
+                let partial = &kept[checkpoint.chunk];
+
+                // Objects which are only held by the pool aren't in use.
+                let mut in_use = |slot: &Slot| {
+                    let ptr = slot as *const Slot;
+                    if pooled.contains(&ptr) {
+                        removed_pooled.insert(ptr);
+                        slot.strong.get() != 1 || slot.weak.get() != 0
+                    } else {
+                        slot.is_referenced()
+                    }
+                };
+
+                // Frozen objects are shared with a `FrozenArena`.
+                let frozen = |chunk: &Chunk<T>, from: usize| chunk.items.is_frozen() && chunk.items.len() > from;
+
+                let in_use = partial.reserved || frozen(partial, checkpoint.len) ||
+                    partial.slots.iter_from(checkpoint.len).any(&mut in_use) ||
+                    removed.iter().any(|chunk| chunk.reserved || frozen(chunk, 0) ||
+                                       chunk.slots.iter_from(0).any(&mut in_use));
+
+                if in_use {
+                    return Err(ArenaError::LiveHandles);
+                }
+            }
+
+            if let Some(ref pool) = chunks_borrow.pool {
+                pool.forget(&removed_pooled);
+                pool.truncate(checkpoint.chunk, checkpoint.len);
+            }
+
+            for chunk in checkpoint.chunk + 1..chunks.len() {
+                chunks_borrow.truncate_slots(chunk, 0);
+            }
+            chunks_borrow.truncate_slots(checkpoint.chunk, checkpoint.len);
+
+            // Frozen objects were ruled out above, but frozen chunks can't
+            // be written to even when nothing is removed from them.
+            #[cfg(feature = "zeroize")]
+            if let (Some(wipe), false) = (chunks_borrow.wipe, keep) {
+                for (index, chunk) in chunks_borrow.list.iter_mut().enumerate().skip(checkpoint.chunk) {
+                    let from = if index == checkpoint.chunk { checkpoint.len } else { 0 };
+                    if !chunk.items.is_frozen() {
+                        chunk.items[from..].iter_mut().for_each(wipe);
+                    }
+                }
+            }
+
+            // Chunks which don't live on the heap have to be emptied
+            // explicitly, as they may outlive the arena.
+            #[cfg(feature = "zeroize")]
+            let wiping = chunks_borrow.wipe.is_some();
+            let take = |items: &mut Items<T>, len| {
+                if !keep {
+                    return items.truncate(len);
+                }
+
+                #[cfg(feature = "zeroize")]
+                let (ptr, old_len) = (items.as_ptr(), items.len());
+                let taken = items.split_off(len);
+                // Objects which are moved out are copied, leaving their bytes
+                // behind, unless the memory they were in went with them.
+                #[cfg(feature = "zeroize")]
+                if wiping && !items.is_frozen() && items.as_ptr() == ptr {
+                    unsafe { wipe::wipe_moved(ptr.add(len) as *mut T, old_len - len) };
+                }
+
+                taken
+            };
+            let chunks = &mut chunks_borrow.list;
+            let removed: Vec<Vec<T>> = chunks.split_off(checkpoint.chunk + 1)
+                                             .iter_mut()
+                                             .map(|chunk| take(&mut chunk.items, 0))
+                                             .collect();
+
+            let truncated = take(&mut chunks[checkpoint.chunk].items, checkpoint.len);
+            #[cfg(feature = "poison")]
+            poison::poison_from(&mut chunks[checkpoint.chunk], checkpoint.len);
+
+            (truncated, removed)
+        };
+
+        Ok(removed)
+    }
+
+    /// Drop every object but the first `len` in allocation order, as if the
+    /// arena had been rolled back to a checkpoint taken when it held that
+    /// many. This fails, leaving the arena untouched, if any `Rc` or `Weak`
+    /// still points at one of those objects, and does nothing if the arena
+    /// doesn't hold more than `len` objects.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(1);
+    ///
+    /// let mark = arena.len();
+    /// for i in 0..10 {
+    ///     arena.alloc(i);
+    /// }
+    /// arena.truncate(mark).unwrap();
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn truncate(&self, len: usize) -> Result<(), ArenaError> {
+        match self.checkpoint_at(len) {
+            Some(checkpoint) => self.rollback(checkpoint),
+            None => Ok(())
+        }
+    }
+
+    /// Move every object from position `index` onwards in allocation order
+    /// into a new arena, in the same order, so that a batch can be handed
+    /// off while the rest of the arena is kept. Like `truncate`, this fails,
+    /// leaving the arena untouched, if any `Rc` or `Weak` still points at
+    /// one of those objects, since handles can't follow their objects into
+    /// another arena. The new arena takes over the chunks which are moved
+    /// whole, and is a plain one, even if this one is a pool or indirect.
+    /// Arenas whose chunks are in a file fail with `InvalidConfiguration`,
+    /// since their objects can't be moved out.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// for i in 0..10 {
+    ///     arena.alloc(i);
+    /// }
+    ///
+    /// let batch = arena.split_off(6).unwrap();
+    /// assert_eq!(batch.to_vec(), [6, 7, 8, 9]);
+    /// assert_eq!(arena.len(), 6);
+    /// ```
+    pub fn split_off(&self, index: usize) -> Result<Arena<T>, ArenaError> {
+        #[cfg(feature = "mmap")]
+        {
+            if self.chunks.borrow().file.is_some() {
+                return Err(ArenaError::InvalidConfiguration);
+            }
+        }
+
+        let (truncated, removed) = match self.checkpoint_at(index) {
+            Some(checkpoint) => self.remove_since(checkpoint, true)?,
+            None => (vec![], vec![])
+        };
+
+        let mut batches = std::iter::once(truncated).chain(removed).filter(|batch| !batch.is_empty());
+        let arena = match batches.next() {
+            Some(first) => Arena::from_vec(first),
+            None => Arena::new()
+        };
+        for batch in batches {
+            arena.append_vec(batch);
+        }
+
+        {
+            let (from, mut to) = (self.chunks.borrow(), arena.chunks.borrow_mut());
+            to.reverse_drop = from.reverse_drop;
+            #[cfg(feature = "zeroize")]
+            {
+                to.wipe = from.wipe;
+            }
+        }
+
+        Ok(arena)
+    }
+
+    /// The checkpoint the arena would have had when it held `len` objects,
+    /// unless it holds fewer.
+    fn checkpoint_at(&self, len: usize) -> Option<Checkpoint> {
+        let chunks = self.chunks.borrow();
+        let mut preceding = 0;
+
+        chunks.list.iter().enumerate().find(|&(_, chunk)| {
+            preceding += chunk.items.len();
+            preceding >= len
+        }).map(|(chunk, _)| Checkpoint {
+            chunk,
+            len: len + chunks.list[chunk].items.len() - preceding
+        })
+    }
+}
+
+impl<T: Clone> Arena<T> {
+    /// Create a new arena holding a copy of every object in this one, in the
+    /// same order. Unlike `clone`, which gives another handle to the same
+    /// arena, the copies are independent of the originals.
+    ///
+    /// The new arena is a plain one, even if this one is a pool or indirect.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(1);
+    ///
+    /// let copy = arena.deep_clone();
+    /// copy.alloc(2);
+    /// assert_eq!(arena.len(), 1);
+    /// assert_eq!(copy.len(), 2);
+    /// ```
+    pub fn deep_clone(&self) -> Arena<T> {
+        Arena::from_vec(self.to_vec())
+    }
+
+    /// Clone the objects into a vector, in the order they were allocated.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(1);
+    /// arena.alloc("a");
+    /// arena.alloc("b");
+    ///
+    /// assert_eq!(arena.to_vec(), ["a", "b"]);
+    /// ```
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        self.each(|rc| values.push(T::clone(rc)));
+
+        values
+    }
+
+    /// Like `deep_clone`, but also return a handle to each copy, indexed by
+    /// the position of the original as given by `index_of`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc("a");
+    /// let b = arena.alloc("b");
+    ///
+    /// let (copy, copies) = arena.deep_clone_mapped();
+    /// let copied_b = &copies[arena.index_of(&b).unwrap()];
+    /// assert_eq!(**copied_b, "b");
+    /// assert!(copy.owns(copied_b));
+    /// ```
+    pub fn deep_clone_mapped(&self) -> (Arena<T>, Vec<Rc<T>>) {
+        let copy = self.deep_clone();
+        let copies = copy.iter().collect();
+
+        (copy, copies)
+    }
+}
+
+/// A position in an arena recorded by `Arena::checkpoint`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    chunk: usize,
+    len: usize
+}
 
-        Rc {
-            chunks: self.chunks.clone(),
-            _ptr: new_item_ref
+/// The error returned by the fallible operations of an arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArenaError {
+    /// The arena would go past the limit it was created with.
+    LimitExceeded,
+    /// The memory for a new chunk couldn't be allocated.
+    AllocationFailed,
+    /// The capacity of a new chunk would overflow a `usize`.
+    CapacityOverflow,
+    /// The options given to an `ArenaBuilder` can't be combined.
+    InvalidConfiguration,
+    /// Objects can't be dropped from the arena because something still
+    /// refers to them.
+    LiveHandles,
+    /// A handle doesn't refer to an object in the arena it was used with.
+    StaleHandle
+}
+
+impl std::fmt::Display for ArenaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            ArenaError::LimitExceeded => write!(f, "the arena's limit would be exceeded"),
+            ArenaError::AllocationFailed => write!(f, "memory for the arena couldn't be allocated"),
+            ArenaError::CapacityOverflow => write!(f, "the arena's capacity would overflow"),
+            ArenaError::InvalidConfiguration => write!(f, "the arena's options can't be combined"),
+            ArenaError::LiveHandles => write!(f, "objects in the arena are still referenced"),
+            ArenaError::StaleHandle => write!(f, "the handle doesn't belong to the arena")
         }
     }
+}
 
-    /// Get the number of objects currently placed in the arena.
-    pub fn len(&self) -> usize {
-        let chunks = self.chunks.borrow();
+impl std::error::Error for ArenaError {}
 
-        chunks.iter().map(|a| a.len()).fold(0, |a, b| a+b)
-    }
+/// Declare types which each stand for a lazily created, thread-local arena,
+/// so that objects can be allocated without passing an `Arena` around.
+///
+/// Each declared type gets `alloc`, `with` and `arena` functions that act on
+/// the calling thread's arena.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// thread_local_arena! {
+///     /// The arena for every string in the program.
+///     pub struct Strings: String;
+///     struct Numbers: usize;
+/// }
+///
+/// fn main() {
+///     let hello = Strings::alloc("Hello".to_string());
+///     Numbers::alloc(1);
+///
+///     assert_eq!(*hello, "Hello");
+///     assert_eq!(Strings::arena().len(), 1);
+///     assert_eq!(Numbers::with(|arena| arena.len()), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! thread_local_arena {
+    ($($(#[$attr:meta])* $vis:vis struct $name:ident: $ty:ty;)*) => {$(
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        $vis struct $name;
 
-    /// Iterate over the objects in the arena, accepting a closure which
-    /// will be passed a reference to the Rc of the object. This may be
-    /// deprecated in favor of a (safe) iterator API in the future.
-    ///
-    /// This will always iterate in the order that the objects were
-    /// allocated.
-    ///
-    /// ```rust
-    /// use rc_arena::Arena;
-    /// 
-    /// let arena = Arena::new();
-    /// arena.alloc("Hello,");
-    /// arena.alloc(" ");
-    /// arena.alloc("world!\n");
-    /// 
-    /// arena.each(|obj| {
-    ///     print!("{}", obj);
-    /// });
-    /// ```
-    pub fn each<F: for<'a> FnMut(&'a Rc<T>)>(&self, mut f: F) {
-        use std::ptr;
+        impl $name {
+            /// Call a closure with this thread's arena.
+            #[allow(dead_code)]
+            $vis fn with<R, F: FnOnce(&$crate::Arena<$ty>) -> R>(f: F) -> R {
+                thread_local!(static ARENA: $crate::Arena<$ty> = $crate::Arena::new());
 
-        let chunks = self.chunks.borrow();
+                ARENA.with(f)
+            }
 
-        let mut rc = Rc {
-            chunks: self.chunks.clone(),
-            _ptr: ptr::null_mut()
-        };
+            /// Store an object in this thread's arena, returning a reference
+            /// counted pointer to it.
+            #[allow(dead_code)]
+            $vis fn alloc(value: $ty) -> $crate::Rc<$ty> {
+                $name::with(|arena| arena.alloc(value))
+            }
+
+            /// Get a handle to this thread's arena.
+            #[allow(dead_code)]
+            $vis fn arena() -> $crate::Arena<$ty> {
+                $name::with(|arena| arena.clone())
+            }
+        }
+    )*}
+}
+
+/// Declare a struct holding an arena for each of a list of types, such as
+/// the allocation context of a compiler with an arena for each kind of node.
+///
+/// Each field is declared with the type of its objects and the name of a
+/// method which stores one in it. The struct gets `new`, `Default` and
+/// `Debug`, along with a `clear` method which replaces every arena with an
+/// empty one, dropping their objects unless an `Rc` to them is still held.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// pub enum Expr {
+///     Number(u64),
+///     Name(rc_arena::Rc<String>)
+/// }
+///
+/// declare_arenas! {
+///     /// Everything allocated while compiling a crate.
+///     pub struct Context {
+///         pub names: String => alloc_name,
+///         exprs: Expr => alloc_expr
+///     }
+/// }
+///
+/// fn main() {
+///     let mut context = Context::new();
+///     let name = context.alloc_name("x".to_string());
+///     context.alloc_expr(Expr::Name(name));
+///     context.alloc_expr(Expr::Number(1));
+///
+///     assert_eq!((context.names.len(), context.exprs.len()), (1, 2));
+///     context.clear();
+///     assert!(context.exprs.is_empty());
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_arenas {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident {
+        $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty => $alloc:ident),* $(,)*
+    }) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $crate::Arena<$ty>),*
+        }
+
+        impl $name {
+            /// Create an empty arena for each type.
+            #[allow(dead_code)]
+            $vis fn new() -> $name {
+                $name {
+                    $($field: $crate::Arena::new()),*
+                }
+            }
 
-        for val in chunks.iter().flat_map(|chunk| chunk.iter()) {
-            rc._ptr = val as *const T as *mut T;
+            $(
+                /// Store an object in its arena, returning a reference
+                /// counted pointer to it.
+                #[allow(dead_code)]
+                $vis fn $alloc(&self, value: $ty) -> $crate::Rc<$ty> {
+                    self.$field.alloc(value)
+                }
+            )*
+
+            /// Replace every arena with an empty one.
+            #[allow(dead_code)]
+            $vis fn clear(&mut self) {
+                $(self.$field = $crate::Arena::new();)*
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name::new()
+            }
+        }
 
-            f(&rc);
+        impl ::std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+                f.debug_struct(stringify!($name))
+                    $(.field(stringify!($field), &self.$field.len()))*
+                    .finish()
+            }
         }
     }
 }
@@ -265,6 +2788,262 @@ fn iterates() {
     assert_eq!(arena.len(), 5);
 }
 
+#[test]
+fn index_of() {
+    let arena = Arena::with_capacity(1);
+    let handles: Vec<_> = (0..10).map(|_| arena.alloc(())).collect();
+    for (i, handle) in handles.iter().enumerate() {
+        assert_eq!(arena.index_of(handle), Some(i));
+    }
+
+    let indirect = Arena::indirect_with_capacity(3);
+    let a = indirect.alloc(0u8);
+    let b = indirect.alloc(1u8);
+    let c = indirect.alloc(2u8);
+    drop(b);
+    assert_eq!(indirect.index_of(&c), Some(2));
+    unsafe { indirect.compact() };
+    assert_eq!(indirect.index_of(&a), Some(0));
+    assert_eq!(indirect.index_of(&c), Some(1));
+    assert_eq!(arena.clone().index_of(&handles[3]), Some(3));
+}
+
+#[test]
+fn zero_sized() {
+    struct Marker(std::rc::Rc<Cell<usize>>);
+
+    impl Drop for Marker {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let arena = Arena::with_capacity(1);
+    let ids: Vec<_> = (0..100).map(|_| arena.alloc(())).collect();
+    assert!(!Rc::ptr_eq(&ids[0], &ids[1]));
+    assert_eq!(arena.index_of(&ids[99]), Some(99));
+    assert_eq!(arena.chunks.borrow().list.iter().map(|chunk| chunk.items.capacity()).min(), Some(usize::MAX));
+
+    let dropped = std::rc::Rc::new(Cell::new(0));
+    let markers = Arena::new();
+    let checkpoint = markers.checkpoint();
+    for _ in 0..10 {
+        markers.alloc(Marker(dropped.clone()));
+    }
+    markers.rollback(checkpoint).unwrap();
+    assert_eq!(dropped.get(), 10);
+}
+
+#[test]
+fn deep_clone() {
+    let arena = Arena::indirect_with_capacity(1);
+    let kept: Vec<_> = (0..10).map(|i| arena.alloc(Cell::new(i))).filter(|i| i.get() % 2 == 1).collect();
+    unsafe { arena.compact() };
+
+    let (copy, copies) = arena.deep_clone_mapped();
+    assert_eq!(copies.len(), kept.len());
+    for (original, copied) in kept.iter().zip(&copies) {
+        copied.set(copied.get() * 10);
+        assert_eq!(original.get() * 10, copied.get());
+        assert_eq!(arena.index_of(original), copy.index_of(copied));
+    }
+    assert_eq!(copy.chunks.borrow().list.len(), 1);
+    assert!(copy.chunks.borrow().table.is_none());
+    assert_eq!(copy.to_vec().iter().map(Cell::get).collect::<Vec<_>>(), [10, 30, 50, 70, 90]);
+}
+
+#[test]
+fn nested_drops() {
+    struct Node {
+        parent: Option<Weak<Node>>,
+        upgraded: std::rc::Rc<Cell<(usize, usize)>>
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            if let Some(ref parent) = self.parent {
+                let (alive, gone) = self.upgraded.get();
+                let parent = parent.clone();
+                self.upgraded.set(match parent.upgrade() {
+                    Some(rc) => {
+                        drop(rc.clone());
+                        (alive + 1, gone)
+                    }
+                    None => (alive, gone + 1)
+                });
+            }
+        }
+    }
+
+    let upgraded = std::rc::Rc::new(Cell::new((0, 0)));
+    let arena = Arena::new();
+    let root = arena.alloc(Node { parent: None, upgraded: upgraded.clone() });
+
+    // Rolled back objects are dropped while the arena is alive.
+    let checkpoint = arena.checkpoint();
+    for _ in 0..3 {
+        arena.alloc(Node { parent: Some(Rc::downgrade(&root)), upgraded: upgraded.clone() });
+    }
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(upgraded.get(), (3, 0));
+
+    // The rest are dropped along with the arena.
+    let children: Vec<_> = (0..2).map(|_| {
+        arena.alloc(Node { parent: Some(Rc::downgrade(&root)), upgraded: upgraded.clone() })
+    }).collect();
+    drop((arena, root, children));
+    assert_eq!(upgraded.get(), (3, 2));
+}
+
+#[test]
+fn downgrade() {
+    let arena = Arena::new();
+    let weak = arena.downgrade();
+    let one = arena.alloc(1);
+    drop(arena);
+
+    // The objects keep the arena alive.
+    let arena = weak.clone().upgrade().unwrap();
+    assert!(arena.owns(&one));
+    drop((arena, one));
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn counts() {
+    let arena = Arena::with_capacity(1);
+    let first = arena.alloc(1);
+    let second = arena.alloc(2);
+    let weak = (Rc::downgrade(&first), Rc::downgrade(&second), Rc::downgrade(&second));
+    let _clone = second.clone();
+    assert_eq!((Rc::strong_count(&second), Rc::weak_count(&second)), (2, 2));
+    assert_eq!(arena.weak_count(), 3);
+
+    drop(second);
+    drop(weak.1);
+    assert_eq!((Rc::strong_count(&first), Rc::weak_count(&first)), (1, 1));
+    assert_eq!(arena.weak_count(), 2);
+}
+
+#[test]
+fn to_std() {
+    let arena = Arena::new();
+    let numbers = arena.alloc(vec![1, 2, 3]);
+    let boxed = Rc::to_box(&numbers);
+    let shared = Rc::to_std_rc(&numbers);
+    assert_eq!((*boxed == *numbers, *shared == *numbers), (true, true));
+
+    let weak = Rc::downgrade(&numbers);
+    let numbers = Rc::take_box(numbers).unwrap_err();
+    drop(weak);
+    assert_eq!(*Rc::take_box(numbers).unwrap(), [1, 2, 3]);
+
+    let frozen = arena.alloc(vec![4]);
+    let _snapshot = arena.freeze();
+    let frozen = Rc::take_box(frozen).unwrap_err();
+    assert_eq!(Rc::arena(&frozen).iter().map(|numbers| numbers.len()).collect::<Vec<_>>(), [0, 1]);
+
+    // References handed out by a borrow of the arena stay valid.
+    let arena = Arena::new();
+    let numbers = arena.alloc(vec![5, 6]);
+    let chunk = arena.chunks().next().unwrap();
+    let numbers = Rc::take_box(numbers).unwrap_err();
+    assert_eq!(chunk[0], [5, 6]);
+    drop(chunk);
+    assert_eq!(*Rc::take_box(numbers).unwrap(), [5, 6]);
+}
+
+#[cfg(feature = "stable_deref_trait")]
+#[test]
+fn stable_deref() {
+    fn address<P: stable_deref_trait::CloneStableDeref<Target = String>>(pointer: &P) -> *const String {
+        &*pointer.clone() as *const String
+    }
+
+    let arena = Arena::with_capacity(1);
+    let name = arena.alloc(String::from("rc_arena"));
+    let before = address(&name);
+    arena.alloc_n(100, |i| i.to_string());
+    assert_eq!(address(&name), before);
+}
+
+#[test]
+fn get_unchecked() {
+    let arena = Arena::with_capacity(1);
+    let handles: Vec<_> = (0..10).map(|i| arena.alloc(i)).collect();
+    for i in 0..handles.len() {
+        assert_eq!(unsafe { *arena.get_unchecked(i) }, i);
+    }
+
+    let indirect = Arena::indirect_with_capacity(1);
+    let kept: Vec<_> = (0..10).map(|i| indirect.alloc(i)).filter(|i| **i % 3 == 0).collect();
+    unsafe { indirect.compact() };
+    for (i, rc) in kept.iter().enumerate() {
+        assert_eq!(unsafe { indirect.get_unchecked(i) }, &**rc);
+    }
+}
+
+#[test]
+fn chunk_slices() {
+    let arena = Arena::with_capacity(1);
+    for i in 0..7 {
+        arena.alloc(i);
+    }
+
+    let chunks: Vec<Vec<usize>> = arena.chunks().map(|chunk| chunk.to_vec()).collect();
+    assert_eq!(chunks, [vec![0], vec![1, 2], vec![3, 4, 5, 6]]);
+
+    let mut slices = arena.chunks();
+    let first = slices.next().unwrap();
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.alloc(7))).is_err());
+    drop(first);
+    drop(slices);
+    arena.alloc(7);
+    assert_eq!(arena.chunks().last().unwrap().len(), 1);
+}
+
+#[test]
+fn single_slice() {
+    let arena = Arena::with_capacity(2);
+    let checkpoint = arena.checkpoint();
+    assert_eq!(arena.as_single_slice().unwrap().len(), 0);
+
+    arena.alloc(1);
+    arena.alloc(2);
+    arena.alloc(3);
+    assert!(arena.as_single_slice().is_none());
+
+    arena.rollback(checkpoint).unwrap();
+    arena.alloc(4);
+    assert_eq!(&*arena.as_single_slice().unwrap(), &[4]);
+
+    // Empty chunks don't count.
+    let arena = Arena::with_capacity(0);
+    arena.alloc(1);
+    assert_eq!(&*arena.as_single_slice().unwrap(), &[1]);
+}
+
+#[test]
+fn iterates_sorted() {
+    let arena: Arena<(usize, usize)> = Arena::with_capacity(2);
+    let checkpoint = arena.checkpoint();
+    for (i, key) in [3, 1, 2, 1, 3].iter().enumerate() {
+        arena.alloc((*key, i));
+    }
+
+    let sorted: Vec<_> = arena.iter_sorted_by(|a, b| a.0.cmp(&b.0)).map(|item| *item).collect();
+    assert_eq!(sorted, [(1, 1), (1, 3), (2, 2), (3, 0), (3, 4)]);
+
+    // Handles which aren't taken from the iterator are released with it.
+    let mut iter = arena.iter_sorted_by(|a, b| b.cmp(a));
+    let first = iter.next().unwrap();
+    drop(iter);
+    assert_eq!(*first, (3, 4));
+    assert_eq!(arena.rollback(checkpoint), Err(ArenaError::LiveHandles));
+    drop(first);
+    arena.rollback(checkpoint).unwrap();
+}
+
 
 #[test]
 fn formatting() {
@@ -278,4 +3057,385 @@ fn formatting() {
     assert_eq!("1", &*format!("{}", test1));
     assert_eq!("2", &*format!("{}", test2));
     assert_eq!("1", &*format!("{:?}", test1));
-}
\ No newline at end of file
+}
+
+#[test]
+fn compares_with_values() {
+    let arena = Arena::new();
+    let number = arena.alloc(5);
+    let string = Arena::new().alloc(String::from("five"));
+
+    assert_eq!(number, 5);
+    assert_eq!(5, number);
+    assert!(number != 6);
+    assert_eq!(string, "five".to_string());
+    assert_eq!("five".to_string(), string);
+
+    let chunked = Arena::with_capacity(1);
+    let whole = Arena::with_capacity(8);
+    for i in 0..5 {
+        chunked.alloc(i);
+        whole.alloc(i);
+    }
+    assert_eq!(chunked, whole);
+    assert_eq!(whole, whole);
+    whole.alloc(5);
+    assert!(chunked != whole);
+    chunked.alloc(6);
+    assert!(chunked != whole);
+}
+
+#[test]
+fn cyclic() {
+    struct Node {
+        value: usize,
+        parent: Option<Weak<Node>>,
+        children: Vec<Rc<Node>>
+    }
+
+    let arena = Arena::new();
+    let root = arena.alloc_cyclic(|me| {
+        assert!(me.upgrade().is_none());
+
+        Node {
+            value: 0,
+            parent: None,
+            children: (1..20).map(|i| arena.alloc(Node {
+                value: i,
+                parent: Some(me.clone()),
+                children: vec![]
+            })).collect()
+        }
+    });
+
+    for child in &root.children {
+        let parent = child.parent.as_ref().unwrap().upgrade().unwrap();
+        assert_eq!(parent.value, 0);
+    }
+
+    let mut expected = 0;
+    arena.each(|node| {
+        assert_eq!(node.value, expected);
+        expected += 1;
+    });
+    assert_eq!(expected, 20);
+
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)]
+fn by_address() {
+    use std::collections::HashSet;
+
+    let arena = Arena::with_capacity(1);
+    let handles: Vec<_> = (0..10).map(|_| arena.alloc(())).collect();
+    let set: HashSet<_> = handles.iter().chain(handles.iter()).map(|rc| ByAddress(rc.clone())).collect();
+    assert_eq!(set.len(), 10);
+    assert!(set.contains(&ByAddress(handles[3].clone())));
+
+    let indirect = Arena::indirect();
+    let a = indirect.alloc(1);
+    indirect.alloc(2);
+    let key = ByAddress(a.clone());
+    unsafe { indirect.compact() };
+    assert_eq!(key, ByAddress(a));
+}
+
+#[test]
+fn alloc_n() {
+    let arena = Arena::with_capacity(4);
+    arena.alloc(0);
+
+    let batch = arena.alloc_n(5, |i| i + 1);
+    assert_eq!(batch.iter().map(|rc| **rc).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [1, 5]);
+
+    // A batch never straddles two chunks.
+    arena.alloc_n(9, |i| i);
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [1, 5, 9]);
+    assert!(arena.alloc_n(0, |i| i).is_empty());
+}
+
+#[test]
+fn weak_upgrade() {
+    let arena = Arena::new();
+    let foo = arena.alloc(1);
+    let weak = Rc::downgrade(&foo);
+
+    drop(foo);
+    assert_eq!(*weak.upgrade().unwrap(), 1);
+    drop(arena);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn truncate() {
+    let arena = Arena::with_capacity(2);
+    let handles: Vec<_> = (0..7).map(|i| arena.alloc(i)).collect();
+    arena.truncate(10).unwrap();
+    assert_eq!(arena.truncate(4), Err(ArenaError::LiveHandles));
+
+    drop(handles);
+    arena.truncate(6).unwrap();
+    assert_eq!(arena.len(), 6);
+    arena.truncate(2).unwrap();
+    assert_eq!(arena.chunks().count(), 1);
+    arena.truncate(5).unwrap();
+    assert_eq!(arena.len(), 2);
+
+    let one = arena.alloc(8);
+    arena.truncate(0).unwrap_err();
+    drop(one);
+    arena.truncate(0).unwrap();
+    assert!(arena.is_empty());
+}
+
+#[test]
+fn rollback() {
+    use std::sync::mpsc::{TryRecvError, Sender, channel};
+
+    struct Foo {
+        tx: Sender<usize>,
+        id: usize
+    }
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            self.tx.send(self.id).unwrap();
+        }
+    }
+
+    let (tx, rx) = channel();
+    let arena = Arena::with_capacity(2);
+    let first = arena.alloc(Foo { tx: tx.clone(), id: 0 });
+
+    let checkpoint = arena.checkpoint();
+    for id in 1..6 {
+        arena.alloc(Foo { tx: tx.clone(), id });
+    }
+    let weak = Rc::downgrade(&arena.alloc(Foo { tx: tx.clone(), id: 6 }));
+
+    assert_eq!(arena.rollback(checkpoint), Err(ArenaError::LiveHandles));
+    assert_eq!(arena.len(), 7);
+    drop(weak);
+
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(arena.len(), 1);
+    assert_eq!(rx.try_recv().unwrap(), 1);
+    for id in 2..7 {
+        assert_eq!(rx.recv().unwrap(), id);
+    }
+    assert_eq!(rx.try_recv().err().unwrap(), TryRecvError::Empty);
+
+    let second = arena.alloc(Foo { tx: tx.clone(), id: 7 });
+    assert_eq!(first.id, 0);
+    assert_eq!(second.id, 7);
+    arena.rollback(arena.checkpoint()).unwrap();
+    assert_eq!(arena.len(), 2);
+}
+
+#[test]
+fn cyclic_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let arena = Arena::new();
+    let mut escaped = None;
+
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        arena.alloc_cyclic(|me| -> usize {
+            escaped = Some(me.clone());
+            panic!()
+        })
+    })).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        arena.alloc_cyclic(|_| -> usize { panic!() })
+    })).is_err());
+
+    let foo = arena.alloc(1);
+    assert!(escaped.unwrap().upgrade().is_none());
+    assert_eq!(*foo, 1);
+    assert_eq!(arena.len(), 1);
+}
+
+#[test]
+fn thread_local() {
+    thread_local_arena! {
+        struct Numbers: usize;
+    }
+
+    let one = Numbers::alloc(1);
+    std::thread::spawn(|| {
+        Numbers::alloc(2);
+        Numbers::alloc(3);
+        assert_eq!(Numbers::arena().len(), 2);
+    }).join().unwrap();
+
+    assert_eq!(*one, 1);
+    assert_eq!(Numbers::arena().len(), 1);
+}
+
+#[test]
+fn alloc_ref() {
+    let arena = Arena::with_capacity(1);
+    let checkpoint = arena.checkpoint();
+    let refs: Vec<&usize> = (0..10).map(|i| arena.alloc_ref(i)).collect();
+
+    assert_eq!(arena.rollback(checkpoint), Err(ArenaError::LiveHandles));
+    for (i, r) in refs.iter().enumerate() {
+        assert_eq!(**r, i);
+    }
+    arena.assert_invariants();
+
+    // The objects are still dropped along with the arena.
+    let token = std::rc::Rc::new(());
+    let arena = Arena::new();
+    arena.alloc_ref(token.clone());
+    let other = arena.alloc(token.clone());
+    arena.assert_invariants();
+    drop((arena, other));
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn traces() {
+    use std::sync::{Arc, Mutex};
+    use tracing::{span, Event, Metadata, Subscriber};
+
+    // Records the names of spans and the messages of events.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Message<'a>(&'a mut String);
+
+    impl<'a> tracing::field::Visit for Message<'a> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                *self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            self.0.lock().unwrap().push(attrs.metadata().name().to_string());
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.0.lock().unwrap().push(message);
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let recorded = Arc::new(Mutex::new(vec![]));
+    tracing::subscriber::with_default(Recorder(recorded.clone()), || {
+        let arena = Arena::with_capacity(2);
+        arena.alloc_n(3, |i| i);
+        drop(arena);
+    });
+
+    assert_eq!(*recorded.lock().unwrap(), ["alloc_n", "arena grew a new chunk", "dropping arena"]);
+}
+
+#[test]
+fn appends_vecs() {
+    let arena = Arena::indirect_with_capacity(1);
+    arena.alloc(0);
+    let batch = vec![1, 2, 3];
+    let first: *const i32 = &batch[0];
+    assert_eq!(arena.append_vec(batch), 1..4);
+    assert_eq!(arena.append_vec(vec![]), 4..4);
+    arena.assert_invariants();
+
+    let four = arena.alloc(4);
+    assert_eq!(&*arena.iter().nth(1).unwrap() as *const i32, first);
+    assert_eq!(*four, 4);
+    unsafe { arena.compact() };
+    assert_eq!(arena.len(), 1);
+
+    let limited = Arena::with_limit(Limit::Objects(4));
+    limited.append_vec(vec![(); 3]);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| limited.append_vec(vec![(); 2])));
+    assert!(result.is_err());
+    assert_eq!(limited.len(), 3);
+}
+
+#[test]
+fn unwinds() {
+    use std::panic::catch_unwind;
+
+    let arena = Arena::new();
+    arena.set_hooks(Hooks::new().on_alloc(|&n: &u32| assert!(n != 1, "rejected")));
+    let first = arena.alloc(0);
+    assert!(catch_unwind(|| arena.alloc(1)).is_err());
+    assert!(catch_unwind(|| arena.alloc_cyclic(|_| panic!("failed"))).is_err());
+    arena.assert_invariants();
+    assert_eq!((*first, *arena.alloc(2), arena.len()), (0, 2, 3));
+
+    let pool = Arena::pool(|n: &mut u32| assert!(*n != 1, "can't reset"));
+    let handles: Vec<_> = (0..3).map(|i| pool.alloc(i)).collect();
+    assert!(catch_unwind(|| drop(handles)).is_err());
+    // The object which couldn't be reset isn't recycled.
+    pool.assert_invariants();
+    let recycled = (pool.recycle().unwrap(), pool.recycle().unwrap());
+    assert_eq!((*recycled.0, *recycled.1), (2, 0));
+    assert!(pool.recycle().is_none());
+}
+
+#[test]
+fn shrinks() {
+    let arena = Arena::pool_with_capacity(2, |i: &mut usize| *i = 0);
+    let kept: Vec<_> = (0..3).map(|i| arena.alloc(i)).collect();
+    arena.try_reserve(100).unwrap();
+    let checkpoint = arena.checkpoint();
+    arena.alloc(3);
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(arena.chunks().count(), 3);
+
+    arena.free_unused_chunks();
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [2, 1]);
+    arena.assert_invariants();
+
+    // The objects stay where they were, and freed ones are still reused.
+    let ptr = &*kept[2] as *const usize;
+    drop(kept);
+    assert_eq!(&*arena.alloc(4) as *const usize, ptr);
+    arena.free_unused_chunks();
+    assert_eq!(arena.chunks().count(), 2);
+}
+
+#[test]
+fn splits_off() {
+    let token = std::rc::Rc::new(());
+    let arena = Arena::with_capacity(2);
+    let handles: Vec<_> = (0..7).map(|i| arena.alloc((i, token.clone()))).collect();
+    let kept: Vec<_> = handles.iter().take(3).cloned().collect();
+
+    // A handle into the batch keeps it where it is.
+    assert_eq!(arena.split_off(3).err(), Some(ArenaError::LiveHandles));
+    drop(handles);
+    let batch = arena.split_off(3).unwrap();
+    assert_eq!(batch.iter().map(|object| object.0).collect::<Vec<_>>(), [3, 4, 5, 6]);
+    assert_eq!((arena.len(), batch.chunks().count()), (3, 2));
+
+    // Both arenas keep growing on their own.
+    arena.alloc((7, token.clone()));
+    batch.alloc((8, token.clone()));
+    assert_eq!((arena.len(), batch.len(), std::rc::Rc::strong_count(&token)), (4, 5, 10));
+    assert_eq!(arena.split_off(10).unwrap().len(), 0);
+    drop(batch);
+    assert_eq!((kept[2].0, std::rc::Rc::strong_count(&token)), (2, 5));
+}
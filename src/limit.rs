@@ -0,0 +1,99 @@
+//! Arenas with a budget, which refuse to grow past it rather than letting
+//! untrusted input take up unbounded memory.
+
+use {Arena, ArenaError, Chunk, Chunks};
+
+/// A budget for an arena created with `Arena::with_limit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// At most this many objects may be placed in the arena.
+    Objects(usize),
+    /// The arena's chunks may take up at most this many bytes for the
+    /// objects themselves, not counting their reference counts.
+    Bytes(usize)
+}
+
+/// Check that `additional` more objects fit in the budget of an arena.
+pub fn check<T>(limit: Limit, chunks: &Chunks<T>, additional: usize) -> Result<(), ArenaError> {
+    match limit {
+        Limit::Objects(max) => {
+            let len: usize = chunks.list.iter().map(|chunk| chunk.items.len()).sum();
+
+            if len.saturating_add(additional) > max {
+                return Err(ArenaError::LimitExceeded);
+            }
+        }
+        Limit::Bytes(_) => {}
+    }
+
+    Ok(())
+}
+
+/// The capacity of a new chunk which is to hold `additional` objects, shrunk
+/// from `capacity` to what's left of the budget. This fails if not even
+/// `additional` objects fit.
+pub fn chunk_capacity<T>(limit: Limit, chunks: &Chunks<T>, capacity: usize, additional: usize)
+                         -> Result<usize, ArenaError> {
+    let remaining = match limit {
+        Limit::Objects(max) => {
+            let len: usize = chunks.list.iter().map(|chunk| chunk.items.len()).sum();
+            max.saturating_sub(len)
+        }
+        Limit::Bytes(_) if std::mem::size_of::<T>() == 0 => return Ok(capacity),
+        Limit::Bytes(max) => {
+            let used: usize = chunks.list.iter().map(|chunk| chunk.capacity()).sum();
+            max.saturating_sub(used * std::mem::size_of::<T>()) / std::mem::size_of::<T>()
+        }
+    };
+
+    if remaining < additional {
+        return Err(ArenaError::LimitExceeded);
+    }
+
+    Ok(std::cmp::min(capacity, remaining))
+}
+
+impl<T> Arena<T> {
+    /// Create a new arena which can't grow past `limit`. Once it's reached,
+    /// `try_alloc` fails and `alloc` panics, but objects recycled by a pool
+    /// can still be reused.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, ArenaError, Limit};
+    ///
+    /// let arena = Arena::with_limit(Limit::Bytes(64));
+    /// let numbers: Vec<_> = (0..8).map(|i| arena.try_alloc(i as u64).unwrap()).collect();
+    ///
+    /// assert_eq!(arena.try_alloc(8).unwrap_err(), ArenaError::LimitExceeded);
+    /// assert_eq!(*numbers[7], 7);
+    /// ```
+    pub fn with_limit(limit: Limit) -> Arena<T> {
+        let mut chunks = Chunks::new(vec![]);
+        let capacity = chunk_capacity(limit, &chunks, 8, 0).unwrap();
+        chunks.list.push(Chunk::with_capacity(capacity));
+        chunks.limit = Some(limit);
+
+        Arena::from_chunks(chunks)
+    }
+}
+
+#[test]
+fn limits() {
+    let arena = Arena::with_limit(Limit::Objects(20));
+    let first: Vec<_> = (0..10).map(|i| arena.alloc(i)).collect();
+    assert!(arena.try_alloc(10).is_ok());
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [8, 3]);
+
+    // The last chunk only makes room for what's left.
+    let rest = arena.alloc_n(9, |i| i);
+    assert_eq!(arena.try_alloc(20).unwrap_err(), ArenaError::LimitExceeded);
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.alloc_n(1, |i| i))).is_err());
+    assert_eq!(arena.len(), 20);
+    assert_eq!(*rest[8] + *first[9], 17);
+
+    let bytes = Arena::with_limit(Limit::Bytes(10));
+    assert!(bytes.try_alloc([0u8; 4]).is_ok());
+    assert!(bytes.try_alloc([1u8; 4]).is_ok());
+    assert!(bytes.try_alloc([2u8; 4]).is_err());
+    assert!(Arena::with_limit(Limit::Bytes(0)).try_alloc(()).is_ok());
+}
@@ -0,0 +1,279 @@
+//! Doubly-linked lists whose nodes live in an arena, so that walking the
+//! list stays close together in memory.
+
+use std::cell::{Cell, RefCell};
+
+use {Arena, Rc, Weak};
+
+/// A node of a `List`, holding a value and its links to its neighbours. Each
+/// node owns the next one, and only weakly refers to the previous one.
+pub struct ListNode<T> {
+    pub value: T,
+    next: RefCell<Option<Rc<ListNode<T>>>>,
+    prev: RefCell<Option<Weak<ListNode<T>>>>,
+    linked: Cell<bool>
+}
+
+impl<T> ListNode<T> {
+    /// The node after this one, if it's still in a list.
+    pub fn next(&self) -> Option<Rc<ListNode<T>>> {
+        self.next.borrow().clone()
+    }
+
+    /// The node before this one, if it's still in a list.
+    pub fn prev(&self) -> Option<Rc<ListNode<T>>> {
+        self.prev.borrow().as_ref().and_then(Weak::upgrade)
+    }
+
+    /// Returns `true` if the node hasn't been removed from its list.
+    pub fn is_linked(&self) -> bool {
+        self.linked.get()
+    }
+}
+
+/// A doubly-linked list which stores its nodes in an arena. Pushing a value
+/// hands out a handle to its node, which can later be removed from the list
+/// in constant time.
+///
+/// Removing a node unlinks it, but its memory is only reclaimed along with
+/// the arena. When the list is dropped, its nodes are unlinked too.
+///
+/// ```rust
+/// use rc_arena::List;
+///
+/// let mut list = List::new();
+/// list.push_back(2);
+/// let three = list.push_back(3);
+/// list.push_front(1);
+///
+/// assert!(list.remove(&three));
+/// assert!(!list.remove(&three));
+/// list.push_back(4);
+///
+/// let values: Vec<_> = list.iter().map(|node| node.value).collect();
+/// assert_eq!(values, [1, 2, 4]);
+/// assert_eq!(list.pop_back().unwrap().value, 4);
+/// assert_eq!(list.len(), 2);
+/// ```
+pub struct List<T> {
+    arena: Arena<ListNode<T>>,
+    head: Option<Rc<ListNode<T>>>,
+    tail: Option<Rc<ListNode<T>>>,
+    len: usize
+}
+
+impl<T> List<T> {
+    /// Create a new list with an unspecified capacity.
+    pub fn new() -> List<T> {
+        List::with_capacity(8)
+    }
+
+    /// Create a new list with a known initial capacity.
+    pub fn with_capacity(n: usize) -> List<T> {
+        List {
+            arena: Arena::with_capacity(n),
+            head: None,
+            tail: None,
+            len: 0
+        }
+    }
+
+    /// Get the number of values in the list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The first node of the list.
+    pub fn front(&self) -> Option<Rc<ListNode<T>>> {
+        self.head.clone()
+    }
+
+    /// The last node of the list.
+    pub fn back(&self) -> Option<Rc<ListNode<T>>> {
+        self.tail.clone()
+    }
+
+    fn new_node(&mut self, value: T) -> Rc<ListNode<T>> {
+        self.len += 1;
+
+        self.arena.alloc(ListNode {
+            value,
+            next: RefCell::new(None),
+            prev: RefCell::new(None),
+            linked: Cell::new(true)
+        })
+    }
+
+    /// Add a value to the end of the list.
+    pub fn push_back(&mut self, value: T) -> Rc<ListNode<T>> {
+        let node = self.new_node(value);
+
+        match self.tail.take() {
+            Some(tail) => {
+                *node.prev.borrow_mut() = Some(Rc::downgrade(&tail));
+                *tail.next.borrow_mut() = Some(node.clone());
+            }
+            None => self.head = Some(node.clone())
+        }
+        self.tail = Some(node.clone());
+
+        node
+    }
+
+    /// Add a value to the start of the list.
+    pub fn push_front(&mut self, value: T) -> Rc<ListNode<T>> {
+        let node = self.new_node(value);
+
+        match self.head.take() {
+            Some(head) => {
+                *head.prev.borrow_mut() = Some(Rc::downgrade(&node));
+                *node.next.borrow_mut() = Some(head);
+            }
+            None => self.tail = Some(node.clone())
+        }
+        self.head = Some(node.clone());
+
+        node
+    }
+
+    /// Remove the first node of the list.
+    pub fn pop_front(&mut self) -> Option<Rc<ListNode<T>>> {
+        let head = self.head.clone()?;
+        self.remove(&head);
+
+        Some(head)
+    }
+
+    /// Remove the last node of the list.
+    pub fn pop_back(&mut self) -> Option<Rc<ListNode<T>>> {
+        let tail = self.tail.clone()?;
+        self.remove(&tail);
+
+        Some(tail)
+    }
+
+    /// Remove a node from the list, joining its neighbours together. This
+    /// returns `false` if the node isn't in this list.
+    pub fn remove(&mut self, node: &Rc<ListNode<T>>) -> bool {
+        if !self.arena.owns(node) || !node.linked.get() {
+            return false;
+        }
+
+        let next = node.next.borrow_mut().take();
+        let prev = node.prev.borrow_mut().take().and_then(|prev| prev.upgrade());
+
+        match next {
+            Some(ref next) => *next.prev.borrow_mut() = prev.as_ref().map(Rc::downgrade),
+            None => self.tail = prev.clone()
+        }
+        match prev {
+            Some(prev) => *prev.next.borrow_mut() = next,
+            None => self.head = next
+        }
+
+        node.linked.set(false);
+        self.len -= 1;
+
+        true
+    }
+
+    /// Iterate over the nodes of the list, from front to back.
+    pub fn iter(&self) -> ListIter<T> {
+        ListIter {
+            next: self.head.clone()
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> List<T> {
+        List::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Each node owns the next one, and so keeps the arena alive, so the
+        // chain has to be broken for the arena to be freed.
+        self.tail = None;
+        let mut next = self.head.take();
+
+        while let Some(node) = next {
+            next = node.next.borrow_mut().take();
+            *node.prev.borrow_mut() = None;
+            node.linked.set(false);
+        }
+    }
+}
+
+impl<T> IntoIterator for &List<T> {
+    type Item = Rc<ListNode<T>>;
+    type IntoIter = ListIter<T>;
+
+    fn into_iter(self) -> ListIter<T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the nodes of a `List`, returned by `List::iter`. It
+/// follows the links as it goes, so it sees changes made to the rest of the
+/// list while iterating.
+pub struct ListIter<T> {
+    next: Option<Rc<ListNode<T>>>
+}
+
+impl<T> Iterator for ListIter<T> {
+    type Item = Rc<ListNode<T>>;
+
+    fn next(&mut self) -> Option<Rc<ListNode<T>>> {
+        let node = self.next.take()?;
+        self.next = node.next();
+
+        Some(node)
+    }
+}
+
+#[test]
+fn links() {
+    let token = std::rc::Rc::new(());
+    let mut list = List::with_capacity(2);
+    let nodes: Vec<_> = (0..6).map(|i| list.push_back((i, token.clone()))).collect();
+
+    assert!(list.remove(&nodes[0]));
+    assert!(list.remove(&nodes[3]));
+    assert!(list.remove(&nodes[5]));
+    assert!(!nodes[3].is_linked());
+    assert_eq!(nodes[2].next().unwrap().value.0, 4);
+    assert_eq!(nodes[4].prev().unwrap().value.0, 2);
+    assert_eq!(list.front().unwrap().value.0, 1);
+    assert_eq!(list.back().unwrap().value.0, 4);
+
+    list.push_front((6, token.clone()));
+    let values: Vec<_> = list.iter().map(|node| node.value.0).collect();
+    assert_eq!(values, [6, 1, 2, 4]);
+    let reversed: Vec<_> = std::iter::successors(list.back(), |node| node.prev()).map(|node| node.value.0).collect();
+    assert_eq!(reversed, [4, 2, 1, 6]);
+
+    let mut other = List::new();
+    let foreign = other.push_back((7, token.clone()));
+    assert!(!list.remove(&foreign));
+    drop((other, foreign));
+
+    while list.pop_front().is_some() {}
+    assert!(list.is_empty() && list.back().is_none());
+
+    // Dropping the list unlinks the nodes so the arena can be freed.
+    for i in 0..4 {
+        list.push_back((i, token.clone()));
+    }
+    let kept = list.front().unwrap();
+    drop((list, nodes));
+    assert!(kept.next().is_none());
+    drop(kept);
+    assert_eq!(std::rc::Rc::strong_count(&token), 1);
+}
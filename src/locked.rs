@@ -0,0 +1,129 @@
+//! Chunks whose pages are locked into memory, so that secrets stored in them
+//! are never swapped out to disk, and on Linux are left out of core dumps.
+
+use std::alloc::Layout;
+use std::ptr::NonNull;
+
+use allocator_api2::alloc::{AllocError, Allocator};
+
+use {Arena, ArenaError};
+
+/// An allocator which hands out whole pages from the global allocator and
+/// locks them into memory with `mlock` on unix or `VirtualLock` on Windows.
+/// On Linux, the pages are also marked with `MADV_DONTDUMP`.
+///
+/// Locking fails once the process goes past its limit of locked memory,
+/// which is often small, in which case allocation fails too.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LockedAllocator;
+
+/// The layout `layout` is allocated with, covering whole pages.
+fn page_layout(layout: Layout) -> Result<Layout, AllocError> {
+    let page_size = page_size();
+    let size = layout.size().checked_add(page_size - 1).ok_or(AllocError)? / page_size * page_size;
+
+    Layout::from_size_align(size, std::cmp::max(layout.align(), page_size)).map_err(|_| AllocError)
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(windows)]
+fn page_size() -> usize {
+    use windows_sys::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+
+    let mut info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
+    unsafe { GetSystemInfo(&mut info) };
+
+    info.dwPageSize as usize
+}
+
+#[cfg(unix)]
+unsafe fn lock(ptr: *mut u8, len: usize) -> bool {
+    if libc::mlock(ptr as *const libc::c_void, len) != 0 {
+        return false;
+    }
+
+    // Not being left out of core dumps isn't worth failing over.
+    #[cfg(target_os = "linux")]
+    libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_DONTDUMP);
+
+    true
+}
+
+#[cfg(unix)]
+unsafe fn unlock(ptr: *mut u8, len: usize) {
+    libc::munlock(ptr as *const libc::c_void, len);
+    #[cfg(target_os = "linux")]
+    libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_DODUMP);
+}
+
+#[cfg(windows)]
+unsafe fn lock(ptr: *mut u8, len: usize) -> bool {
+    windows_sys::Win32::System::Memory::VirtualLock(ptr as *const _, len) != 0
+}
+
+#[cfg(windows)]
+unsafe fn unlock(ptr: *mut u8, len: usize) {
+    windows_sys::Win32::System::Memory::VirtualUnlock(ptr as *const _, len);
+}
+
+unsafe impl Allocator for LockedAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+
+        let layout = page_layout(layout)?;
+        let ptr = NonNull::new(unsafe { std::alloc::alloc(layout) }).ok_or(AllocError)?;
+
+        if !unsafe { lock(ptr.as_ptr(), layout.size()) } {
+            unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) };
+            return Err(AllocError);
+        }
+
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+
+        // This layout was accepted when the memory was allocated.
+        let layout = page_layout(layout).unwrap();
+        unlock(ptr.as_ptr(), layout.size());
+        std::alloc::dealloc(ptr.as_ptr(), layout);
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a new arena with a known initial capacity, whose chunks are
+    /// allocated by `LockedAllocator`. This fails if the first chunk can't be
+    /// locked, and allocating fails once later chunks can't be.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let keys = Arena::locked_with_capacity(4).unwrap();
+    /// let key = keys.alloc([0xa5u8; 32]);
+    /// assert_eq!(key[31], 0xa5);
+    /// ```
+    pub fn locked_with_capacity(n: usize) -> Result<Arena<T>, ArenaError> {
+        Arena::try_with_capacity_in(n, LockedAllocator)
+    }
+}
+
+#[test]
+fn locks_chunks() {
+    let arena = Arena::locked_with_capacity(1).unwrap();
+    let handles: Vec<_> = (0..100u64).map(|i| arena.alloc(i)).collect();
+    assert_eq!(handles.iter().map(|i| **i).sum::<u64>(), 4950);
+
+    let ptr = &*handles[0] as *const u64 as usize;
+    assert_eq!(ptr % page_size(), 0);
+    assert_eq!(arena.chunks().count(), 7);
+}
@@ -0,0 +1,174 @@
+//! Small values attached to each object of an arena, such as the flags of a
+//! graph traversal, kept in a column beside each chunk rather than in the
+//! objects themselves.
+
+use std::any::{Any, TypeId};
+
+use {Arena, Chunks, Rc, Slot};
+
+/// The metadata of a chunk's objects, by position, whose type is chosen by
+/// the first call to `Arena::set_meta`. Positions past the end have the
+/// default value.
+pub trait Column {
+    /// Forget the metadata from position `len` onwards.
+    fn truncate(&mut self, len: usize);
+
+    /// Reset the metadata at position `index` to the default value.
+    fn reset(&mut self, index: usize);
+
+    /// Create an empty column of the same type.
+    fn empty(&self) -> Box<dyn Column>;
+
+    /// Append the metadata at position `index` of `from`, or the default
+    /// value if there's no column.
+    fn push_from(&mut self, from: Option<&dyn Column>, index: usize);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<M: Copy + Default + 'static> Column for Vec<M> {
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+
+    fn reset(&mut self, index: usize) {
+        if let Some(meta) = self.get_mut(index) {
+            *meta = M::default();
+        }
+    }
+
+    fn empty(&self) -> Box<dyn Column> {
+        Box::new(Vec::<M>::new())
+    }
+
+    fn push_from(&mut self, from: Option<&dyn Column>, index: usize) {
+        let from = from.and_then(|from| from.as_any().downcast_ref::<Vec<M>>());
+        self.push(from.and_then(|from| from.get(index)).copied().unwrap_or_default());
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<T> Arena<T> {
+    /// Attach `meta` to the object `rc` points at, returning what was
+    /// attached before. Every object starts out with the default value,
+    /// which it's reset to when an object pool reuses its place, and which
+    /// doesn't take up any room until metadata is set for an object in its
+    /// chunk.
+    ///
+    /// An arena has a single type of metadata, which stays with its objects
+    /// when an indirect arena is compacted or sorted.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let a = arena.alloc("a");
+    /// let b = arena.alloc("b");
+    ///
+    /// arena.set_meta(&b, true);
+    /// assert_eq!((arena.meta::<bool>(&a), arena.meta::<bool>(&b)), (false, true));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't in the arena, or if metadata of another
+    /// type has been set in it.
+    pub fn set_meta<M: Copy + Default + 'static>(&self, rc: &Rc<T>, meta: M) -> M {
+        assert!(self.owns(rc), "object isn't in the arena");
+
+        let mut chunks = self.chunks.borrow_mut();
+        let kind = *chunks.meta.get_or_insert(TypeId::of::<M>());
+        assert!(kind == TypeId::of::<M>(), "the arena has metadata of another type");
+
+        let (chunk, index) = chunks.position(rc.ptr(), rc.slot).unwrap();
+        let column = chunks.list[chunk].meta.get_or_insert_with(|| Box::new(Vec::<M>::new()));
+        let column = column.as_any_mut().downcast_mut::<Vec<M>>().unwrap();
+        if column.len() <= index {
+            column.resize(index + 1, M::default());
+        }
+
+        std::mem::replace(&mut column[index], meta)
+    }
+
+    /// Get the metadata attached to the object `rc` points at. See
+    /// `set_meta`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `set_meta`.
+    pub fn meta<M: Copy + Default + 'static>(&self, rc: &Rc<T>) -> M {
+        assert!(self.owns(rc), "object isn't in the arena");
+
+        let chunks = self.chunks.borrow();
+        assert!(chunks.meta.is_none_or(|kind| kind == TypeId::of::<M>()), "the arena has metadata of another type");
+
+        let (chunk, index) = chunks.position(rc.ptr(), rc.slot).unwrap();
+        let column = chunks.list[chunk].meta.as_ref().and_then(|column| column.as_any().downcast_ref::<Vec<M>>());
+
+        column.and_then(|column| column.get(index)).copied().unwrap_or_default()
+    }
+}
+
+impl<T> Chunks<T> {
+    /// The chunk an object is in, and its position in the chunk.
+    fn position(&self, ptr: *mut T, slot: *const Slot) -> Option<(usize, usize)> {
+        self.list.iter().enumerate().find_map(|(chunk, list)| list.index_of(ptr, slot).map(|index| (chunk, index)))
+    }
+}
+
+/// Reset the metadata of an object whose place is about to be reused.
+pub fn reset<T>(chunks: &mut Chunks<T>, ptr: *mut T, slot: *const Slot) {
+    if chunks.meta.is_some() {
+        if let Some((chunk, index)) = chunks.position(ptr, slot) {
+            if let Some(ref mut column) = chunks.list[chunk].meta {
+                column.reset(index);
+            }
+        }
+    }
+}
+
+#[test]
+fn attaches_metadata() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let arena = Arena::pool_with_capacity(2, |_: &mut usize| {});
+    let handles: Vec<_> = (0..5).map(|i| arena.alloc(i)).collect();
+    assert!(arena.chunks.borrow().list.iter().all(|chunk| chunk.meta.is_none()));
+
+    assert_eq!(arena.set_meta(&handles[3], 7u8), 0);
+    assert_eq!(arena.set_meta(&handles[3], 8u8), 7);
+    assert_eq!(arena.meta::<u8>(&handles[4]), 0);
+    assert!(catch_unwind(AssertUnwindSafe(|| arena.meta::<u16>(&handles[3]))).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| arena.meta::<u8>(&Arena::new().alloc(0)))).is_err());
+
+    // A reused place starts out with the default value again.
+    let mut handles = handles.into_iter();
+    let kept: Vec<_> = handles.by_ref().take(3).collect();
+    let reused = handles.next().unwrap();
+    drop(reused);
+    let reused = arena.alloc(10);
+    assert_eq!((arena.index_of(&reused), arena.meta::<u8>(&reused)), (Some(3), 0));
+    drop(kept);
+}
+
+#[test]
+fn keeps_metadata_through_compaction() {
+    let arena = Arena::indirect_with_capacity(2);
+    let handles: Vec<_> = (0..6).map(|i| arena.alloc(5 - i)).collect();
+    for handle in handles.iter() {
+        arena.set_meta(handle, **handle * 10);
+    }
+
+    let kept: Vec<_> = handles.into_iter().filter(|i| **i % 2 == 0).collect();
+    unsafe { arena.sort_by(|a, b| a.cmp(b)) };
+    assert_eq!(kept.iter().map(|i| arena.meta::<i32>(i)).collect::<Vec<_>>(), [40, 20, 0]);
+}
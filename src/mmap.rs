@@ -0,0 +1,412 @@
+//! Chunks which live in a memory-mapped file rather than on the heap, so that
+//! an arena can grow larger than physical memory, and so that once it has
+//! been built it can be mapped read-only by other processes.
+//!
+//! The file starts with a page holding a small header, followed by each
+//! chunk in allocation order. Every chunk starts on a page boundary with its
+//! capacity and length, followed by the objects themselves.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::{mem, ptr, slice};
+
+use memmap2::{Mmap, MmapMut, MmapOptions};
+
+use {Arena, Chunk, Chunks, Items};
+
+const MAGIC: &[u8; 8] = b"RCARENA\0";
+const PAGE_SIZE: u64 = 4096;
+const CHUNK_HEADER_LEN: u64 = 16;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn overflow() -> io::Error {
+    invalid_data("chunk is too large")
+}
+
+fn file_header<T>() -> [u8; 24] {
+    let mut header = [0; 24];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..16].copy_from_slice(&(mem::size_of::<T>() as u64).to_le_bytes());
+    header[16..].copy_from_slice(&(mem::align_of::<T>() as u64).to_le_bytes());
+
+    header
+}
+
+/// The offset of the first object from the start of its chunk, and the
+/// total size of a chunk of `capacity` objects.
+fn chunk_layout<T>(capacity: u64) -> io::Result<(u64, u64)> {
+    let align = mem::align_of::<T>() as u64;
+    if align > PAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "objects are too strictly aligned to be mapped"));
+    }
+
+    let data_offset = CHUNK_HEADER_LEN.div_ceil(align) * align;
+    let len = capacity.checked_mul(mem::size_of::<T>() as u64)
+                      .and_then(|len| len.checked_add(data_offset + PAGE_SIZE - 1))
+                      .ok_or_else(overflow)?;
+
+    Ok((data_offset, len / PAGE_SIZE * PAGE_SIZE))
+}
+
+enum Map {
+    Writable(MmapMut),
+    // Only held to keep the mapping alive.
+    ReadOnly(#[allow(dead_code)] Mmap)
+}
+
+/// The objects of a single chunk, stored in a mapping of part of a file.
+pub struct MappedItems<T> {
+    map: Map,
+    ptr: *mut T,
+    len: usize,
+    capacity: usize
+}
+
+impl<T> MappedItems<T> {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+
+        match self.map {
+            Map::Writable(ref mut map) => {
+                map[8..16].copy_from_slice(&(len as u64).to_le_bytes());
+            }
+            // Read-only chunks are always full, so that nothing is ever
+            // written to them.
+            Map::ReadOnly(_) => self.capacity = len
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        assert!(self.len < self.capacity);
+
+        unsafe { ptr::write(self.ptr.add(self.len), value) };
+        let len = self.len + 1;
+        self.set_len(len);
+    }
+
+    /// Forget the objects from `len` onwards. Only arenas of `Copy` objects
+    /// are ever mapped, so there is nothing to drop.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.set_len(len);
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match self.map {
+            Map::Writable(ref map) => map.flush(),
+            Map::ReadOnly(_) => Ok(())
+        }
+    }
+}
+
+impl<T> Deref for MappedItems<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for MappedItems<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+/// A file which new chunks are appended to as the arena grows.
+pub struct MappedFile {
+    file: File,
+    end: u64
+}
+
+impl MappedFile {
+    pub fn try_new_chunk<T>(&mut self, capacity: usize) -> io::Result<MappedItems<T>> {
+        let (data_offset, chunk_len) = chunk_layout::<T>(capacity as u64)?;
+        let end = self.end.checked_add(chunk_len).ok_or_else(overflow)?;
+        self.file.set_len(end)?;
+
+        let mut map = unsafe {
+            MmapOptions::new().offset(self.end).len(chunk_len as usize).map_mut(&self.file)?
+        };
+        map[..8].copy_from_slice(&(capacity as u64).to_le_bytes());
+        map[8..16].copy_from_slice(&0u64.to_le_bytes());
+        self.end = end;
+
+        Ok(MappedItems {
+            ptr: unsafe { map.as_mut_ptr().add(data_offset as usize) as *mut T },
+            map: Map::Writable(map),
+            len: 0,
+            capacity
+        })
+    }
+}
+
+impl<T: Copy> Arena<T> {
+    /// Create a new arena whose chunks live in `file`, starting from a known
+    /// initial capacity. The file is truncated, and grows along with the
+    /// arena. Allocation panics if the file can't be grown.
+    ///
+    /// Once the arena has been built it can be opened elsewhere with
+    /// `open_mmap_file`. The objects must not contain pointers or handles,
+    /// since these won't be valid in another process.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may modify or truncate the file while the arena or any of
+    /// its handles are alive.
+    pub unsafe fn with_mmap_file(mut file: File, capacity: usize) -> io::Result<Arena<T>> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&file_header::<T>())?;
+
+        let mut mapped = MappedFile {
+            file,
+            end: PAGE_SIZE
+        };
+        let first = Chunk::new(Items::Mapped(mapped.try_new_chunk(capacity)?), capacity);
+
+        let mut chunks = Chunks::new(vec![first]);
+        chunks.file = Some(mapped);
+
+        Ok(Arena::from_chunks(chunks))
+    }
+
+    /// Map the objects of an arena created by `with_mmap_file` read-only.
+    /// Objects allocated into the returned arena are stored on the heap.
+    ///
+    /// # Safety
+    ///
+    /// The file must have been written by an arena of the same type `T`,
+    /// with every bit pattern in it being a valid `T`. Nothing may modify or
+    /// truncate the file while the arena or any of its handles are alive.
+    pub unsafe fn open_mmap_file(file: &File) -> io::Result<Arena<T>> {
+        let mut header = [0; 24];
+        let mut reader = file;
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+
+        if header != file_header::<T>() {
+            return Err(invalid_data("file wasn't written by an arena of this type"));
+        }
+
+        let file_len = file.metadata()?.len();
+        let mut offset = PAGE_SIZE;
+        let mut chunks = vec![];
+
+        while offset < file_len {
+            let mut chunk_header = [0; 16];
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(&mut chunk_header)?;
+
+            let capacity = u64::from_le_bytes(chunk_header[..8].try_into().unwrap());
+            let len = u64::from_le_bytes(chunk_header[8..].try_into().unwrap());
+            if len > capacity || len > usize::MAX as u64 {
+                return Err(invalid_data("chunk length exceeds its capacity"));
+            }
+
+            let (data_offset, chunk_len) = chunk_layout::<T>(capacity)?;
+            if offset.checked_add(chunk_len).is_none_or(|end| end > file_len) {
+                return Err(invalid_data("chunk is truncated"));
+            }
+
+            let map = MmapOptions::new().offset(offset).len(chunk_len as usize).map(file)?;
+            let items = MappedItems {
+                ptr: map.as_ptr().add(data_offset as usize) as *mut T,
+                map: Map::ReadOnly(map),
+                len: len as usize,
+                capacity: len as usize
+            };
+
+            let mut chunk = Chunk::new(Items::Mapped(items), len as usize);
+            for _ in 0..len {
+                chunk.push_slot(0);
+            }
+            chunks.push(chunk);
+
+            offset += chunk_len;
+        }
+
+        if chunks.is_empty() {
+            return Err(invalid_data("an arena has at least one chunk"));
+        }
+
+        Ok(Arena::from_chunks(Chunks::new(chunks)))
+    }
+}
+
+/// The file backing the shared memory segment called `name`.
+#[cfg(target_os = "linux")]
+fn shared_memory_path(name: &str) -> io::Result<std::path::PathBuf> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid shared memory segment name"));
+    }
+
+    Ok(std::path::Path::new("/dev/shm").join(name))
+}
+
+#[cfg(target_os = "linux")]
+impl<T: Copy> Arena<T> {
+    /// Create a new arena whose chunks live in the shared memory segment
+    /// called `name`, replacing any segment of that name, like
+    /// `with_mmap_file`. Other processes can map the objects with
+    /// `open_shared_memory` without copying them, and objects can refer to
+    /// each other with `Offset`s, which mean the same in every process.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Offset};
+    ///
+    /// #[derive(Clone, Copy)]
+    /// struct Node {
+    ///     value: u32,
+    ///     next: Option<Offset<Node>>
+    /// }
+    ///
+    /// let name = format!("rc_arena-doc-{}", std::process::id());
+    /// let producer = unsafe { Arena::with_shared_memory(&name, 16).unwrap() };
+    /// let last = producer.alloc(Node { value: 2, next: None });
+    /// producer.alloc(Node { value: 1, next: producer.offset_of(&last) });
+    /// producer.flush().unwrap();
+    ///
+    /// // In another process:
+    /// let consumer = unsafe { Arena::<Node>::open_shared_memory(&name).unwrap() };
+    /// let first = consumer.last().unwrap();
+    /// assert_eq!(consumer.at(first.next.unwrap()).unwrap().value, 2);
+    ///
+    /// Arena::<Node>::remove_shared_memory(&name).unwrap();
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as for `with_mmap_file`, for the segment's file.
+    pub unsafe fn with_shared_memory(name: &str, capacity: usize) -> io::Result<Arena<T>> {
+        let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true)
+                                               .open(shared_memory_path(name)?)?;
+
+        Arena::with_mmap_file(file, capacity)
+    }
+
+    /// Map the objects of an arena created by `with_shared_memory` read-only,
+    /// like `open_mmap_file`. Only the objects which were in the segment when
+    /// it was opened are visible, so the producer should finish its changes
+    /// and `flush` them first.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `open_mmap_file`, for the segment's file. In
+    /// particular, the producer must not allocate more objects into chunks
+    /// which are already full while the arena is mapped.
+    pub unsafe fn open_shared_memory(name: &str) -> io::Result<Arena<T>> {
+        Arena::open_mmap_file(&File::open(shared_memory_path(name)?)?)
+    }
+
+    /// Remove the shared memory segment called `name`. Arenas which have it
+    /// mapped keep working until they are dropped.
+    pub fn remove_shared_memory(name: &str) -> io::Result<()> {
+        std::fs::remove_file(shared_memory_path(name)?)
+    }
+}
+
+impl<T> Arena<T> {
+    /// Write any changes to the arena's memory-mapped chunks back to their
+    /// file.
+    pub fn flush(&self) -> io::Result<()> {
+        let chunks = self.chunks.borrow();
+
+        for chunk in chunks.list.iter() {
+            if let Items::Mapped(ref items) = chunk.items {
+                items.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn temp_file(name: &str) -> (File, std::path::PathBuf) {
+    use std::fs::OpenOptions;
+
+    let path = std::env::temp_dir().join(format!("rc_arena-{}-{}", name, std::process::id()));
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+
+    (file, path)
+}
+
+#[test]
+fn mapped_round_trip() {
+    let (file, path) = temp_file("mapped_round_trip");
+
+    let arena = unsafe { Arena::<(u64, u8)>::with_mmap_file(file, 2).unwrap() };
+    let handles: Vec<_> = (0..100).map(|i| arena.alloc((i, i as u8))).collect();
+    assert_eq!(*handles[50], (50, 50));
+    arena.flush().unwrap();
+    drop(handles);
+    drop(arena);
+
+    let opened = unsafe { Arena::<(u64, u8)>::open_mmap_file(&File::open(&path).unwrap()).unwrap() };
+    assert_eq!(opened.len(), 100);
+    let extra = opened.alloc((100, 100));
+
+    let mut expected = 0;
+    opened.each(|item| {
+        assert_eq!(**item, (expected, expected as u8));
+        expected += 1;
+    });
+    assert_eq!(expected, 101);
+    assert_eq!(*extra, (100, 100));
+
+    assert!(unsafe { Arena::<u32>::open_mmap_file(&File::open(&path).unwrap()) }.is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mapped_rollback() {
+    let (file, path) = temp_file("mapped_rollback");
+
+    let arena = unsafe { Arena::<u32>::with_mmap_file(file, 4).unwrap() };
+    arena.alloc(1);
+    let checkpoint = arena.checkpoint();
+    for i in 2..20 {
+        arena.alloc(i);
+    }
+    arena.rollback(checkpoint).unwrap();
+    arena.alloc(2);
+    drop(arena);
+
+    let opened = unsafe { Arena::<u32>::open_mmap_file(&File::open(&path).unwrap()).unwrap() };
+    // The chunks which were rolled back are still in the file, but empty.
+    assert_eq!(opened.len(), 2);
+    opened.rollback(opened.checkpoint()).unwrap();
+    opened.alloc(3);
+    assert_eq!(opened.len(), 3);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn shared_memory() {
+    let name = format!("rc_arena-shared_memory-{}", std::process::id());
+
+    let producer = unsafe { Arena::<u64>::with_shared_memory(&name, 4).unwrap() };
+    for i in 0..10 {
+        producer.alloc(i);
+    }
+    producer.flush().unwrap();
+
+    let consumer = unsafe { Arena::<u64>::open_shared_memory(&name).unwrap() };
+    Arena::<u64>::remove_shared_memory(&name).unwrap();
+    assert_eq!(consumer.iter().map(|i| *i).sum::<u64>(), 45);
+    assert!(unsafe { Arena::<u64>::open_shared_memory(&name) }.is_err());
+    assert_eq!(Arena::<u64>::remove_shared_memory("../etc").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,307 @@
+//! A compact binary format for saving an arena and loading it again, which
+//! is much faster than rebuilding its contents from scratch.
+
+use std::io::{self, Read, Write};
+
+use {Arena, Chunk, Chunks, Items, Slots};
+
+/// Types which can be written to and read back from a byte stream by
+/// `Arena::serialize_into` and `Arena::deserialize_from`.
+///
+/// Integers and floats are written in little-endian byte order, and lengths
+/// are written as a `u64`.
+pub trait Persist: Sized {
+    /// Write this value to `writer`.
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Read a value previously written by `write_to` from `reader`.
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! persist_number {
+    ($($ty:ty),*) => {$(
+        impl Persist for $ty {
+            fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.to_le_bytes())
+            }
+
+            fn read_from<R: Read>(reader: &mut R) -> io::Result<$ty> {
+                let mut bytes = [0; std::mem::size_of::<$ty>()];
+                reader.read_exact(&mut bytes)?;
+
+                Ok(<$ty>::from_le_bytes(bytes))
+            }
+        }
+    )*}
+}
+
+persist_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_len<W: Write>(len: usize, writer: &mut W) -> io::Result<()> {
+    (len as u64).write_to(writer)
+}
+
+fn read_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let len = u64::read_from(reader)?;
+
+    if len > usize::MAX as u64 {
+        return Err(invalid_data("length does not fit in a usize"));
+    }
+
+    Ok(len as usize)
+}
+
+/// Allocate a vector for `capacity` elements read from a stream, without
+/// aborting if the stream asks for something absurd.
+fn vec_with_capacity<T>(capacity: usize) -> io::Result<Vec<T>> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity).map_err(|_| invalid_data("capacity is too large"))?;
+
+    Ok(vec)
+}
+
+impl Persist for usize {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_len(*self, writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<usize> {
+        read_len(reader)
+    }
+}
+
+impl Persist for isize {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as i64).write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<isize> {
+        let value = i64::read_from(reader)?;
+
+        if value < isize::MIN as i64 || value > isize::MAX as i64 {
+            return Err(invalid_data("value does not fit in an isize"));
+        }
+
+        Ok(value as isize)
+    }
+}
+
+impl Persist for bool {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u8).write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<bool> {
+        match u8::read_from(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool"))
+        }
+    }
+}
+
+impl Persist for char {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u32).write_to(writer)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<char> {
+        std::char::from_u32(u32::read_from(reader)?).ok_or_else(|| invalid_data("invalid char"))
+    }
+}
+
+impl Persist for String {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_len(self.len(), writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<String> {
+        let len = read_len(reader)?;
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "string is truncated"));
+        }
+
+        String::from_utf8(bytes).map_err(|_| invalid_data("invalid utf-8"))
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match *self {
+            Some(ref value) => {
+                true.write_to(writer)?;
+                value.write_to(writer)
+            }
+            None => false.write_to(writer)
+        }
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<T>> {
+        if bool::read_from(reader)? {
+            Ok(Some(T::read_from(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Persist> Persist for Vec<T> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_len(self.len(), writer)?;
+
+        for value in self {
+            value.write_to(writer)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Vec<T>> {
+        let len = read_len(reader)?;
+        // Don't trust the length for preallocation, since each element may
+        // be much smaller than a `T`.
+        let mut vec = Vec::new();
+
+        for _ in 0..len {
+            vec.push(T::read_from(reader)?);
+        }
+
+        Ok(vec)
+    }
+}
+
+macro_rules! persist_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Persist),*> Persist for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                let ($(ref $name,)*) = *self;
+                $($name.write_to(writer)?;)*
+
+                Ok(())
+            }
+
+            fn read_from<R: Read>(reader: &mut R) -> io::Result<($($name,)*)> {
+                Ok(($($name::read_from(reader)?,)*))
+            }
+        }
+    }
+}
+
+persist_tuple!(A);
+persist_tuple!(A, B);
+persist_tuple!(A, B, C);
+persist_tuple!(A, B, C, D);
+
+impl<T: Persist> Arena<T> {
+    /// Write the contents of the arena to `writer`. The layout of the arena's
+    /// chunks is preserved, so that `deserialize_from` can rebuild it with
+    /// the same capacities and allocation order.
+    ///
+    /// Only the objects themselves are written. Handles stored inside them
+    /// can't be persisted directly, and must be turned into `Offset`s first.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// arena.alloc(String::from("Hello"));
+    /// arena.alloc(String::from("world"));
+    ///
+    /// let mut bytes = vec![];
+    /// arena.serialize_into(&mut bytes).unwrap();
+    ///
+    /// let loaded: Arena<String> = Arena::deserialize_from(&bytes[..]).unwrap();
+    /// assert_eq!(loaded.len(), 2);
+    /// ```
+    pub fn serialize_into<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let chunks = self.chunks.borrow();
+
+        write_len(chunks.list.len(), &mut writer)?;
+
+        for chunk in chunks.list.iter() {
+            write_len(chunk.items.capacity(), &mut writer)?;
+            write_len(chunk.items.len(), &mut writer)?;
+
+            for item in chunk.items.iter() {
+                item.write_to(&mut writer)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    /// Read an arena previously written by `serialize_into` from `reader`.
+    pub fn deserialize_from<R: Read>(mut reader: R) -> io::Result<Arena<T>> {
+        let num_chunks = read_len(&mut reader)?;
+
+        if num_chunks == 0 {
+            return Err(invalid_data("an arena has at least one chunk"));
+        }
+
+        let mut chunks = Vec::new();
+
+        for _ in 0..num_chunks {
+            let capacity = read_len(&mut reader)?;
+            let len = read_len(&mut reader)?;
+
+            if len > capacity {
+                return Err(invalid_data("chunk length exceeds its capacity"));
+            }
+
+            let mut chunk = Chunk {
+                items: Items::Heap(vec_with_capacity(capacity)?),
+                slots: Slots::Inline(vec_with_capacity(capacity)?),
+                reserved: false,
+                #[cfg(feature = "alloc-backtraces")]
+                backtraces: vec![],
+                meta: None
+            };
+
+            for _ in 0..len {
+                chunk.items.push(T::read_from(&mut reader)?);
+                chunk.push_slot(0);
+            }
+
+            chunks.push(chunk);
+        }
+
+        Ok(Arena::from_chunks(Chunks::new(chunks)))
+    }
+}
+
+#[test]
+fn round_trip() {
+    let arena = Arena::with_capacity(2);
+    for i in 0..10u32 {
+        arena.alloc((i, format!("{}", i), if i % 2 == 0 { Some(vec![i; i as usize]) } else { None }));
+    }
+
+    let mut bytes = vec![];
+    arena.serialize_into(&mut bytes).unwrap();
+    let loaded = Arena::deserialize_from(&bytes[..]).unwrap();
+
+    let mut expected = 0;
+    loaded.each(|item: &::Rc<(u32, String, Option<Vec<u32>>)>| {
+        assert_eq!(item.0, expected);
+        assert_eq!(item.1, format!("{}", expected));
+        assert_eq!(item.2.as_ref().map(|v| v.len()), if expected % 2 == 0 { Some(expected as usize) } else { None });
+        expected += 1;
+    });
+    assert_eq!(expected, 10);
+
+    // New objects go into the same chunks they would have in the original.
+    let mut reloaded_bytes = vec![];
+    loaded.serialize_into(&mut reloaded_bytes).unwrap();
+    assert_eq!(bytes, reloaded_bytes);
+
+    assert!(Arena::<(u32, String, Option<Vec<u32>>)>::deserialize_from(&bytes[..bytes.len() - 1]).is_err());
+}
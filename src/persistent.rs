@@ -0,0 +1,170 @@
+//! Persistent arenas, in the sense of persistent data structures: copies
+//! share their chunks until one of them changes.
+
+use std::rc::Rc;
+
+/// An arena whose clones are snapshots of it, each of which can change
+/// independently. Cloning only copies a pointer; changing a snapshot then
+/// copies just the chunk being changed, if it's shared with another one.
+///
+/// Since chunks move when they are copied, objects are referred to by their
+/// position in allocation order rather than by handles.
+///
+/// ```rust
+/// use rc_arena::PersistentArena;
+///
+/// let mut document = PersistentArena::new();
+/// let title = document.alloc("Draft".to_string());
+/// document.alloc("Hello".to_string());
+///
+/// let mut history = vec![document.clone()];
+/// *document.get_mut(title).unwrap() = "Final".to_string();
+/// history.push(document.clone());
+///
+/// // Undo.
+/// let document = history.remove(0);
+/// assert_eq!(document[title], "Draft");
+/// assert_eq!(history[0][title], "Final");
+/// ```
+pub struct PersistentArena<T> {
+    chunks: Rc<Vec<Rc<Vec<T>>>>,
+    first_capacity: usize
+}
+
+impl<T> PersistentArena<T> {
+    /// Create a new arena with an unspecified capacity.
+    pub fn new() -> PersistentArena<T> {
+        PersistentArena::with_capacity(8)
+    }
+
+    /// Create a new arena with a known initial capacity.
+    pub fn with_capacity(n: usize) -> PersistentArena<T> {
+        PersistentArena {
+            chunks: Rc::new(vec![Rc::new(Vec::with_capacity(n))]),
+            first_capacity: std::cmp::max(n, 1)
+        }
+    }
+
+    /// Get the number of objects in the arena.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+
+    /// Returns `true` if there are no objects in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The chunk and position within it of the object at `index`.
+    fn position(&self, index: usize) -> Option<(usize, usize)> {
+        let mut index = index;
+
+        for (chunk_index, chunk) in self.chunks.iter().enumerate() {
+            if index < chunk.len() {
+                return Some((chunk_index, index));
+            }
+            index -= chunk.len();
+        }
+
+        None
+    }
+
+    /// Get a reference to the object at position `index` in allocation
+    /// order.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.position(index).map(|(chunk, index)| &self.chunks[chunk][index])
+    }
+
+    /// Iterate over the objects in the order they were allocated.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Returns `true` if both arenas are the same snapshot, which neither
+    /// has changed since.
+    pub fn ptr_eq(&self, other: &PersistentArena<T>) -> bool {
+        Rc::ptr_eq(&self.chunks, &other.chunks)
+    }
+}
+
+impl<T: Clone> PersistentArena<T> {
+    /// Store an object in the arena, returning its position. Only the last
+    /// chunk is copied, if another snapshot shares it.
+    pub fn alloc(&mut self, value: T) -> usize {
+        let len = self.len();
+        let first_capacity = self.first_capacity;
+        let chunks = Rc::make_mut(&mut self.chunks);
+
+        // Chunks double in size, whether or not they are still in their
+        // original allocation.
+        let capacity = first_capacity.checked_shl(chunks.len() as u32 - 1).unwrap_or(usize::MAX);
+        if chunks[chunks.len() - 1].len() == capacity {
+            chunks.push(Rc::new(Vec::with_capacity(capacity.saturating_mul(2))));
+        }
+
+        let last = chunks.len() - 1;
+        Rc::make_mut(&mut chunks[last]).push(value);
+
+        len
+    }
+
+    /// Get a mutable reference to the object at position `index`, copying
+    /// its chunk first if another snapshot shares it.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (chunk, index) = self.position(index)?;
+        let chunks = Rc::make_mut(&mut self.chunks);
+
+        Some(&mut Rc::make_mut(&mut chunks[chunk])[index])
+    }
+}
+
+impl<T> std::ops::Index<usize> for PersistentArena<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> Clone for PersistentArena<T> {
+    fn clone(&self) -> PersistentArena<T> {
+        PersistentArena {
+            chunks: self.chunks.clone(),
+            first_capacity: self.first_capacity
+        }
+    }
+}
+
+impl<T> Default for PersistentArena<T> {
+    fn default() -> PersistentArena<T> {
+        PersistentArena::new()
+    }
+}
+
+impl<T> std::fmt::Debug for PersistentArena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[test]
+fn copies_on_write() {
+    let mut arena = PersistentArena::with_capacity(2);
+    for i in 0..6 {
+        assert_eq!(arena.alloc(i), i);
+    }
+    let snapshot = arena.clone();
+    assert!(arena.ptr_eq(&snapshot));
+
+    // Only the chunk which changed is copied.
+    *arena.get_mut(0).unwrap() = 10;
+    arena.alloc(6);
+    assert!(!arena.ptr_eq(&snapshot));
+    assert!(!Rc::ptr_eq(&arena.chunks[0], &snapshot.chunks[0]));
+    assert!(Rc::ptr_eq(&arena.chunks[1], &snapshot.chunks[1]));
+
+    assert_eq!(format!("{:?}", snapshot), "[0, 1, 2, 3, 4, 5]");
+    assert_eq!(format!("{:?}", arena), "[10, 1, 2, 3, 4, 5, 6]");
+    assert_eq!(arena.chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(), [2, 4, 1]);
+    assert!(snapshot.get(6).is_none());
+}
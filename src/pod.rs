@@ -0,0 +1,62 @@
+//! Byte views of arenas of plain data, for writing their objects out to a
+//! file or a GPU buffer without copying them, and reading them back.
+
+use std::cell::Ref;
+
+use bytemuck::{Pod, PodCastError};
+
+use Arena;
+
+impl<T: Pod> Arena<T> {
+    /// Borrow the bytes of each chunk of the arena, like `chunks`.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(2);
+    /// for i in 0..3u16 {
+    ///     arena.alloc(i);
+    /// }
+    ///
+    /// let bytes: Vec<u8> = arena.chunks_as_bytes().flat_map(|chunk| chunk.to_vec()).collect();
+    /// let copy = Arena::<u16>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(copy.iter().map(|i| *i).collect::<Vec<_>>(), [0, 1, 2]);
+    /// ```
+    pub fn chunks_as_bytes<'a>(&'a self) -> impl Iterator<Item = Ref<'a, [u8]>> + 'a {
+        self.chunks().map(|chunk| Ref::map(chunk, bytemuck::cast_slice))
+    }
+
+    /// Create an arena holding the objects whose bytes are in `bytes`, which
+    /// needn't be aligned. This fails if the objects are zero-sized, or if
+    /// the length of `bytes` isn't a multiple of their size.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Arena<T>, PodCastError> {
+        let size = std::mem::size_of::<T>();
+        if size == 0 {
+            return Err(PodCastError::SizeMismatch);
+        }
+        if !bytes.len().is_multiple_of(size) {
+            return Err(PodCastError::OutputSliceWouldHaveSlop);
+        }
+
+        Ok(Arena::from_vec(bytemuck::pod_collect_to_vec(bytes)))
+    }
+}
+
+#[test]
+fn byte_views() {
+    let arena = Arena::with_capacity(1);
+    arena.alloc([1u32, 2]);
+    arena.alloc([3, 4]);
+    let lens: Vec<_> = arena.chunks_as_bytes().map(|chunk| chunk.len()).collect();
+    assert_eq!(lens, [8, 8]);
+
+    let bytes: Vec<u8> = arena.chunks_as_bytes().flat_map(|chunk| chunk.to_vec()).collect();
+    // Reading from an odd address works just as well.
+    let mut unaligned = vec![0];
+    unaligned.extend_from_slice(&bytes);
+    let copy = Arena::<[u32; 2]>::from_bytes(&unaligned[1..]).unwrap();
+    assert_eq!(*copy.last().unwrap(), [3, 4]);
+
+    assert_eq!(Arena::<[u32; 2]>::from_bytes(&bytes[1..]).err(), Some(PodCastError::OutputSliceWouldHaveSlop));
+    assert_eq!(Arena::<()>::from_bytes(&[]).err(), Some(PodCastError::SizeMismatch));
+}
@@ -0,0 +1,94 @@
+//! Poisoning the memory of dropped objects and freed chunks, to catch unsafe
+//! code built on top of an arena which keeps using objects after they're
+//! gone.
+//!
+//! Objects and their slots are overwritten with `POISON` once they're
+//! removed by `rollback` or `truncate`, and whole chunks are before their
+//! memory is freed. In debug builds, every use of a handle checks that its
+//! slot hasn't been poisoned, which no real count can be mistaken for.
+
+use std::ptr;
+
+use {Chunk, Items, Slot, Slots};
+
+/// The byte which the memory of dropped objects is filled with.
+pub const POISON: u8 = 0xde;
+
+/// A count made of poison bytes.
+const POISONED: usize = usize::MAX / 0xff * POISON as usize;
+
+/// The memory of a chunk's objects, unless it mustn't be overwritten.
+fn memory<T>(items: &mut Items<T>) -> Option<*mut T> {
+    match *items {
+        Items::Heap(ref mut vec) => Some(vec.as_mut_ptr()),
+        Items::Buffer(ref mut buffer) => Some(buffer.as_mut_ptr()),
+        #[cfg(feature = "allocator-api2")]
+        Items::Allocated(ref mut vec) => Some(vec.as_mut_ptr()),
+        // Mapped objects are written through to their file, and frozen ones
+        // are shared with a `FrozenArena`.
+        _ => None
+    }
+}
+
+/// Poison the unused part of a chunk from `from` onwards, once its objects
+/// from there have been removed.
+pub fn poison_from<T>(chunk: &mut Chunk<T>, from: usize) {
+    if std::mem::size_of::<T>() != 0 {
+        let capacity = chunk.items.capacity();
+        if let Some(memory) = memory(&mut chunk.items) {
+            debug_assert!(chunk.items.len() <= from);
+            unsafe { ptr::write_bytes(memory.add(from), POISON, capacity - from) };
+        }
+    }
+
+    // Entries of an indirection table have their pointers cleared instead.
+    if let Slots::Inline(ref mut slots) = chunk.slots {
+        debug_assert!(slots.len() <= from);
+        let capacity = slots.capacity();
+        unsafe { ptr::write_bytes(slots.as_mut_ptr().add(from), POISON, capacity - from) };
+    }
+}
+
+/// Drop the objects of a chunk which is being freed, and poison its memory.
+pub fn free<T>(chunk: &mut Chunk<T>) {
+    if memory(&mut chunk.items).is_some() {
+        drop(chunk.items.truncate(0));
+    }
+    if let Slots::Inline(ref mut slots) = chunk.slots {
+        slots.clear();
+    }
+
+    poison_from(chunk, 0);
+}
+
+/// Check that a handle's slot hasn't been poisoned.
+pub fn check(slot: &Slot) {
+    debug_assert!(slot.strong.get() != POISONED, "handle to an object which was dropped");
+}
+
+#[test]
+fn poisons() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use Arena;
+
+    let poisoned = u64::from_ne_bytes([POISON; 8]);
+    let arena = Arena::with_capacity(4);
+    arena.alloc(1u64);
+    let checkpoint = arena.checkpoint();
+    let second = arena.alloc(2u64);
+    let ptr = &*second as *const u64;
+
+    // Unsafe code which holds on to a handle past the rollback.
+    let stale = unsafe { ptr::read(&second) };
+    drop(second);
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(unsafe { ptr::read(ptr) }, poisoned);
+    if cfg!(debug_assertions) {
+        assert!(catch_unwind(AssertUnwindSafe(|| *stale)).is_err());
+    }
+    std::mem::forget(stale);
+
+    // The positions are reused as usual.
+    let again = arena.alloc(3u64);
+    assert_eq!((&*again as *const u64, *again), (ptr, 3));
+}
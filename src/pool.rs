@@ -0,0 +1,340 @@
+//! Object pooling: arenas which recycle objects once nothing refers to them,
+//! rather than keeping them around untouched until the arena is dropped.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use {meta, Arena, Chunk, Chunks, Rc, Slot};
+
+/// What an object pool passes its objects to before reusing them.
+pub type Reset<T> = std::rc::Rc<dyn Fn(&mut T)>;
+
+/// The recycled objects of an arena created with `Arena::pool`. Every object
+/// on the free list is held by the pool with a strong reference of its own,
+/// so visiting it with `each` doesn't recycle it a second time.
+pub struct Pool<T> {
+    reset: Reset<T>,
+    free: RefCell<Vec<(*mut T, *const Slot)>>,
+    // For each chunk, a bit for each object which is set while the object is
+    // on the free list, so `each_live` can skip them a word at a time.
+    free_bits: RefCell<Vec<Vec<u64>>>
+}
+
+impl<T> Pool<T> {
+    pub fn new(reset: Reset<T>) -> Pool<T> {
+        Pool {
+            reset,
+            free: RefCell::new(vec![]),
+            free_bits: RefCell::new(vec![])
+        }
+    }
+
+    /// Take an object off the free list, along with the pool's reference
+    /// to it.
+    pub fn take(&self, list: &[Chunk<T>]) -> Option<(*mut T, *const Slot)> {
+        let (ptr, slot) = self.free.borrow_mut().pop()?;
+        self.mark(list, ptr, slot, false);
+
+        Some((ptr, slot))
+    }
+
+    /// Put an object on the free list.
+    fn put(&self, list: &[Chunk<T>], ptr: *mut T, slot: *const Slot) {
+        self.free.borrow_mut().push((ptr, slot));
+        self.mark(list, ptr, slot, true);
+    }
+
+    fn mark(&self, list: &[Chunk<T>], ptr: *mut T, slot: *const Slot, free: bool) {
+        let position = list.iter().enumerate().filter_map(|(chunk, items)| {
+            items.index_of(ptr, slot).map(|index| (chunk, index))
+        }).next();
+        let (chunk, index) = match position {
+            Some(position) => position,
+            None => return
+        };
+
+        let mut free_bits = self.free_bits.borrow_mut();
+        if free_bits.len() <= chunk {
+            free_bits.resize(chunk + 1, vec![]);
+        }
+        let words = &mut free_bits[chunk];
+        if words.len() <= index / 64 {
+            words.resize(index / 64 + 1, 0);
+        }
+
+        let bit = 1 << (index % 64);
+        if free {
+            words[index / 64] |= bit;
+        } else {
+            words[index / 64] &= !bit;
+        }
+    }
+
+    /// The word of bits for the objects of `chunk` from `index` onwards
+    /// which are on the free list.
+    pub fn free_word(&self, chunk: usize, index: usize) -> u64 {
+        let free_bits = self.free_bits.borrow();
+
+        free_bits.get(chunk).and_then(|words| words.get(index / 64)).map_or(0, |&word| word)
+    }
+
+    /// Forget about the free objects of `chunk` from `len` onwards, and of
+    /// the chunks after it, once they have been rolled back.
+    pub fn truncate(&self, chunk: usize, len: usize) {
+        let mut free_bits = self.free_bits.borrow_mut();
+        free_bits.truncate(chunk + 1);
+
+        if let Some(words) = free_bits.get_mut(chunk) {
+            words.truncate(len / 64 + 1);
+            if let Some(word) = words.get_mut(len / 64) {
+                *word &= (1 << (len % 64)) - 1;
+            }
+        }
+    }
+
+    /// The slots on the free list, which are only referenced by the pool.
+    pub fn free_slots(&self) -> HashSet<*const Slot> {
+        self.free.borrow().iter().map(|&(_, slot)| slot).collect()
+    }
+
+    /// Check that the free list holds objects of `list` which nothing but
+    /// the pool refers to, each once, and that exactly those are marked.
+    pub fn assert_invariants(&self, list: &[Chunk<T>]) {
+        let free = self.free.borrow();
+        let mut seen = HashSet::new();
+
+        for &(ptr, slot) in free.iter() {
+            assert!(seen.insert(slot), "an object is on the free list twice");
+
+            let position = list.iter().enumerate().filter_map(|(chunk, items)| {
+                items.index_of(ptr, slot).map(|index| (chunk, index))
+            }).next();
+            let (chunk, index) = position.expect("the free list holds an object from another arena");
+
+            let slot = unsafe { &*slot };
+            assert!(slot.strong.get() == 1 && slot.weak.get() == 0,
+                    "object {} of chunk {} is on the free list but still referenced", index, chunk);
+            assert!(self.free_word(chunk, index) & (1 << (index % 64)) != 0,
+                    "object {} of chunk {} is on the free list but isn't marked free", index, chunk);
+        }
+
+        let marked: u32 = self.free_bits.borrow().iter().flatten().map(|word| word.count_ones()).sum();
+        assert!(marked as usize == free.len(),
+                "{} objects are marked free, but {} are on the free list", marked, free.len());
+    }
+
+    /// Forget about the objects whose slots are in `removed`.
+    pub fn forget(&self, removed: &HashSet<*const Slot>) {
+        self.free.borrow_mut().retain(|&(_, slot)| !removed.contains(&slot));
+    }
+}
+
+/// Called when the last `Rc` or `Weak` to an object is dropped, to recycle
+/// it if the arena is a pool.
+pub fn release<T>(chunks: &std::rc::Rc<RefCell<Chunks<T>>>, ptr: *mut T, slot: &Slot) {
+    // If nothing else holds the arena, everything is about to be dropped.
+    if std::rc::Rc::strong_count(chunks) == 1 {
+        return;
+    }
+
+    let reset = match chunks.borrow().pool {
+        Some(ref pool) => pool.reset.clone(),
+        None => return
+    };
+
+    // Leaves the object unreferenced rather than recycled if `reset` panics.
+    struct Unrecycled<'a>(&'a Slot);
+
+    impl<'a> Drop for Unrecycled<'a> {
+        fn drop(&mut self) {
+            self.0.strong.set(0);
+        }
+    }
+
+    slot.strong.set(1);
+    let unrecycled = Unrecycled(slot);
+    // Nothing else refers to the object, so we have exclusive access to it.
+    reset(unsafe { &mut *ptr });
+    std::mem::forget(unrecycled);
+
+    let mut chunks = chunks.borrow_mut();
+    meta::reset(&mut chunks, ptr, slot);
+    if let Some(ref pool) = chunks.pool {
+        pool.put(&chunks.list, ptr, slot);
+    }
+}
+
+impl<T> Arena<T> {
+    /// Like `each`, but skips the objects which are only kept by an object
+    /// pool for reuse, so that only objects in use are visited. The pool
+    /// keeps track of them with a bit per object, so a sparse pool is
+    /// skipped through quickly.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::pool(|_: &mut usize| {});
+    /// let handles: Vec<_> = (0..100).map(|i| arena.alloc(i)).collect();
+    /// let odd: Vec<_> = handles.into_iter().filter(|n| **n % 2 == 1).collect();
+    ///
+    /// let mut sum = 0;
+    /// arena.each_live(|n| sum += **n);
+    /// assert_eq!(sum, 2500);
+    /// assert_eq!(odd.len(), 50);
+    /// ```
+    pub fn each_live<F: for<'a> FnMut(&'a Rc<T>)>(&self, mut f: F) {
+        let chunks = self.chunks.borrow();
+
+        for (chunk_index, chunk) in chunks.list.iter().enumerate() {
+            let len = chunk.items.len();
+
+            for start in (0..len).step_by(64) {
+                let mut live = !chunks.pool.as_ref().map_or(0, |pool| pool.free_word(chunk_index, start));
+                if len - start < 64 {
+                    live &= (1 << (len - start)) - 1;
+                }
+
+                while live != 0 {
+                    let (ptr, slot) = chunk.handle(start + live.trailing_zeros() as usize);
+                    live &= live - 1;
+
+                    f(&Rc::new_handle(self.chunks.clone(), ptr, slot));
+                }
+            }
+        }
+    }
+
+    /// Create a new object pool with an unspecified capacity. See
+    /// `pool_with_capacity`.
+    pub fn pool<F: Fn(&mut T) + 'static>(reset: F) -> Arena<T> {
+        Arena::pool_with_capacity(8, reset)
+    }
+
+    /// Create a new object pool with a known initial capacity. Once the last
+    /// `Rc` or `Weak` to an object is dropped, the object is passed to
+    /// `reset` and kept for reuse: `alloc` then stores new values in its
+    /// place, and `recycle` hands it out again as it is.
+    ///
+    /// Recycled objects keep their original position in the arena, and are
+    /// visited by `each` along with everything else. `reset` must not use
+    /// the arena.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let buffers = Arena::pool_with_capacity(16, |buffer: &mut Vec<u8>| buffer.clear());
+    ///
+    /// let mut buffer = buffers.alloc(Vec::with_capacity(1024));
+    /// drop(buffer);
+    ///
+    /// let buffer = buffers.recycle().unwrap();
+    /// assert!(buffer.is_empty());
+    /// assert_eq!(buffer.capacity(), 1024);
+    /// assert_eq!(buffers.len(), 1);
+    /// ```
+    pub fn pool_with_capacity<F: Fn(&mut T) + 'static>(n: usize, reset: F) -> Arena<T> {
+        let mut chunks = Chunks::new(vec![Chunk::with_capacity(n)]);
+        chunks.pool = Some(Pool::new(std::rc::Rc::new(reset)));
+
+        Arena::from_chunks(chunks)
+    }
+
+    /// Take an object which has been reset and kept for reuse by an object
+    /// pool. This returns `None` if there aren't any, if the arena wasn't
+    /// created with `pool`, or if it has been sealed.
+    pub fn recycle(&self) -> Option<Rc<T>> {
+        let chunks = self.chunks.borrow();
+        let (ptr, slot) = match chunks.pool {
+            Some(ref pool) if !chunks.sealed => pool.take(&chunks.list)?,
+            _ => return None
+        };
+        drop(chunks);
+
+        // The pool's reference is handed over to the new handle.
+        Some(Rc {
+            chunks: self.chunks.clone(),
+            _ptr: ptr,
+            slot
+        })
+    }
+}
+
+#[test]
+fn recycles() {
+    use std::cell::Cell;
+
+    let resets = std::rc::Rc::new(Cell::new(0));
+    let counter = resets.clone();
+    let arena = Arena::pool_with_capacity(2, move |value: &mut Vec<usize>| {
+        counter.set(counter.get() + 1);
+        value.clear();
+    });
+
+    let a = arena.alloc(vec![1, 2, 3]);
+    let b = arena.alloc(vec![4]);
+    let weak = Rc::downgrade(&b);
+    drop(a);
+    drop(b);
+    assert_eq!(resets.get(), 1);
+
+    // Visiting recycled objects doesn't recycle them again.
+    arena.each(|_| {});
+    assert_eq!(resets.get(), 1);
+
+    let c = arena.alloc(vec![5, 6]);
+    assert_eq!(*c, vec![5, 6]);
+    assert_eq!(arena.len(), 2);
+    assert!(arena.recycle().is_none());
+
+    drop(weak);
+    assert_eq!(resets.get(), 2);
+    let d = arena.recycle().unwrap();
+    assert!(d.is_empty());
+    assert_eq!(arena.len(), 2);
+
+    drop(d);
+    drop(c);
+    assert_eq!(resets.get(), 4);
+
+    // Objects which are only held by the pool can be rolled back.
+    let held = (arena.recycle().unwrap(), arena.recycle().unwrap());
+    let checkpoint = arena.checkpoint();
+    let added: Vec<_> = (0..3).map(|_| arena.alloc(vec![])).collect();
+    drop(added);
+    assert_eq!(arena.len(), 5);
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(arena.len(), 2);
+    assert!(arena.recycle().is_none());
+
+    drop(held);
+    let again = (arena.recycle(), arena.recycle(), arena.recycle());
+    assert!(again.0.is_some() && again.1.is_some() && again.2.is_none());
+}
+
+#[test]
+fn skips_free_objects() {
+    let arena = Arena::pool_with_capacity(100, |value: &mut usize| *value = 0);
+    let mut handles: Vec<_> = (1..=150).map(|i| Some(arena.alloc(i))).collect();
+    for i in (0..150).filter(|i| i % 3 != 0) {
+        handles[i] = None;
+    }
+
+    let live = |arena: &Arena<usize>| {
+        let mut live = vec![];
+        arena.each_live(|rc| live.push(**rc));
+        live
+    };
+    assert_eq!(live(&arena), (1..=150).step_by(3).collect::<Vec<_>>());
+
+    // Recycled objects are in use again, and rolled back ones are gone.
+    let recycled = arena.alloc(1000);
+    assert_eq!(live(&arena).len(), 51);
+    let checkpoint = arena.checkpoint();
+    drop(arena.alloc(2000));
+    arena.rollback(checkpoint).unwrap();
+    drop(handles);
+    assert_eq!(live(&arena), [1000]);
+    drop(recycled);
+    assert!(live(&arena).is_empty());
+    assert_eq!(arena.len(), 150);
+}
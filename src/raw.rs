@@ -0,0 +1,127 @@
+//! Handles as raw pointers to their objects, for passing through code which
+//! only knows about pointers, such as C callbacks, and managing their counts
+//! by hand like `std::rc::Rc::increment_strong_count` does.
+//!
+//! A pointer to an object doesn't say where its count or its arena is, so
+//! unlike the standard library's functions these take the arena too.
+
+use {Arena, Chunks, Rc, Slot};
+
+/// The pointers a handle to the object at `ptr` is made of.
+fn find<T>(chunks: &Chunks<T>, ptr: *const T) -> Option<(*mut T, *const Slot)> {
+    assert!(std::mem::size_of::<T>() != 0, "zero-sized objects can't be told apart by their address");
+
+    for chunk in chunks.list.iter() {
+        let offset = (ptr as usize).wrapping_sub(chunk.items.as_ptr() as usize);
+        let index = offset / std::mem::size_of::<T>();
+
+        if index < chunk.items.len() && offset.is_multiple_of(std::mem::size_of::<T>()) {
+            return Some(chunk.handle(index));
+        }
+    }
+
+    None
+}
+
+impl<T> Rc<T> {
+    /// Give up the handle, returning a pointer to its object without
+    /// changing its strong count. The handle can be rebuilt from the pointer
+    /// with `Arena::rc_from_raw`, or its count given back with
+    /// `Arena::decrement_strong_count`.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, Rc};
+    ///
+    /// let arena = Arena::new();
+    /// let name = arena.alloc(String::from("rc_arena"));
+    /// let weak = Rc::downgrade(&name);
+    ///
+    /// let ptr = Rc::into_raw(name);
+    /// unsafe {
+    ///     arena.increment_strong_count(ptr);
+    ///     assert_eq!(*arena.rc_from_raw(ptr), "rc_arena");
+    ///     arena.decrement_strong_count(ptr);
+    /// }
+    /// assert!(weak.upgrade().is_some());
+    /// ```
+    pub fn into_raw(this: Rc<T>) -> *const T {
+        let ptr = this.ptr();
+        std::mem::forget(this);
+
+        ptr
+    }
+}
+
+impl<T> Arena<T> {
+    /// Rebuild a handle given up by `Rc::into_raw`, taking over its strong
+    /// count.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from `Rc::into_raw` for an object of this arena,
+    /// and there must be a strong count to take over for it. An indirect
+    /// arena must not have been compacted since.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the objects are zero-sized, or if `ptr` doesn't point at an
+    /// object of this arena.
+    pub unsafe fn rc_from_raw(&self, ptr: *const T) -> Rc<T> {
+        let (ptr, slot) = find(&self.chunks.borrow(), ptr).expect("pointer must point into this arena");
+
+        Rc {
+            chunks: self.chunks.clone(),
+            _ptr: ptr,
+            slot
+        }
+    }
+
+    /// Add one to the strong count of the object at `ptr`, as if a handle to
+    /// it had been cloned and given up with `Rc::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `rc_from_raw`, except that the count isn't taken over.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `rc_from_raw`.
+    pub unsafe fn increment_strong_count(&self, ptr: *const T) {
+        let rc = self.rc_from_raw(ptr);
+        std::mem::forget(rc.clone());
+        std::mem::forget(rc);
+    }
+
+    /// Take one from the strong count of the object at `ptr`, as if a handle
+    /// to it had been rebuilt with `rc_from_raw` and dropped.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `rc_from_raw`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `rc_from_raw`.
+    pub unsafe fn decrement_strong_count(&self, ptr: *const T) {
+        drop(self.rc_from_raw(ptr));
+    }
+}
+
+#[test]
+fn manages_counts() {
+    let arena = Arena::pool_with_capacity(2, |value: &mut u32| *value = 0);
+    let handles: Vec<_> = (1..5).map(|i| arena.alloc(i)).collect();
+    let ptrs: Vec<_> = handles.into_iter().map(Rc::into_raw).collect();
+
+    unsafe {
+        arena.increment_strong_count(ptrs[3]);
+        let last = arena.rc_from_raw(ptrs[3]);
+        assert_eq!(*last, 4);
+        arena.decrement_strong_count(ptrs[3]);
+
+        // The last count going away recycles the object.
+        arena.decrement_strong_count(ptrs[1]);
+        assert_eq!(&*arena.alloc(5) as *const u32, ptrs[1]);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.rc_from_raw(&0))).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! A summary of how an arena's memory is laid out and used, for tuning its
+//! initial capacity and growth.
+
+use std::fmt;
+
+use Arena;
+
+/// How one chunk of an arena is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkReport {
+    /// The number of objects the chunk has room for.
+    pub capacity: usize,
+    /// The number of objects which have been placed in the chunk.
+    pub len: usize,
+    /// The number of objects which are still referenced by an `Rc`.
+    pub live: usize,
+    /// The number of objects which aren't referenced by an `Rc` anymore, but
+    /// still take up room until the arena is dropped or they are recycled.
+    pub dropped: usize,
+    /// The bytes taken up by unused capacity and by dropped objects.
+    pub wasted_bytes: usize
+}
+
+/// A report on the chunks of an arena, returned by `Arena::debug_report`.
+/// Its `Display` implementation writes one line per chunk, for logging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugReport {
+    /// The size of each object in bytes.
+    pub object_size: usize,
+    /// Each chunk, in allocation order.
+    pub chunks: Vec<ChunkReport>
+}
+
+impl DebugReport {
+    /// The number of objects the arena has room for without growing.
+    pub fn capacity(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.capacity).sum()
+    }
+
+    /// The number of objects in the arena.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len).sum()
+    }
+
+    /// Returns `true` if there are no objects in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of objects which are still referenced.
+    pub fn live(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.live).sum()
+    }
+
+    /// The number of objects which aren't referenced anymore.
+    pub fn dropped(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.dropped).sum()
+    }
+
+    /// The bytes taken up by unused capacity and by dropped objects.
+    pub fn wasted_bytes(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.wasted_bytes).sum()
+    }
+}
+
+impl fmt::Display for DebugReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} objects of {} bytes in {} chunks, {} live, {} dropped, {} bytes wasted",
+                 self.len(), self.object_size, self.chunks.len(), self.live(), self.dropped(), self.wasted_bytes())?;
+
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            writeln!(f, "  chunk {}: {}/{} filled, {} live, {} dropped, {} bytes wasted",
+                     index, chunk.len, chunk.capacity, chunk.live, chunk.dropped, chunk.wasted_bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Arena<T> {
+    /// Report on the capacity and fill of each chunk, and on how many of the
+    /// objects in it are still referenced.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(4);
+    /// let kept: Vec<_> = (0..6u32).map(|i| arena.alloc(i)).filter(|i| **i % 2 == 0).collect();
+    ///
+    /// let report = arena.debug_report();
+    /// assert_eq!((report.len(), report.live(), report.dropped()), (6, 3, 3));
+    /// assert_eq!(report.chunks[1].capacity, 8);
+    /// // Six unused objects in the second chunk, and three dropped ones.
+    /// assert_eq!(report.wasted_bytes(), (6 + 3) * 4);
+    /// # drop(kept);
+    /// ```
+    pub fn debug_report(&self) -> DebugReport {
+        let chunks = self.chunks.borrow();
+        let object_size = std::mem::size_of::<T>();
+
+        DebugReport {
+            object_size,
+            chunks: chunks.list.iter().map(|chunk| {
+                let len = chunk.items.len();
+                let live = (0..len).filter(|&index| unsafe { (*chunk.handle(index).1).strong.get() } > 0).count();
+                let capacity = chunk.capacity();
+
+                ChunkReport {
+                    capacity,
+                    len,
+                    live,
+                    dropped: len - live,
+                    wasted_bytes: (capacity - live) * object_size
+                }
+            }).collect()
+        }
+    }
+}
+
+#[test]
+fn reports() {
+    let arena = Arena::with_capacity(2);
+    let first = arena.alloc([0u8; 3]);
+    let rest = arena.alloc_n(3, |_| [1u8; 3]);
+    drop(first);
+    let kept = rest[2].clone();
+    drop(rest);
+
+    let report = arena.debug_report();
+    assert_eq!(report.object_size, 3);
+    assert_eq!(report.chunks, [
+        ChunkReport { capacity: 2, len: 1, live: 0, dropped: 1, wasted_bytes: 6 },
+        ChunkReport { capacity: 4, len: 3, live: 1, dropped: 2, wasted_bytes: 9 }
+    ]);
+    assert_eq!(report.capacity(), 6);
+    assert!(report.to_string().starts_with("4 objects of 3 bytes in 2 chunks, 1 live, 3 dropped, 15 bytes wasted\n"));
+    assert_eq!(kept[0], 1);
+
+    let empty = Arena::<u8>::new().debug_report();
+    assert!(empty.is_empty());
+    assert_eq!(empty.wasted_bytes(), 8);
+}
@@ -0,0 +1,136 @@
+//! Sealed arenas, which can still be read through but never grow again.
+
+use std::cell::Ref;
+
+use {Arena, Handle, Iter, Rc};
+#[cfg(feature = "futures")]
+use stream;
+
+/// A view of an arena returned by `Arena::seal`, which has every method of
+/// `Arena` that reads from it, but none that allocate. The arena's other
+/// clones can't allocate anymore either, so the objects' positions and the
+/// arena's chunks stay as they are for as long as it lives.
+pub struct SealedArena<T> {
+    arena: Arena<T>
+}
+
+impl<T> SealedArena<T> {
+    /// Get the number of objects placed in the arena.
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Returns `true` if no objects have been placed in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Returns `true` if `rc` points at an object of this arena.
+    pub fn owns(&self, rc: &Rc<T>) -> bool {
+        self.arena.owns(rc)
+    }
+
+    /// Get the position of `rc`'s object in allocation order, as with
+    /// `Arena::index_of`.
+    pub fn index_of(&self, rc: &Rc<T>) -> Option<usize> {
+        self.arena.index_of(rc)
+    }
+
+    /// Get the object a `Handle` refers to, as with `Arena::resolve`.
+    pub fn resolve(&self, handle: Handle<T>) -> Rc<T> {
+        self.arena.resolve(handle)
+    }
+
+    /// Iterate over the objects of the arena.
+    pub fn iter(&self) -> Iter<T> {
+        self.arena.iter()
+    }
+
+    /// Visit every object of the arena, as with `Arena::each`.
+    pub fn each<F: for<'a> FnMut(&'a Rc<T>)>(&self, f: F) {
+        self.arena.each(f)
+    }
+
+    /// Iterate over the chunks of the arena as slices.
+    pub fn chunks<'a>(&'a self) -> impl Iterator<Item = Ref<'a, [T]>> + 'a {
+        self.arena.chunks()
+    }
+}
+
+impl<T> Clone for SealedArena<T> {
+    fn clone(&self) -> SealedArena<T> {
+        SealedArena {
+            arena: Arena::from_shared(self.arena.chunks.clone())
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for SealedArena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.arena.fmt(f)
+    }
+}
+
+impl<T> Arena<T> {
+    /// Seal the arena, so that nothing more can be allocated in it, and get a
+    /// view of it without any way to allocate.
+    ///
+    /// Allocating through a clone of the arena, or one recovered with
+    /// `Rc::arena`, panics from then on, and a pool stops recycling objects.
+    /// Existing handles keep working, and the objects can still be changed
+    /// through interior mutability.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let one = arena.alloc(1);
+    /// arena.alloc(2);
+    ///
+    /// let sealed = arena.seal();
+    /// assert_eq!(sealed.index_of(&one), Some(0));
+    /// assert_eq!(sealed.iter().map(|rc| *rc).sum::<usize>(), 3);
+    /// assert!(arena.is_sealed());
+    /// ```
+    pub fn seal(&self) -> SealedArena<T> {
+        {
+            let mut chunks = self.chunks.borrow_mut();
+            chunks.sealed = true;
+            // Subscriptions end once they have seen everything.
+            #[cfg(feature = "futures")]
+            stream::notify(&mut chunks);
+        }
+
+        SealedArena {
+            arena: Arena::from_shared(self.chunks.clone())
+        }
+    }
+
+    /// Returns `true` if the arena has been sealed with `Arena::seal`.
+    pub fn is_sealed(&self) -> bool {
+        self.chunks.borrow().sealed
+    }
+}
+
+#[test]
+fn seals() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let arena = Arena::pool_with_capacity(2, |_: &mut usize| {});
+    let handles: Vec<_> = (0..3).map(|i| arena.alloc(i)).collect();
+    drop(arena.alloc(3));
+    assert!(!arena.is_sealed());
+
+    let sealed = arena.seal();
+    assert_eq!(sealed.len(), 4);
+    assert!(sealed.owns(&handles[2]));
+    assert_eq!(sealed.chunks().count(), 2);
+
+    // Neither the pool nor a fresh chunk can provide room anymore.
+    assert!(arena.recycle().is_none());
+    assert!(catch_unwind(AssertUnwindSafe(|| arena.alloc(4))).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| Rc::arena(&handles[0]).alloc_n(2, |i| i))).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| arena.alloc_cyclic(|_| 5))).is_err());
+    assert_eq!(sealed.len(), 4);
+    assert_eq!(format!("{:?}", sealed.clone()), "[0, 1, 2, 3]");
+}
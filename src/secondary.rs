@@ -0,0 +1,149 @@
+//! Extra data for the objects of an arena, stored outside of the objects.
+
+use {Arena, Rc};
+
+/// A map from the objects of an arena to values of another type, stored
+/// densely by each object's position in allocation order. Analysis passes
+/// can use it to annotate objects without making room for that in the
+/// objects themselves.
+///
+/// The map keeps the arena alive. Positions only stay meaningful while the
+/// arena isn't rolled back, truncated or compacted.
+///
+/// ```rust
+/// use rc_arena::{Arena, SecondaryMap};
+///
+/// let arena = Arena::new();
+/// let a = arena.alloc("a");
+/// let b = arena.alloc("b");
+///
+/// let mut depths = SecondaryMap::new(&arena);
+/// depths.insert(&b, 1);
+/// assert_eq!(depths.get(&a), None);
+/// assert_eq!(depths.get(&b), Some(&1));
+/// ```
+pub struct SecondaryMap<T, V> {
+    arena: Arena<T>,
+    values: Vec<Option<V>>,
+    len: usize
+}
+
+impl<T, V> SecondaryMap<T, V> {
+    /// Create an empty map for the objects of `arena`.
+    pub fn new(arena: &Arena<T>) -> SecondaryMap<T, V> {
+        SecondaryMap {
+            arena: Arena::from_shared(arena.chunks.clone()),
+            values: vec![],
+            len: 0
+        }
+    }
+
+    /// Get the number of objects with a value.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no object has a value.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Store a value for the object `rc` points at, returning the one it
+    /// had before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object isn't in the map's arena.
+    pub fn insert(&mut self, rc: &Rc<T>, value: V) -> Option<V> {
+        let index = self.arena.index_of(rc).expect("object isn't in the map's arena");
+
+        // Make room for every object allocated so far, so that the map
+        // doesn't have to grow again for each one.
+        if index >= self.values.len() {
+            let len = std::cmp::max(index + 1, self.arena.len());
+            self.values.resize_with(len, || None);
+        }
+
+        let old = self.values[index].replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+
+        old
+    }
+
+    /// Remove the value of the object `rc` points at.
+    pub fn remove(&mut self, rc: &Rc<T>) -> Option<V> {
+        let old = self.slot(rc)?.take();
+        if old.is_some() {
+            self.len -= 1;
+        }
+
+        old
+    }
+
+    /// Get the value of the object `rc` points at.
+    pub fn get(&self, rc: &Rc<T>) -> Option<&V> {
+        let index = self.arena.index_of(rc)?;
+
+        self.values.get(index)?.as_ref()
+    }
+
+    /// Get a mutable reference to the value of the object `rc` points at.
+    pub fn get_mut(&mut self, rc: &Rc<T>) -> Option<&mut V> {
+        self.slot(rc)?.as_mut()
+    }
+
+    /// Returns `true` if the object `rc` points at has a value.
+    pub fn contains(&self, rc: &Rc<T>) -> bool {
+        self.get(rc).is_some()
+    }
+
+    /// Get the value of the object at position `index` in allocation order.
+    pub fn get_index(&self, index: usize) -> Option<&V> {
+        self.values.get(index)?.as_ref()
+    }
+
+    /// Iterate over the positions of the objects with a value, along with
+    /// their values.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &V)> {
+        self.values.iter().enumerate().filter_map(|(index, value)| value.as_ref().map(|value| (index, value)))
+    }
+
+    fn slot(&mut self, rc: &Rc<T>) -> Option<&mut Option<V>> {
+        let index = self.arena.index_of(rc)?;
+
+        self.values.get_mut(index)
+    }
+}
+
+impl<T, V> std::fmt::Debug for SecondaryMap<T, V> where V: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[test]
+fn annotates() {
+    let arena = Arena::with_capacity(2);
+    let handles: Vec<_> = (0..5).map(|i| arena.alloc(i)).collect();
+
+    let mut squares = SecondaryMap::new(&arena);
+    for handle in handles.iter().step_by(2) {
+        assert!(squares.insert(handle, **handle * **handle).is_none());
+    }
+    assert_eq!(squares.insert(&handles[4], 0), Some(16));
+    *squares.get_mut(&handles[4]).unwrap() = 16;
+
+    // Objects allocated after the map was created can have values too.
+    let late = arena.alloc(9);
+    assert!(!squares.contains(&late));
+    squares.insert(&late, 81);
+    assert_eq!(squares.len(), 4);
+    assert_eq!(squares.get_index(5), Some(&81));
+
+    assert_eq!(squares.remove(&handles[0]), Some(0));
+    assert_eq!(squares.remove(&handles[1]), None);
+    assert!(squares.get(&Arena::new().alloc(2)).is_none());
+    assert_eq!(format!("{:?}", squares), "{2: 4, 4: 16, 5: 81}");
+}
@@ -0,0 +1,112 @@
+//! A set of arenas with one for each type of object, created the first time
+//! an object of that type is stored, for frameworks which offer a single
+//! place to allocate anything in.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use {Arena, Rc};
+
+/// A set of arenas, one for each type of object which has been stored in it.
+/// Storing an object of a type for the first time creates its arena, much
+/// like a struct with an arena field for each type, without having to know
+/// the types up front.
+///
+/// Objects are dropped along with their arena, once the set and every `Rc`
+/// to an object of that type are gone.
+///
+/// ```rust
+/// use rc_arena::ArenaSet;
+///
+/// let frame = ArenaSet::new();
+/// let name = frame.alloc(String::from("player"));
+/// let position = frame.alloc([1.0f32, 2.0]);
+/// frame.alloc([3.0f32, 4.0]);
+///
+/// assert_eq!((&**name, *position), ("player", [1.0, 2.0]));
+/// assert_eq!(frame.arena::<[f32; 2]>().len(), 2);
+/// assert_eq!(frame.len(), 2);
+/// ```
+#[derive(Default)]
+pub struct ArenaSet {
+    // Each arena is boxed, so that it stays put while others are added.
+    arenas: RefCell<HashMap<TypeId, Box<dyn Any>>>
+}
+
+impl ArenaSet {
+    /// Create a new set without any arenas.
+    pub fn new() -> ArenaSet {
+        ArenaSet::default()
+    }
+
+    /// Store an object in the arena for its type, creating the arena if
+    /// there isn't one yet.
+    pub fn alloc<T: 'static>(&self, value: T) -> Rc<T> {
+        self.arena::<T>().alloc(value)
+    }
+
+    /// Get the arena for objects of type `T`, creating it if there isn't one
+    /// yet.
+    pub fn arena<T: 'static>(&self) -> &Arena<T> {
+        let mut arenas = self.arenas.borrow_mut();
+        let arena = arenas.entry(TypeId::of::<T>()).or_insert_with(|| Box::new(Arena::<T>::new()));
+
+        // Arenas are only removed from the set through `&mut self`.
+        unsafe { &*(arena.downcast_ref::<Arena<T>>().unwrap() as *const Arena<T>) }
+    }
+
+    /// Get the arena for objects of type `T`, if any have been stored.
+    pub fn get<T: 'static>(&self) -> Option<&Arena<T>> {
+        let arenas = self.arenas.borrow();
+        let arena = arenas.get(&TypeId::of::<T>())?.downcast_ref::<Arena<T>>()?;
+
+        // As in `arena`.
+        Some(unsafe { &*(arena as *const Arena<T>) })
+    }
+
+    /// Get the number of arenas in the set, one for each type of object.
+    pub fn len(&self) -> usize {
+        self.arenas.borrow().len()
+    }
+
+    /// Returns `true` if no objects have been stored in the set.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every arena from the set, dropping their objects unless an
+    /// `Rc` to them is still held.
+    pub fn clear(&mut self) {
+        self.arenas.get_mut().clear();
+    }
+}
+
+impl std::fmt::Debug for ArenaSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_struct("ArenaSet").field("arenas", &self.len()).finish()
+    }
+}
+
+#[test]
+fn allocates_by_type() {
+    let token = std::rc::Rc::new(());
+    let mut set = ArenaSet::new();
+    assert!(set.get::<u32>().is_none());
+
+    let first = set.alloc(1u32);
+    let arena = set.arena::<u32>();
+    // Creating arenas for other types leaves the first one where it is.
+    for i in 0..100u64 {
+        set.alloc(i);
+    }
+    set.alloc(token.clone());
+    set.alloc(2u32);
+    assert_eq!(arena.to_vec(), [1, 2]);
+    assert!(std::ptr::eq(arena, set.get::<u32>().unwrap()));
+    assert_eq!((set.len(), set.arena::<u64>().len()), (3, 100));
+
+    set.clear();
+    assert!(set.is_empty());
+    assert_eq!((*first, std::rc::Rc::strong_count(&token)), (1, 1));
+}
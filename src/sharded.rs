@@ -0,0 +1,180 @@
+//! Arenas split into shards, one for each thread which allocates, so that
+//! fork-join workloads can build their objects without contending, and then
+//! work through all of them together.
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::marker::PhantomData;
+use std::sync::{Mutex, PoisonError};
+
+/// An arena which threads allocate in through a `Shard` each, with its own
+/// chunks, so that allocating never waits for another thread. The shard's
+/// objects join the arena once it's dropped, and are visited by `iter` from
+/// then on, a shard at a time in the order they were dropped.
+///
+/// Objects are dropped along with the arena, and are borrowed from it until
+/// then, much like those of an arena which hands out references.
+///
+/// ```rust
+/// use rc_arena::ShardedArena;
+///
+/// let arena = ShardedArena::new();
+/// std::thread::scope(|scope| {
+///     for thread in 0..4 {
+///         let mut shard = arena.shard();
+///         scope.spawn(move || {
+///             for i in 0..100 {
+///                 shard.alloc(thread * 100 + i);
+///             }
+///         });
+///     }
+/// });
+///
+/// assert_eq!(arena.len(), 400);
+/// assert_eq!(arena.iter().sum::<usize>(), 79800);
+/// ```
+pub struct ShardedArena<T> {
+    // The chunks of each shard which has been dropped.
+    shards: Mutex<Vec<Vec<Vec<T>>>>,
+    capacity: usize,
+    // Objects are shared with every thread which borrows the arena, so it's
+    // only `Sync` if they are.
+    _marker: PhantomData<T>
+}
+
+impl<T> ShardedArena<T> {
+    /// Create a new arena with an unspecified capacity for each shard.
+    pub fn new() -> ShardedArena<T> {
+        ShardedArena::with_capacity(64)
+    }
+
+    /// Create a new arena whose shards start out with room for `n` objects.
+    pub fn with_capacity(n: usize) -> ShardedArena<T> {
+        ShardedArena {
+            shards: Mutex::new(vec![]),
+            capacity: std::cmp::max(n, 1),
+            _marker: PhantomData
+        }
+    }
+
+    /// Start a new shard, for a thread to allocate in.
+    pub fn shard(&self) -> Shard<'_, T> {
+        Shard {
+            arena: self,
+            chunks: vec![]
+        }
+    }
+
+    /// The objects of each chunk of the shards which have been dropped.
+    fn chunks(&self) -> Vec<&[T]> {
+        let shards = self.shards.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // The chunks of a dropped shard are never written to again, and live
+        // as long as the arena, while their objects are borrowed from it.
+        shards.iter().flat_map(|chunks| chunks.iter()).map(|chunk| {
+            unsafe { std::slice::from_raw_parts(chunk.as_ptr(), chunk.len()) }
+        }).collect()
+    }
+
+    /// Get the number of objects in the shards which have been dropped.
+    pub fn len(&self) -> usize {
+        self.chunks().iter().map(|chunk| chunk.len()).sum()
+    }
+
+    /// Returns `true` if no shard which has been dropped stored any objects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the objects in the shards which have been dropped.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks().into_iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Iterate over the objects in the shards which have been dropped in
+    /// parallel, splitting the work by chunk and then within chunks.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = &T> where T: Sync {
+        self.chunks().into_par_iter().flat_map(|chunk| chunk.par_iter())
+    }
+}
+
+impl<T> Default for ShardedArena<T> {
+    fn default() -> ShardedArena<T> {
+        ShardedArena::new()
+    }
+}
+
+impl<T> std::fmt::Debug for ShardedArena<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A part of a `ShardedArena` which a single thread allocates in, created
+/// by `ShardedArena::shard`.
+pub struct Shard<'a, T: 'a> {
+    arena: &'a ShardedArena<T>,
+    chunks: Vec<Vec<T>>
+}
+
+impl<'a, T> Shard<'a, T> {
+    /// Store an object in the shard, returning a reference to it which lasts
+    /// as long as the arena is borrowed.
+    pub fn alloc(&mut self, value: T) -> &'a T {
+        let full = self.chunks.last().is_none_or(|chunk| chunk.len() == chunk.capacity());
+        if full {
+            let capacity = self.chunks.last().map_or(self.arena.capacity, |chunk| chunk.capacity() * 2);
+            self.chunks.push(Vec::with_capacity(capacity));
+        }
+
+        let chunk = self.chunks.last_mut().unwrap();
+        chunk.push(value);
+
+        // Chunks never grow past their capacity, so objects never move, and
+        // the chunk is handed over to the arena rather than dropped.
+        unsafe { &*chunk.as_ptr().add(chunk.len() - 1) }
+    }
+}
+
+impl<'a, T> Drop for Shard<'a, T> {
+    fn drop(&mut self) {
+        let chunks = std::mem::take(&mut self.chunks);
+        self.arena.shards.lock().unwrap_or_else(PoisonError::into_inner).push(chunks);
+    }
+}
+
+#[test]
+fn shards() {
+    let arena = ShardedArena::with_capacity(1);
+    let firsts: Vec<&String> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..8).map(|thread| {
+            let mut shard = arena.shard();
+            scope.spawn(move || {
+                let first = shard.alloc(format!("{}-0", thread));
+                for i in 1..1000 {
+                    shard.alloc(format!("{}-{}", thread, i));
+                }
+                first
+            })
+        }).collect();
+
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    });
+
+    assert_eq!(arena.len(), 8000);
+    assert_eq!(*firsts[3], "3-0");
+    // Each shard's objects are together, in the order they were stored.
+    let position = arena.iter().position(|s| std::ptr::eq(s, firsts[5])).unwrap();
+    let run: Vec<_> = arena.iter().skip(position).take(3).cloned().collect();
+    assert_eq!(run, ["5-0", "5-1", "5-2"]);
+
+    let mut shard = arena.shard();
+    let late = shard.alloc(String::from("late"));
+    assert_eq!(arena.len(), 8000);
+    drop(shard);
+    assert_eq!((arena.len(), arena.iter().last()), (8001, Some(late)));
+
+    #[cfg(feature = "rayon")]
+    assert_eq!(arena.par_iter().filter(|s| s.ends_with("-999")).count(), 8);
+}
@@ -0,0 +1,862 @@
+//! Runs of objects stored next to each other in an arena, behind a single
+//! handle, and a writer which streams bytes into one.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+
+use {Arena, ArenaError, Chunks, Rc, Slot};
+#[cfg(feature = "bytes")]
+use Items;
+#[cfg(feature = "poison")]
+use poison;
+use pool;
+#[cfg(feature = "futures")]
+use stream;
+
+/// A run of objects stored next to each other in a chunk of a direct arena,
+/// whose slots are next to each other too. Each run holds a strong count on
+/// every object in it, which it takes and gives back all at once.
+struct Run<T> {
+    chunks: std::rc::Rc<RefCell<Chunks<T>>>,
+    ptr: *mut T,
+    slots: *const Slot,
+    len: usize
+}
+
+impl<T> Run<T> {
+    /// Refer to the `len` objects from position `start` in `chunk`.
+    fn new(chunks: &std::rc::Rc<RefCell<Chunks<T>>>, chunk: usize, start: usize, len: usize) -> Run<T> {
+        let (ptr, slots) = chunks.borrow().list[chunk].handle(start);
+        let run = Run {
+            chunks: chunks.clone(),
+            ptr,
+            slots,
+            len
+        };
+        run.hold();
+
+        run
+    }
+
+    fn hold(&self) {
+        for slot in self.slots() {
+            slot.strong.set(slot.strong.get() + 1);
+        }
+
+        let runs = &self.chunks.borrow().runs;
+        runs.set(runs.get() + self.len - 1);
+    }
+
+    fn slots(&self) -> &[Slot] {
+        // Slots live alongside the objects, and the chunk's slots never
+        // reallocate, so this is okay for the same reason as `objects`.
+        unsafe { std::slice::from_raw_parts(self.slots, self.len) }
+    }
+
+    fn objects(&self) -> &[T] {
+        #[cfg(feature = "poison")]
+        poison::check(&self.slots()[0]);
+
+        // This is okay because the objects were stored next to each other in
+        // a chunk, which the run keeps alive and referenced.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> Clone for Run<T> {
+    fn clone(&self) -> Run<T> {
+        let run = Run {
+            chunks: self.chunks.clone(),
+            ptr: self.ptr,
+            slots: self.slots,
+            len: self.len
+        };
+        run.hold();
+
+        run
+    }
+}
+
+impl<T> Drop for Run<T> {
+    fn drop(&mut self) {
+        {
+            let runs = &self.chunks.borrow().runs;
+            runs.set(runs.get() - (self.len - 1));
+        }
+
+        // As for `Rc`, one object at a time.
+        for (index, slot) in self.slots().iter().enumerate() {
+            slot.strong.set(slot.strong.get() - 1);
+
+            if !slot.is_referenced() {
+                pool::release(&self.chunks, unsafe { self.ptr.add(index) }, slot);
+            }
+        }
+    }
+}
+
+/// A reference counted handle to a run of objects stored next to each other
+/// in an arena, created by `Arena::alloc_slice` or `ArenaWriter::finish`.
+/// It keeps the arena alive like an `Rc` does, without allocating anything
+/// to keep track of the objects.
+pub struct RcSlice<T> {
+    // Empty slices don't refer to an arena at all.
+    run: Option<Run<T>>
+}
+
+impl<T> RcSlice<T> {
+    /// Returns `true` if both handles point to the same run of objects.
+    pub fn ptr_eq(this: &RcSlice<T>, other: &RcSlice<T>) -> bool {
+        // Objects may be zero-sized, but their slots aren't.
+        match (&this.run, &other.run) {
+            (Some(this), Some(other)) => this.slots == other.slots && this.len == other.len,
+            (None, None) => true,
+            _ => false
+        }
+    }
+
+    /// The arena the objects live in, unless there are none.
+    pub fn arena(this: &RcSlice<T>) -> Option<Arena<T>> {
+        this.run.as_ref().map(|run| Arena::from_shared(run.chunks.clone()))
+    }
+}
+
+impl<T> Clone for RcSlice<T> {
+    fn clone(&self) -> RcSlice<T> {
+        RcSlice {
+            run: self.run.clone()
+        }
+    }
+}
+
+impl<T> Deref for RcSlice<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self.run {
+            Some(ref run) => run.objects(),
+            None => &[]
+        }
+    }
+}
+
+impl<T> AsRef<[T]> for RcSlice<T> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T> std::fmt::Debug for RcSlice<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+/// A reference counted handle to an array of objects stored next to each
+/// other in an arena, created by `Arena::alloc_array`. It keeps the arena
+/// alive like an `Rc` does.
+pub struct RcArray<T, const N: usize> {
+    // As in `RcSlice`, with `N` objects in the run.
+    run: Option<Run<T>>
+}
+
+impl<T, const N: usize> RcArray<T, N> {
+    /// Returns `true` if both handles point to the same array.
+    pub fn ptr_eq(this: &RcArray<T, N>, other: &RcArray<T, N>) -> bool {
+        match (&this.run, &other.run) {
+            (Some(this), Some(other)) => this.slots == other.slots,
+            _ => N == 0
+        }
+    }
+
+    /// The arena the objects live in, unless there are none.
+    pub fn arena(this: &RcArray<T, N>) -> Option<Arena<T>> {
+        this.run.as_ref().map(|run| Arena::from_shared(run.chunks.clone()))
+    }
+}
+
+impl<T, const N: usize> Clone for RcArray<T, N> {
+    fn clone(&self) -> RcArray<T, N> {
+        RcArray {
+            run: self.run.clone()
+        }
+    }
+}
+
+impl<T, const N: usize> Deref for RcArray<T, N> {
+    type Target = [T; N];
+
+    fn deref(&self) -> &[T; N] {
+        let ptr = match self.run {
+            Some(ref run) => run.objects().as_ptr(),
+            None => std::ptr::NonNull::dangling().as_ptr()
+        };
+
+        // The run holds `N` objects.
+        unsafe { &*(ptr as *const [T; N]) }
+    }
+}
+
+impl<T, const N: usize> AsRef<[T]> for RcArray<T, N> {
+    fn as_ref(&self) -> &[T] {
+        &**self
+    }
+}
+
+impl<T, const N: usize> std::fmt::Debug for RcArray<T, N> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+impl<T: Copy> Arena<T> {
+    /// Copy `values` into the arena, next to each other, returning a single
+    /// handle to all of them.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(2);
+    /// arena.alloc(0);
+    /// let digits = arena.alloc_slice(&[1, 2, 3, 4]);
+    ///
+    /// assert_eq!(&*digits, [1, 2, 3, 4]);
+    /// assert_eq!(arena.len(), 5);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is indirect, since `sort_by` could move the
+    /// objects apart, if the arena has been sealed, or if an arena created
+    /// with `with_limit` can't fit all of the objects.
+    pub fn alloc_slice(&self, values: &[T]) -> RcSlice<T> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("alloc_slice", count = values.len()).entered();
+
+        if values.is_empty() {
+            return RcSlice {
+                run: None
+            };
+        }
+
+        self.assert_direct();
+        let (chunk, start) = self.alloc_copies(values);
+
+        RcSlice {
+            run: Some(Run::new(&self.chunks, chunk, start, values.len()))
+        }
+    }
+
+    /// Copy `values` into the arena, next to each other, returning a handle
+    /// to each of them. Room is made for all of them at once and they're
+    /// copied in with a single `memcpy`, which is much faster than storing
+    /// them one at a time with `alloc`. The alloc hook isn't run for them.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::with_capacity(1);
+    /// let samples: Vec<f32> = (0..1000).map(|i| i as f32).collect();
+    /// let handles = arena.alloc_copy_slice(&samples);
+    ///
+    /// assert_eq!(*handles[999], 999.0);
+    /// assert_eq!(arena.chunks().last().unwrap().len(), 1000);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `alloc_slice`, except that the
+    /// arena may be indirect.
+    pub fn alloc_copy_slice(&self, values: &[T]) -> Vec<Rc<T>> {
+        if values.is_empty() {
+            return vec![];
+        }
+
+        let (chunk, start) = self.alloc_copies(values);
+        let chunks = self.chunks.borrow();
+
+        (start..start + values.len()).map(|index| {
+            let (ptr, slot) = chunks.list[chunk].handle(index);
+            Rc::new_handle(self.chunks.clone(), ptr, slot)
+        }).collect()
+    }
+
+    fn alloc_copies(&self, values: &[T]) -> (usize, usize) {
+        // Copying the objects in can't panic part of the way through.
+        self.alloc_run(values.len(), |memory| unsafe {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), memory, values.len())
+        })
+    }
+}
+
+impl<T> Arena<T> {
+    /// Store an array of objects in the arena, next to each other, returning
+    /// a single handle to all of them. Room is made for all of them at once,
+    /// and the alloc hook isn't run for them.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let children = arena.alloc_array([String::from("nw"), "ne".into(), "sw".into(), "se".into()]);
+    ///
+    /// assert_eq!(children[3], "se");
+    /// assert_eq!(arena.len(), 4);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `alloc_slice`.
+    pub fn alloc_array<const N: usize>(&self, values: [T; N]) -> RcArray<T, N> {
+        if N == 0 {
+            return RcArray {
+                run: None
+            };
+        }
+
+        self.assert_direct();
+        // Moving the array in can't panic part of the way through.
+        let (chunk, start) = self.alloc_run(N, |memory| unsafe { std::ptr::write(memory as *mut [T; N], values) });
+
+        RcArray {
+            run: Some(Run::new(&self.chunks, chunk, start, N))
+        }
+    }
+
+    /// Store an array of objects in the arena, constructing each from its
+    /// position, like `alloc_array`. The objects are all constructed before
+    /// any of them are stored, so `f` may allocate into the arena too.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let squares = arena.alloc_array_with(|i: usize| i * i);
+    ///
+    /// assert_eq!(*squares, [0, 1, 4, 9]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `alloc_slice`.
+    pub fn alloc_array_with<const N: usize, F: FnMut(usize) -> T>(&self, f: F) -> RcArray<T, N> {
+        self.alloc_array(std::array::from_fn(f))
+    }
+
+    /// Make room for `n` objects next to each other, have `write` move them
+    /// into the memory, and return the chunk they were stored in and the
+    /// position of the first. Nothing refers to them yet.
+    fn alloc_run<F: FnOnce(*mut T)>(&self, n: usize, write: F) -> (usize, usize) {
+        if let Err(error) = self.try_reserve(n) {
+            panic!("{}", error);
+        }
+
+        let mut chunks = self.chunks.borrow_mut();
+        let chunk = chunks.list.len() - 1;
+        let items = &mut chunks.list[chunk].items;
+        let start = items.len();
+        // There's room for the objects after the last one, and `write`
+        // initializes all of them.
+        unsafe {
+            write(items.as_mut_ptr().add(start));
+            items.set_len(start + n);
+        }
+        for _ in 0..n {
+            chunks.push_slot(chunk, 0);
+        }
+        #[cfg(feature = "futures")]
+        stream::notify(&mut chunks);
+
+        (chunk, start)
+    }
+
+    /// Check that the slots of a run will be next to each other.
+    fn assert_direct(&self) {
+        assert!(self.chunks.borrow().table.is_none(), "runs of objects can't be stored in an indirect arena");
+    }
+}
+
+impl Arena<u8> {
+    /// A writer which stores the bytes written to it in the arena, next to
+    /// each other, handing them out once it's finished. The bytes are written
+    /// straight into a chunk, which is closed to other allocations until the
+    /// writer is finished or dropped, as for `alloc_fmt`.
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let mut writer = arena.writer();
+    /// write!(writer, "{} + {} = {}", 1, 2, 1 + 2).unwrap();
+    /// let bytes = writer.finish();
+    ///
+    /// assert_eq!(&*bytes, b"1 + 2 = 3");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is indirect.
+    pub fn writer(&self) -> ArenaWriter<'_> {
+        self.assert_direct();
+
+        ArenaWriter {
+            bytes: BytesWriter::new(self)
+        }
+    }
+}
+
+impl Arena<u8> {
+    /// Copy a string into the arena, returning a single handle to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `alloc_slice`.
+    pub fn alloc_str(&self, string: &str) -> RcStr {
+        RcStr {
+            bytes: self.alloc_slice(string.as_bytes())
+        }
+    }
+
+    /// Format a string directly into the memory of a chunk, returning a
+    /// single handle to it, without building a `String` first. This is
+    /// usually called through `alloc_format!`.
+    ///
+    /// The chunk is closed to other allocations until the string is done,
+    /// so formatting may allocate in the arena too. If the string outgrows
+    /// the room left in the chunk, it's moved to a new one with more room,
+    /// so a few bytes may be wasted.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// let message = arena.alloc_fmt(format_args!("{} errors in {}", 3, "lib.rs"));
+    /// assert_eq!(&*message, "3 errors in lib.rs");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics like `format!` does if a formatting trait returns an error,
+    /// and under the same conditions as `alloc_slice`.
+    pub fn alloc_fmt(&self, args: fmt::Arguments) -> RcStr {
+        if let Some(string) = args.as_str() {
+            return self.alloc_str(string);
+        }
+
+        self.assert_direct();
+        let mut writer = BytesWriter::new(self);
+        if fmt::write(&mut writer, args).is_err() {
+            match writer.error {
+                Some(error) => panic!("{}", error),
+                None => panic!("a formatting trait implementation returned an error")
+            }
+        }
+
+        RcStr {
+            bytes: writer.finish()
+        }
+    }
+}
+
+/// Store a string formatted like `format!` does in an arena of bytes,
+/// returning an `RcStr`. See `Arena::alloc_fmt`.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rc_arena;
+///
+/// use rc_arena::Arena;
+///
+/// fn main() {
+///     let arena = Arena::new();
+///     let line = 12;
+///     let warning = alloc_format!(arena, "unused variable on line {}", line);
+///
+///     assert_eq!(&*warning, "unused variable on line 12");
+/// }
+/// ```
+#[macro_export]
+macro_rules! alloc_format {
+    ($arena:expr, $($arg:tt)*) => {
+        $crate::Arena::alloc_fmt(&$arena, format_args!($($arg)*))
+    }
+}
+
+/// Writes bytes into the room after the last object of a chunk, which is
+/// closed to other allocations until the bytes are stored.
+struct BytesWriter<'a> {
+    arena: &'a Arena<u8>,
+    chunk: Option<usize>,
+    room: usize,
+    len: usize,
+    error: Option<ArenaError>
+}
+
+impl<'a> BytesWriter<'a> {
+    fn new(arena: &'a Arena<u8>) -> BytesWriter<'a> {
+        BytesWriter {
+            arena,
+            chunk: None,
+            room: 0,
+            len: 0,
+            error: None
+        }
+    }
+
+    /// Write `bytes` after those which have been written so far, moving them
+    /// all to a new chunk if they don't fit.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ArenaError> {
+        let needed = self.len.checked_add(bytes.len()).ok_or(ArenaError::CapacityOverflow)?;
+        if needed > self.room {
+            self.grow(needed)?;
+        }
+
+        let mut chunks = self.arena.chunks.borrow_mut();
+        let items = &mut chunks.list[self.chunk.unwrap()].items;
+        let start = items.len();
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), items.as_mut_ptr().add(start + self.len), bytes.len()) };
+        self.len = needed;
+
+        Ok(())
+    }
+
+    /// Move what has been written so far to a chunk with room for `needed`
+    /// bytes.
+    fn grow(&mut self, needed: usize) -> Result<(), ArenaError> {
+        // A string which doesn't fit in the room it was given probably has
+        // further to go.
+        let additional = if self.chunk.is_some() { std::cmp::max(needed, 2 * self.len) } else { needed };
+        self.arena.try_reserve(additional)?;
+
+        let mut chunks = self.arena.chunks.borrow_mut();
+        let chunk = chunks.list.len() - 1;
+        let start = chunks.list[chunk].items.len();
+        let to = unsafe { chunks.list[chunk].items.as_mut_ptr().add(start) };
+        if let Some(old) = self.chunk {
+            let old_chunk = &mut chunks.list[old];
+            let from = unsafe { old_chunk.items.as_mut_ptr().add(old_chunk.items.len()) };
+            unsafe { std::ptr::copy_nonoverlapping(from, to, self.len) };
+            old_chunk.reserved = false;
+        }
+
+        chunks.list[chunk].reserved = true;
+        self.room = chunks.list[chunk].capacity() - start;
+        self.chunk = Some(chunk);
+
+        Ok(())
+    }
+
+    /// Store the bytes which have been written.
+    fn finish(mut self) -> RcSlice<u8> {
+        let chunk = match self.chunk.take() {
+            Some(chunk) => chunk,
+            None => return RcSlice {
+                run: None
+            }
+        };
+
+        let mut chunks = self.arena.chunks.borrow_mut();
+        chunks.list[chunk].reserved = false;
+        let start = chunks.list[chunk].items.len();
+        // The bytes were written in the room after the last object.
+        unsafe { chunks.list[chunk].items.set_len(start + self.len) };
+        for _ in 0..self.len {
+            chunks.push_slot(chunk, 0);
+        }
+        #[cfg(feature = "futures")]
+        stream::notify(&mut chunks);
+        drop(chunks);
+
+        RcSlice {
+            run: Some(Run::new(&self.arena.chunks, chunk, start, self.len))
+        }
+    }
+}
+
+impl<'a> fmt::Write for BytesWriter<'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.write(string.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
+    }
+}
+
+// If formatting panics, or a writer isn't finished, the chunk is opened again
+// and the bytes are left behind.
+impl<'a> Drop for BytesWriter<'a> {
+    fn drop(&mut self) {
+        if let Some(chunk) = self.chunk {
+            self.arena.chunks.borrow_mut().list[chunk].reserved = false;
+        }
+    }
+}
+
+/// A reference counted handle to a string stored in an arena of bytes,
+/// created by `Arena::alloc_str` or `Arena::alloc_fmt`.
+#[derive(Clone)]
+pub struct RcStr {
+    bytes: RcSlice<u8>
+}
+
+impl RcStr {
+    /// The bytes of the string.
+    pub fn as_bytes(this: &RcStr) -> &RcSlice<u8> {
+        &this.bytes
+    }
+}
+
+impl Deref for RcStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        // The bytes were copied or formatted from strings.
+        unsafe { std::str::from_utf8_unchecked(&self.bytes) }
+    }
+}
+
+impl AsRef<str> for RcStr {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl fmt::Debug for RcStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+impl fmt::Display for RcStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.deref().fmt(f)
+    }
+}
+
+/// A writer which stores the bytes written to it in an arena, created by
+/// `Arena::writer`.
+pub struct ArenaWriter<'a> {
+    bytes: BytesWriter<'a>
+}
+
+impl<'a> ArenaWriter<'a> {
+    /// Hand out the bytes which have been written, opening their chunk to
+    /// other allocations again.
+    pub fn finish(self) -> RcSlice<u8> {
+        self.bytes.finish()
+    }
+}
+
+impl<'a> io::Write for ArenaWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An arena which can't make room is out of memory as far as the
+        // writer is concerned.
+        self.bytes.write(buf).map_err(|error| io::Error::new(io::ErrorKind::OutOfMemory, error))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `Bytes` can be sent to other threads, which an arena can't, so only the
+/// bytes of an arena which has been frozen with `Arena::freeze` can become
+/// `Bytes`. They're shared with the frozen chunk rather than copied, and
+/// the slice is given back if its bytes aren't frozen.
+///
+/// ```rust
+/// # extern crate bytes;
+/// # extern crate rc_arena;
+/// use std::convert::TryFrom;
+/// use bytes::Bytes;
+/// use rc_arena::Arena;
+///
+/// # fn main() {
+/// let arena = Arena::new();
+/// let body = arena.alloc_slice(b"hello");
+/// let body = Bytes::try_from(body).unwrap_err();
+///
+/// let _frozen = arena.freeze();
+/// let bytes = Bytes::try_from(body.clone()).unwrap();
+/// assert_eq!((&*bytes, bytes.as_ptr()), (&b"hello"[..], body.as_ptr()));
+/// # }
+/// ```
+#[cfg(feature = "bytes")]
+impl std::convert::TryFrom<RcSlice<u8>> for bytes::Bytes {
+    type Error = RcSlice<u8>;
+
+    fn try_from(slice: RcSlice<u8>) -> Result<bytes::Bytes, RcSlice<u8>> {
+        let frozen = match slice.run {
+            Some(ref run) => run.chunks.borrow().list.iter().find_map(|chunk| match chunk.items {
+                Items::Frozen(ref items) => chunk.index_of(run.ptr, run.slots).map(|start| (items.clone(), start)),
+                _ => None
+            }),
+            None => return Ok(bytes::Bytes::new())
+        };
+
+        match frozen {
+            Some((items, start)) => Ok(bytes::Bytes::from_owner(FrozenBytes(items)).slice(start..start + slice.len())),
+            None => Err(slice)
+        }
+    }
+}
+
+/// The objects of a frozen chunk of bytes, which are never written to.
+#[cfg(feature = "bytes")]
+struct FrozenBytes(std::sync::Arc<Vec<u8>>);
+
+#[cfg(feature = "bytes")]
+impl AsRef<[u8]> for FrozenBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[test]
+fn stores_slices() {
+    use std::io::Write;
+
+    let arena = Arena::with_capacity(4);
+    let digits = arena.alloc_slice(&[1u8, 2, 3]);
+    let mut writer = arena.writer();
+    writer.write_all(b"hello").unwrap();
+    let hello = writer.finish();
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [3, 5]);
+    assert_eq!(&*hello, b"hello");
+
+    // The handle keeps the arena alive, and the objects stay where they were.
+    let copy = hello.clone();
+    drop(arena);
+    assert!(RcSlice::ptr_eq(&hello, &copy));
+    assert!(!RcSlice::ptr_eq(&hello, &digits));
+    assert_eq!(RcSlice::arena(&digits).unwrap().len(), 8);
+
+    let arena = Arena::indirect_with_capacity(2);
+    arena.alloc(0u32);
+    let copied = arena.alloc_copy_slice(&[1, 2, 3]);
+    assert_eq!(copied.iter().map(|i| **i).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(arena.chunks().map(|chunk| chunk.len()).collect::<Vec<_>>(), [1, 3]);
+    arena.assert_invariants();
+
+    // The run stays referenced until its last handle is dropped.
+    let arena = Arena::new();
+    let checkpoint = arena.checkpoint();
+    let run = arena.alloc_slice(&[4u8, 5]);
+    let again = run.clone();
+    drop(run);
+    arena.assert_invariants();
+    assert!(arena.rollback(checkpoint).is_err());
+    drop(again);
+    assert!(arena.rollback(checkpoint).is_ok());
+
+    let indirect = Arena::indirect();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| indirect.alloc_slice(&[1u8])));
+    assert!(result.is_err() && indirect.is_empty());
+
+    let empty = Arena::<u8>::new().writer().finish();
+    assert!(empty.is_empty() && RcSlice::arena(&empty).is_none());
+
+    #[cfg(feature = "bytes")]
+    {
+        use std::convert::TryFrom;
+
+        let copy = bytes::Bytes::try_from(copy).unwrap_err();
+        let _frozen = RcSlice::arena(&copy).unwrap().freeze();
+        assert_eq!(bytes::Bytes::try_from(copy).unwrap().as_ptr(), hello.as_ptr());
+        assert!(bytes::Bytes::try_from(empty).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn formats_strings() {
+    use std::fmt::Display;
+    use std::io::Write;
+
+    // Allocates in the arena it's being formatted into.
+    struct Nested<'a>(&'a Arena<u8>);
+
+    impl<'a> Display for Nested<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            let inner = alloc_format!(self.0, "{}", 42);
+            write!(f, "<{}>", inner)
+        }
+    }
+
+    let arena = Arena::with_capacity(8);
+    arena.alloc(0);
+    let short = alloc_format!(arena, "{}-{}", 1, 2);
+    assert_eq!(&*short, "1-2");
+    assert_eq!(arena.chunks().count(), 1);
+
+    // Outgrowing the chunk moves the string to a new one.
+    let long = alloc_format!(arena, "{}{}", "x".repeat(10), "y".repeat(20));
+    assert_eq!(&*long, "x".repeat(10) + &"y".repeat(20));
+    assert_eq!(arena.len(), 4 + 30);
+
+    let nested = alloc_format!(arena, "{} {}", "a", Nested(&arena));
+    assert_eq!(format!("{:?}", nested), "\"a <42>\"");
+    assert_eq!(&*arena.alloc_str("plain"), "plain");
+    assert_eq!(&*alloc_format!(arena, "{}", ""), "");
+    arena.assert_invariants();
+
+    // A panic leaves the arena as it was.
+    struct Panics;
+
+    impl Display for Panics {
+        fn fmt(&self, _: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            panic!("formatting failed")
+        }
+    }
+
+    let len = arena.len();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| alloc_format!(arena, "ab{}", Panics)));
+    assert!(result.is_err());
+    assert_eq!(arena.len(), len);
+    assert_eq!(&*alloc_format!(arena, "{}", 7), "7");
+
+    // Writers store their bytes the same way, and one which isn't finished
+    // leaves nothing behind.
+    let arena = Arena::with_capacity(4);
+    let mut writer = arena.writer();
+    writer.write_all(b"abc").unwrap();
+    let other = arena.alloc(b'!');
+    writer.write_all(b"defgh").unwrap();
+    assert_eq!(&*writer.finish(), b"abcdefgh");
+    let mut unfinished = arena.writer();
+    unfinished.write_all(b"xyz").unwrap();
+    drop(unfinished);
+    assert_eq!((*other, arena.len()), (b'!', 9));
+    arena.assert_invariants();
+}
+
+#[test]
+fn stores_arrays() {
+    let token = std::rc::Rc::new(());
+    let arena = Arena::with_capacity(3);
+    arena.alloc(token.clone());
+    let array = arena.alloc_array([token.clone(), token.clone(), token.clone()]);
+    let again = array.clone();
+    assert!(std::ptr::eq(&array[0], &*arena.iter().nth(1).unwrap()));
+    assert_eq!((arena.chunks().count(), std::rc::Rc::strong_count(&token)), (2, 5));
+    assert!(RcArray::ptr_eq(&array, &again));
+    arena.assert_invariants();
+
+    // Once the last handle is gone, the objects can be removed.
+    drop(array);
+    arena.truncate(1).unwrap_err();
+    drop(again);
+    arena.truncate(1).unwrap();
+    assert_eq!(std::rc::Rc::strong_count(&token), 2);
+
+    // The objects are constructed first, so they can allocate as well.
+    let arena = Arena::new();
+    let nested = arena.alloc_array_with(|i| *arena.alloc(i) + 10);
+    assert_eq!((*nested, arena.len()), ([10, 11, 12], 6));
+    let empty: RcArray<usize, 0> = arena.alloc_array([]);
+    assert!(empty.is_empty() && RcArray::arena(&empty).is_none());
+}
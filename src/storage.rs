@@ -0,0 +1,133 @@
+//! Custom sources of raw memory for the chunks of an arena, such as
+//! page-aligned slabs. The arena's own heap, allocator and mmap chunks are
+//! separate from these, since each needs more than raw memory.
+
+use std::alloc::Layout;
+use std::ptr::NonNull;
+
+use {Arena, Chunks};
+
+/// Where the chunks of an arena created with `Arena::with_storage` get their
+/// memory from. Each chunk asks for room for a number of objects once, and
+/// gives the memory back when it's dropped, after dropping its objects.
+/// Arenas created any other way don't go through a `Storage`.
+///
+/// # Safety
+///
+/// The memory returned by `allocate` must be valid for reads and writes of
+/// `capacity` objects, properly aligned for `T`, and not used by anything
+/// else until it's given back to `deallocate`.
+pub unsafe trait Storage<T> {
+    /// Get the memory for `capacity` objects, or `None` if there isn't any.
+    fn allocate(&self, capacity: usize) -> Option<NonNull<T>>;
+
+    /// Give back memory returned by `allocate` for `capacity` objects.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` with the same `capacity`,
+    /// and must not be used afterwards.
+    unsafe fn deallocate(&self, ptr: NonNull<T>, capacity: usize);
+}
+
+/// Storage which allocates each chunk from the global allocator in whole
+/// pages of 4096 bytes, starting on a page boundary.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PageStorage;
+
+impl PageStorage {
+    const PAGE_SIZE: usize = 4096;
+
+    fn layout<T>(capacity: usize) -> Option<Layout> {
+        let size = capacity.checked_mul(std::mem::size_of::<T>())?;
+        let size = size.checked_add(Self::PAGE_SIZE - 1)? / Self::PAGE_SIZE * Self::PAGE_SIZE;
+
+        Layout::from_size_align(size, std::cmp::max(std::mem::align_of::<T>(), Self::PAGE_SIZE)).ok()
+    }
+}
+
+unsafe impl<T> Storage<T> for PageStorage {
+    fn allocate(&self, capacity: usize) -> Option<NonNull<T>> {
+        let layout = PageStorage::layout::<T>(capacity)?;
+        if layout.size() == 0 {
+            return Some(NonNull::dangling());
+        }
+
+        NonNull::new(unsafe { std::alloc::alloc(layout) } as *mut T)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<T>, capacity: usize) {
+        // This layout was accepted when the memory was allocated.
+        let layout = PageStorage::layout::<T>(capacity).unwrap();
+        if layout.size() != 0 {
+            std::alloc::dealloc(ptr.as_ptr() as *mut u8, layout);
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a new arena with a known initial capacity, whose chunks get
+    /// their memory from `storage`. The arena's own bookkeeping is still
+    /// allocated on the heap.
+    ///
+    /// ```rust
+    /// use rc_arena::{Arena, PageStorage};
+    ///
+    /// let arena = Arena::with_storage(16, PageStorage);
+    /// let first = arena.alloc(1u64);
+    /// assert_eq!(&*first as *const u64 as usize % 4096, 0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage` can't provide the first chunk. Allocating panics
+    /// when it can't provide a new chunk, like it does when the heap is out
+    /// of memory.
+    pub fn with_storage<S: Storage<T> + 'static>(n: usize, storage: S) -> Arena<T> {
+        let storage: std::rc::Rc<dyn Storage<T>> = std::rc::Rc::new(storage);
+        let mut chunks = Chunks::new(vec![]);
+        chunks.storage = Some(storage);
+        let first = chunks.new_chunk(std::cmp::max(n, 1));
+        chunks.list.push(first);
+
+        Arena::from_chunks(chunks)
+    }
+}
+
+/// Counts the chunks which are currently allocated.
+#[cfg(test)]
+pub struct Counting(pub std::rc::Rc<std::cell::Cell<isize>>);
+
+#[cfg(test)]
+unsafe impl<T> Storage<T> for Counting {
+    fn allocate(&self, capacity: usize) -> Option<NonNull<T>> {
+        self.0.set(self.0.get() + 1);
+        Storage::<T>::allocate(&PageStorage, capacity)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<T>, capacity: usize) {
+        self.0.set(self.0.get() - 1);
+        Storage::<T>::deallocate(&PageStorage, ptr, capacity)
+    }
+}
+
+#[test]
+fn uses_storage() {
+    use std::cell::Cell;
+
+    let chunks = std::rc::Rc::new(Cell::new(0));
+    let token = std::rc::Rc::new(());
+    let arena = Arena::with_storage(2, Counting(chunks.clone()));
+    let first = arena.alloc(token.clone());
+    let checkpoint = arena.checkpoint();
+    for _ in 0..10 {
+        arena.alloc(token.clone());
+    }
+    assert_eq!(chunks.get(), 3);
+    assert_eq!(&*first as *const _ as usize % PageStorage::PAGE_SIZE, 0);
+
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!((chunks.get(), std::rc::Rc::strong_count(&token)), (1, 2));
+    drop((arena, first));
+    assert_eq!((chunks.get(), std::rc::Rc::strong_count(&token)), (0, 1));
+}
@@ -0,0 +1,130 @@
+//! Streams of the objects allocated in an arena, for async tasks which
+//! consume work as it's produced.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use {Arena, Chunks, Rc};
+
+/// A stream of handles to the objects allocated in an arena after it was
+/// created by `Arena::subscribe`, in allocation order. It ends once the
+/// arena has been sealed and every object before that has been yielded.
+///
+/// The stream goes by position, like `Arena::iter_since`: objects which a
+/// pool recycles in place aren't yielded again, and after a rollback, the
+/// objects allocated in the place of those removed are yielded as new.
+pub struct Subscription<T> {
+    arena: Arena<T>,
+    next: usize
+}
+
+/// Wake the tasks waiting for something to be allocated in the arena. They
+/// only run once the arena is no longer borrowed.
+pub fn notify<T>(chunks: &mut Chunks<T>) {
+    for waker in chunks.wakers.drain(..) {
+        waker.wake();
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = Rc<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Rc<T>>> {
+        let this = self.get_mut();
+        if let Some((ptr, slot)) = this.arena.locate(this.next) {
+            this.next += 1;
+            return Poll::Ready(Some(Rc::new_handle(this.arena.chunks.clone(), ptr, slot)));
+        }
+
+        let mut chunks = this.arena.chunks.borrow_mut();
+        if chunks.sealed {
+            return Poll::Ready(None);
+        }
+        if !chunks.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            chunks.wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> Arena<T> {
+    /// Get a stream of handles to the objects allocated from now on, which
+    /// wakes the task polling it whenever there's a new one.
+    ///
+    /// ```rust
+    /// # extern crate futures_core;
+    /// # extern crate rc_arena;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// use futures_core::Stream;
+    /// use rc_arena::Arena;
+    ///
+    /// # fn main() {
+    /// let arena = Arena::new();
+    /// arena.alloc("before");
+    /// let mut jobs = arena.subscribe();
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert!(Pin::new(&mut jobs).poll_next(&mut cx).is_pending());
+    ///
+    /// arena.alloc("after");
+    /// match Pin::new(&mut jobs).poll_next(&mut cx) {
+    ///     Poll::Ready(Some(job)) => assert_eq!(*job, "after"),
+    ///     _ => unreachable!()
+    /// }
+    ///
+    /// arena.seal();
+    /// assert!(matches!(Pin::new(&mut jobs).poll_next(&mut cx), Poll::Ready(None)));
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> Subscription<T> {
+        Subscription {
+            arena: Arena::from_shared(self.chunks.clone()),
+            next: self.len()
+        }
+    }
+}
+
+#[test]
+fn streams_new_objects() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    // Counts how often the task is woken.
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let arena = Arena::with_capacity(1);
+    let mut stream = arena.subscribe();
+    let mut poll = || match Pin::new(&mut stream).poll_next(&mut cx) {
+        Poll::Ready(item) => Some(item.map(|rc| *rc)),
+        Poll::Pending => None
+    };
+    assert_eq!(poll(), None);
+    assert_eq!(poll(), None);
+
+    arena.alloc(1);
+    arena.alloc_slice(&[2, 3]);
+    unsafe { arena.emplace(|slot| { slot.write(4); }) };
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    assert_eq!((poll(), poll(), poll(), poll()), (Some(Some(1)), Some(Some(2)), Some(Some(3)), Some(Some(4))));
+
+    assert_eq!(poll(), None);
+    arena.alloc_cyclic(|_| 5);
+    arena.seal();
+    assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+    assert_eq!((poll(), poll()), (Some(Some(5)), Some(None)));
+}
@@ -0,0 +1,341 @@
+//! Indirect arenas, whose handles find their objects through a table of
+//! stable entries rather than pointing at them directly, so that the objects
+//! themselves can be moved by `Arena::compact` and `Arena::sort_by`.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use {Arena, Chunk, Chunks, Slot, Slots};
+
+/// Where an object of an indirect arena currently lives, along with its
+/// counts. Handles point at the entry's slot, which comes first so that the
+/// entry can be found again from it.
+#[repr(C)]
+pub struct Entry<T> {
+    pub slot: Slot,
+    pub ptr: Cell<*mut T>
+}
+
+/// The entries of an indirect arena. Like chunks, blocks of entries are never
+/// grown past their initial capacity, so entries never move.
+pub struct Table<T> {
+    blocks: Vec<Vec<Entry<T>>>,
+    free: Vec<*const Entry<T>>
+}
+
+impl<T> Table<T> {
+    pub fn new() -> Table<T> {
+        Table {
+            blocks: vec![],
+            free: vec![]
+        }
+    }
+
+    /// Create an entry for an object which hasn't been stored yet.
+    pub fn insert(&mut self, strong: usize) -> *const Entry<T> {
+        if let Some(entry) = self.free.pop() {
+            unsafe { (*entry).slot.strong.set(strong) };
+            return entry;
+        }
+
+        let full = self.blocks.last().is_none_or(|block| block.len() == block.capacity());
+        if full {
+            let capacity = self.blocks.last().map_or(8, |block| block.capacity() * 2);
+            self.blocks.push(Vec::with_capacity(capacity));
+        }
+
+        let block = self.blocks.last_mut().unwrap();
+        block.push(Entry {
+            slot: Slot {
+                strong: Cell::new(strong),
+                weak: Cell::new(0)
+            },
+            ptr: Cell::new(std::ptr::null_mut())
+        });
+
+        block.last().unwrap()
+    }
+
+    /// Reuse an entry which nothing refers to anymore.
+    pub fn remove(&mut self, entry: *const Entry<T>) {
+        unsafe { (*entry).ptr.set(std::ptr::null_mut()) };
+        self.free.push(entry);
+    }
+}
+
+impl<T> Table<T> {
+    /// Check that the entries of the chunks in `list` point at their objects,
+    /// and that the free entries are neither referenced nor used by a chunk.
+    pub fn assert_invariants(&self, list: &[Chunk<T>]) {
+        let free: HashSet<_> = self.free.iter().cloned().collect();
+        assert!(free.len() == self.free.len(), "an entry is on the free list twice");
+
+        for &entry in &self.free {
+            let entry = unsafe { &*entry };
+            assert!(!entry.slot.is_referenced() && entry.ptr.get().is_null(), "a free entry is still in use");
+        }
+
+        for (index, chunk) in list.iter().enumerate() {
+            if let Slots::Table(ref entries) = chunk.slots {
+                for (position, &entry) in entries.iter().enumerate() {
+                    assert!(!free.contains(&entry), "object {} of chunk {} has a free entry", position, index);
+
+                    let stored = if position < chunk.items.len() {
+                        chunk.items[position..].as_ptr() as *mut T
+                    } else {
+                        std::ptr::null_mut()
+                    };
+                    assert!(unsafe { (*entry).ptr.get() } == stored,
+                            "the entry of object {} of chunk {} points elsewhere", position, index);
+                }
+            }
+        }
+    }
+}
+
+/// The object a handle points at. Indirect handles have a null `ptr`, and
+/// look the object up in the entry their `slot` belongs to, giving a null
+/// pointer if the object hasn't been stored yet.
+pub fn resolve<T>(ptr: *mut T, slot: *const Slot) -> *mut T {
+    if ptr.is_null() {
+        unsafe { (*(slot as *const Entry<T>)).ptr.get() }
+    } else {
+        ptr
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create a new indirect arena with an unspecified capacity. See
+    /// `indirect_with_capacity`.
+    pub fn indirect() -> Arena<T> {
+        Arena::indirect_with_capacity(8)
+    }
+
+    /// Create a new indirect arena with a known initial capacity. Its handles
+    /// go through a table of stable entries, which costs an extra pointer
+    /// lookup on every access, but allows `compact` to move the objects.
+    pub fn indirect_with_capacity(n: usize) -> Arena<T> {
+        let mut chunks = Chunks::new(vec![]);
+        chunks.table = Some(Table::new());
+        let first = chunks.new_chunk(n);
+        chunks.list.push(first);
+
+        Arena::from_chunks(chunks)
+    }
+
+    /// Drop the objects of an indirect arena which nothing refers to anymore,
+    /// and move the rest into a single chunk, keeping their order. Handles
+    /// are unaffected, but checkpoints taken beforehand must not be used.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::indirect_with_capacity(4);
+    /// let kept: Vec<_> = (0..100).map(|i| arena.alloc(i))
+    ///                            .filter(|i| **i % 10 == 0)
+    ///                            .collect();
+    /// assert_eq!(arena.len(), 100);
+    ///
+    /// unsafe { arena.compact() };
+    /// assert_eq!(arena.len(), 10);
+    /// assert_eq!(*kept[3], 30);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// No references to the arena's objects may be alive, whether borrowed
+    /// from a handle, held through `StableDeref` by a crate like `owning_ref`,
+    /// or returned by `alloc_ref` or `get`, since they would be left
+    /// dangling. `Handle`s and `Rc32`s taken beforehand must not be used
+    /// afterwards, since the objects they refer to will have moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena wasn't created with `indirect`, if an object is
+    /// being constructed by `alloc_cyclic`, or if the arena has been frozen.
+    pub unsafe fn compact(&self) {
+        // The objects are only dropped once the borrow is released, in case
+        // their destructors want to use the arena.
+        let _removed = rebuild(&mut self.chunks.borrow_mut(), "compact", true, |_, _| Ordering::Equal);
+    }
+
+    /// Reorder the objects of an indirect arena by `cmp`, moving them into a
+    /// single chunk, so that iterating visits them in that order from then
+    /// on. Objects which compare equal keep their order. Handles are
+    /// unaffected, but checkpoints taken beforehand must not be used.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::indirect_with_capacity(2);
+    /// let words: Vec<_> = "delta alpha charlie bravo".split(' ').map(|word| arena.alloc(word)).collect();
+    ///
+    /// unsafe { arena.sort_by(|a, b| a.cmp(b)) };
+    /// let sorted: Vec<_> = arena.iter().map(|word| *word).collect();
+    /// assert_eq!(sorted, ["alpha", "bravo", "charlie", "delta"]);
+    /// assert_eq!(*words[0], "delta");
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as for `compact`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `compact`, or if `cmp` uses the
+    /// arena.
+    pub unsafe fn sort_by<F: FnMut(&T, &T) -> Ordering>(&self, cmp: F) {
+        rebuild(&mut self.chunks.borrow_mut(), "sort", false, cmp);
+    }
+}
+
+/// Move the objects of an indirect arena into a single chunk in the order
+/// given by `cmp`, pointing their entries at their new places, and return
+/// the objects which were removed because nothing referred to them, if
+/// `remove` is set.
+unsafe fn rebuild<T, F>(chunks: &mut Chunks<T>, what: &str, remove: bool, mut cmp: F) -> Vec<T>
+    where F: FnMut(&T, &T) -> Ordering
+{
+    assert!(chunks.list.iter().all(|chunk| !chunk.reserved),
+            "can't {} an arena while an object is being constructed", what);
+    assert!(chunks.list.iter().all(|chunk| !chunk.items.is_frozen()),
+            "can't {} an arena which has been frozen", what);
+    assert!(chunks.table.is_some(), "only indirect arenas can be {}ed", what);
+
+    // Everything which can panic, `cmp` included, happens before the chunks
+    // are taken apart, so that a panic leaves the arena as it was.
+    let keep: Vec<Vec<bool>> = chunks.list.iter().map(|chunk| {
+        chunk.slots.iter_from(0).take(chunk.items.len()).map(|slot| !remove || slot.is_referenced()).collect()
+    }).collect();
+    let positions: Vec<(usize, usize)> = keep.iter().enumerate().flat_map(|(chunk, keep)| {
+        keep.iter().enumerate().filter(|&(_, &keep)| keep).map(move |(index, _)| (chunk, index))
+    }).collect();
+
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    {
+        let list = &chunks.list;
+        let item = |position: usize| &list[positions[position].0].items[positions[position].1];
+        order.sort_by(|&a, &b| cmp(item(a), item(b)));
+    }
+    let mut compacted = chunks.new_chunk(std::cmp::max(positions.len(), 1));
+    let columns: Vec<_> = chunks.list.iter_mut().map(|chunk| chunk.meta.take()).collect();
+    compacted.meta = columns.iter().flatten().next().map(|column| column.empty());
+
+    let mut kept = vec![];
+    #[cfg(feature = "alloc-backtraces")]
+    let mut kept_backtraces = vec![];
+    let mut removed = vec![];
+    {
+        let table = chunks.table.as_mut().unwrap();
+
+        for (mut chunk, keep) in chunks.list.drain(..).zip(keep) {
+            let items = chunk.items.split_off(0);
+            #[cfg(feature = "alloc-backtraces")]
+            let mut backtraces = std::mem::take(&mut chunk.backtraces).into_iter();
+            let entries = match chunk.slots {
+                Slots::Table(ref mut entries) => std::mem::take(entries),
+                Slots::Inline(_) => unreachable!()
+            };
+
+            for ((item, entry), keep) in items.into_iter().zip(entries).zip(keep) {
+                #[cfg(feature = "alloc-backtraces")]
+                let backtrace = backtraces.next().and_then(|backtrace| backtrace);
+
+                if keep {
+                    kept.push(Some((item, entry)));
+                    #[cfg(feature = "alloc-backtraces")]
+                    kept_backtraces.push(backtrace);
+                } else {
+                    table.remove(entry);
+                    if std::mem::needs_drop::<T>() {
+                        removed.push(item);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc-backtraces")]
+    let mut kept_backtraces: Vec<_> = kept_backtraces.into_iter().map(Some).collect();
+    for (index, &from) in order.iter().enumerate() {
+        let (item, entry) = kept[from].take().unwrap();
+        compacted.items.push(item);
+        if let Slots::Table(ref mut entries) = compacted.slots {
+            entries.push(entry);
+        }
+        compacted.placed(index);
+        #[cfg(feature = "alloc-backtraces")]
+        compacted.backtraces.push(kept_backtraces[from].take().unwrap());
+        if let Some(ref mut column) = compacted.meta {
+            let (chunk, index) = positions[from];
+            column.push_from(columns[chunk].as_deref(), index);
+        }
+    }
+    chunks.list.push(compacted);
+
+    removed
+}
+
+#[test]
+fn compacts() {
+    use {Rc, Weak};
+
+    let token = std::rc::Rc::new(());
+    let arena = Arena::indirect_with_capacity(2);
+    let handles: Vec<_> = (0..20).map(|i| arena.alloc((i, token.clone()))).collect();
+    let weak = Rc::downgrade(&handles[7]);
+    let kept: Vec<_> = handles.into_iter().filter(|item| item.0 % 5 == 0).collect();
+
+    unsafe { arena.compact() };
+    assert_eq!(arena.len(), 5);
+    assert_eq!(arena.chunks.borrow().list.len(), 1);
+    assert_eq!(std::rc::Rc::strong_count(&token), 6);
+    for (i, item) in kept.iter().enumerate() {
+        assert_eq!(item.0, i * 5);
+    }
+    assert_eq!(weak.upgrade().unwrap().0, 7);
+
+    // Entries are reused, and new objects go after the compacted ones.
+    let node = arena.alloc_cyclic(|me: &Weak<(usize, _)>| {
+        assert!(me.upgrade().is_none());
+        (20, token.clone())
+    });
+    drop(weak);
+    unsafe { arena.compact() };
+    assert_eq!(node.0, 20);
+
+    let mut expected = vec![0, 5, 10, 15, 20].into_iter();
+    arena.each(|item| assert_eq!(item.0, expected.next().unwrap()));
+    assert!(expected.next().is_none());
+
+    let checkpoint = arena.checkpoint();
+    arena.alloc((21, token.clone()));
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(arena.len(), 5);
+}
+
+#[test]
+fn sorts() {
+    use Rc;
+
+    let arena = Arena::indirect_with_capacity(3);
+    let handles: Vec<_> = (0..10).map(|i| arena.alloc((i * 7) % 10)).collect();
+    let weak = Rc::downgrade(&handles[4]);
+    drop(handles);
+
+    unsafe { arena.sort_by(|a, b| b.cmp(a)) };
+    assert_eq!(arena.chunks.borrow().list.len(), 1);
+    assert_eq!(arena.iter().map(|i| *i).collect::<Vec<_>>(), [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    assert_eq!(*weak.upgrade().unwrap(), 8);
+
+    // A panicking comparison leaves the arena as it was.
+    let result = std::panic::catch_unwind(|| unsafe { arena.sort_by(|_, _| panic!("can't compare")) });
+    assert!(result.is_err());
+    arena.assert_invariants();
+    assert_eq!(*weak.upgrade().unwrap(), 8);
+
+    // Unreferenced objects are kept, unlike with `compact`.
+    unsafe { arena.compact() };
+    assert_eq!(arena.iter().map(|i| *i).collect::<Vec<_>>(), [8]);
+}
@@ -0,0 +1,272 @@
+//! Tearing arenas down: running finalizers over their objects before
+//! they're dropped, dropping them in reverse for objects which depend on
+//! those allocated before them, and dropping the objects of a large arena
+//! on another thread, so that their destructors don't hold up the thread
+//! which is done with them.
+
+use std::thread::JoinHandle;
+
+use {Arena, Chunks, Items};
+
+pub type Finalizer<T> = Box<dyn FnOnce(&mut dyn Iterator<Item = &T>)>;
+
+impl<T> Arena<T> {
+    /// Add a finalizer, which is called with an iterator over the arena's
+    /// objects right before they're dropped along with the arena, once it
+    /// and every `Rc` to its objects are gone. Finalizers are called in the
+    /// order they were added.
+    ///
+    /// Objects which have been handed over to a `FrozenArena` by `freeze`
+    /// aren't visited, since they aren't dropped yet.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use rc_arena::Arena;
+    ///
+    /// let total = Rc::new(Cell::new(0));
+    /// let arena = Arena::new();
+    /// let flushed = total.clone();
+    /// arena.on_drop(move |sizes| flushed.set(sizes.sum()));
+    ///
+    /// arena.alloc(100);
+    /// let kept = arena.alloc(20);
+    /// drop(arena);
+    /// assert_eq!(total.get(), 0);
+    /// drop(kept);
+    /// assert_eq!(total.get(), 120);
+    /// ```
+    pub fn on_drop<F: FnOnce(&mut dyn Iterator<Item = &T>) + 'static>(&self, f: F) {
+        self.chunks.borrow_mut().finalizers.push(Box::new(f));
+    }
+
+    /// Choose whether the arena's objects are dropped in the reverse of the
+    /// order they were allocated in once the arena is, like the locals of a
+    /// function, rather than in the order they were allocated in. Objects
+    /// removed by `rollback` or `truncate` are still dropped in order.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use rc_arena::Arena;
+    ///
+    /// struct Resource(&'static str, Rc<RefCell<Vec<&'static str>>>);
+    ///
+    /// impl Drop for Resource {
+    ///     fn drop(&mut self) {
+    ///         self.1.borrow_mut().push(self.0);
+    ///     }
+    /// }
+    ///
+    /// let released = Rc::new(RefCell::new(vec![]));
+    /// let arena = Arena::new();
+    /// arena.set_reverse_drop(true);
+    /// arena.alloc(Resource("device", released.clone()));
+    /// arena.alloc(Resource("buffer", released.clone()));
+    ///
+    /// drop(arena);
+    /// assert_eq!(*released.borrow(), ["buffer", "device"]);
+    /// ```
+    pub fn set_reverse_drop(&self, reverse: bool) {
+        self.chunks.borrow_mut().reverse_drop = reverse;
+    }
+}
+
+/// Call the finalizers of an arena whose objects are about to be dropped.
+pub fn finalize<T>(chunks: &mut Chunks<T>) {
+    for finalizer in std::mem::take(&mut chunks.finalizers) {
+        let mut objects = chunks.list.iter().filter(|chunk| !chunk.items.is_frozen()).flat_map(|chunk| chunk.items.iter());
+        finalizer(&mut objects);
+    }
+}
+
+/// Drop the objects of an arena which is being dropped from the last one
+/// back, if it was asked to. Otherwise they're dropped along with their
+/// chunks.
+pub fn drop_objects<T>(chunks: &mut Chunks<T>) {
+    if !chunks.reverse_drop || !std::mem::needs_drop::<T>() {
+        return;
+    }
+
+    // Frozen objects belong to a `FrozenArena` as well.
+    for chunk in chunks.list.iter_mut().rev().filter(|chunk| !chunk.items.is_frozen()) {
+        drop_reversed(chunk.items.split_off(0));
+    }
+}
+
+fn drop_reversed<T>(mut objects: Vec<T>) {
+    while let Some(object) = objects.pop() {
+        drop(object);
+    }
+}
+
+impl<T: Send + 'static> Arena<T> {
+    /// Drop the arena, leaving its objects to be dropped on a new thread,
+    /// which can be joined to wait for them. This fails, giving the arena
+    /// back, if anything else still refers to the arena or its objects with
+    /// an `Arena` or `Rc`.
+    ///
+    /// Only chunks on the heap are handed over; objects in memory from a
+    /// buffer, an allocator or a file are dropped right away.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let arena = Arena::new();
+    /// for i in 0..1000 {
+    ///     arena.alloc(vec![i; 16]);
+    /// }
+    ///
+    /// let handle = arena.drop_in_background().ok().unwrap();
+    /// handle.join().unwrap();
+    /// ```
+    pub fn drop_in_background(self) -> Result<JoinHandle<()>, Arena<T>> {
+        if std::rc::Rc::strong_count(&self.chunks) != 1 {
+            return Err(self);
+        }
+
+        let (finalizers, items, wipe, reverse) = {
+            let mut chunks = self.chunks.borrow_mut();
+            let finalizers = std::mem::take(&mut chunks.finalizers);
+            let items: Vec<Vec<T>> = chunks.list.iter_mut().filter_map(|chunk| {
+                match chunk.items {
+                    Items::Heap(ref mut vec) => Some(std::mem::take(vec)),
+                    _ => None
+                }
+            }).collect();
+
+            #[cfg(feature = "zeroize")]
+            let wipe = chunks.wipe;
+            #[cfg(not(feature = "zeroize"))]
+            let wipe: Option<fn(&mut T)> = None;
+
+            (finalizers, items, wipe, chunks.reverse_drop)
+        };
+
+        // The finalizers see the objects before they're handed over, with the
+        // arena released so that they can still reach it through a weak
+        // pointer. It's only borrowed again if objects outside the heap are
+        // left in it.
+        let rest = self.chunks.borrow().list.iter().any(|chunk| {
+            !chunk.items.is_frozen() && !chunk.items.is_empty()
+        });
+        for finalizer in finalizers {
+            let chunks = if rest { Some(self.chunks.borrow()) } else { None };
+            let mut objects = items.iter().flat_map(|vec| vec.iter()).chain(
+                chunks.iter().flat_map(|chunks| chunks.list.iter())
+                      .filter(|chunk| !chunk.items.is_frozen())
+                      .flat_map(|chunk| chunk.items.iter()));
+            finalizer(&mut objects);
+        }
+        drop(self);
+
+        Ok(std::thread::spawn(move || {
+            let mut items = items;
+            if reverse {
+                items.reverse();
+            }
+
+            for mut chunk in items {
+                if let Some(wipe) = wipe {
+                    chunk.iter_mut().for_each(wipe);
+                }
+                if reverse {
+                    drop_reversed(chunk);
+                }
+            }
+        }))
+    }
+}
+
+#[test]
+fn drops_in_background() {
+    use std::sync::mpsc::{channel, Sender};
+
+    struct Foo(Sender<std::thread::ThreadId>);
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            self.0.send(std::thread::current().id()).unwrap();
+        }
+    }
+
+    let (tx, rx) = channel();
+    let arena = Arena::with_capacity(4);
+    for _ in 0..10 {
+        arena.alloc(Foo(tx.clone()));
+    }
+
+    let handle = arena.alloc(Foo(tx.clone()));
+    let arena = arena.drop_in_background().unwrap_err();
+    drop(handle);
+    let background = arena.drop_in_background().ok().unwrap().thread().id();
+    drop(tx);
+
+    let dropped: Vec<_> = rx.iter().collect();
+    assert_eq!(dropped.len(), 11);
+    assert!(dropped.iter().all(|&id| id == background));
+}
+
+#[test]
+fn finalizes() {
+    use std::cell::RefCell;
+
+    let events = std::rc::Rc::new(RefCell::new(vec![]));
+    let arena = Arena::with_capacity(2);
+    for i in 0..5 {
+        arena.alloc(i);
+    }
+    for name in ["first", "second"] {
+        let events = events.clone();
+        arena.on_drop(move |objects| events.borrow_mut().push((name, objects.sum::<usize>())));
+    }
+    assert!(events.borrow().is_empty());
+
+    arena.drop_in_background().ok().unwrap().join().unwrap();
+    assert_eq!(*events.borrow(), [("first", 10), ("second", 10)]);
+}
+
+#[test]
+fn finalizes_with_the_arena_released() {
+    let arena = Arena::new();
+    arena.alloc(1);
+    let weak = arena.downgrade();
+    arena.on_drop(move |objects| {
+        assert_eq!(objects.count(), 1);
+        // The arena is still there, and no longer borrowed.
+        let arena = weak.upgrade().unwrap();
+        arena.alloc(2);
+        assert_eq!(arena.len(), 1);
+    });
+
+    arena.drop_in_background().ok().unwrap().join().unwrap();
+}
+
+#[test]
+fn drops_in_reverse() {
+    use std::sync::mpsc::{channel, Sender};
+
+    struct Foo(usize, Sender<usize>);
+
+    impl Drop for Foo {
+        fn drop(&mut self) {
+            self.1.send(self.0).unwrap();
+        }
+    }
+
+    let (tx, rx) = channel();
+    for background in [false, true] {
+        let arena = Arena::with_capacity(2);
+        arena.set_reverse_drop(true);
+        for i in 0..5 {
+            arena.alloc(Foo(i, tx.clone()));
+        }
+
+        if background {
+            arena.drop_in_background().ok().unwrap().join().unwrap();
+        } else {
+            drop(arena);
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [4, 3, 2, 1, 0]);
+    }
+}
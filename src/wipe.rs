@@ -0,0 +1,112 @@
+//! Arenas which wipe their objects with `Zeroize` once they're done with
+//! them, for secrets such as key schedules and nonces.
+
+use zeroize::Zeroize;
+
+use {Arena, Chunks};
+
+impl<T: Zeroize + 'static> Arena<T> {
+    /// Create a new zeroizing arena with an unspecified capacity. See
+    /// `zeroizing_with_capacity`.
+    pub fn zeroizing() -> Arena<T> {
+        Arena::zeroizing_with_capacity(8)
+    }
+
+    /// Create a new arena with a known initial capacity, which wipes its
+    /// objects so that they don't linger in memory. It's an object pool like
+    /// one created with `pool`, which wipes each object once the last `Rc` or
+    /// `Weak` to it is dropped, before reusing its place. The remaining
+    /// objects are wiped before they're dropped, whether by `rollback` or
+    /// along with the arena.
+    ///
+    /// Objects which are handed over to a `FrozenArena` by `freeze` aren't
+    /// wiped.
+    ///
+    /// ```rust
+    /// use rc_arena::Arena;
+    ///
+    /// let keys = Arena::zeroizing();
+    /// let key = keys.alloc([7u8; 32]);
+    /// let kept = keys.alloc([9u8; 32]);
+    ///
+    /// drop(key);
+    /// assert_eq!(*keys.first().unwrap(), [0; 32]);
+    /// assert_eq!(*kept, [9; 32]);
+    /// ```
+    pub fn zeroizing_with_capacity(n: usize) -> Arena<T> {
+        let arena = Arena::pool_with_capacity(n, T::zeroize);
+        arena.chunks.borrow_mut().wipe = Some(T::zeroize);
+
+        arena
+    }
+}
+
+/// Wipe every object of an arena which is being dropped.
+pub fn wipe_all<T>(chunks: &mut Chunks<T>) {
+    if let Some(wipe) = chunks.wipe {
+        // Frozen objects belong to a `FrozenArena` as well.
+        for chunk in chunks.list.iter_mut().filter(|chunk| !chunk.items.is_frozen()) {
+            chunk.items.iter_mut().for_each(wipe);
+        }
+    }
+}
+
+/// Zero the memory that `len` objects from `ptr` were moved out of, which is
+/// left uninitialized, so it's written to byte by byte.
+pub unsafe fn wipe_moved<T>(ptr: *mut T, len: usize) {
+    let bytes = ptr as *mut u8;
+    for offset in 0..len * std::mem::size_of::<T>() {
+        std::ptr::write_volatile(bytes.add(offset), 0);
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+#[test]
+fn wipes() {
+    use std::cell::RefCell;
+
+    // Records what each object held when it was dropped.
+    struct Secret(u8, std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Zeroize for Secret {
+        fn zeroize(&mut self) {
+            self.0.zeroize();
+        }
+    }
+
+    impl Drop for Secret {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    let dropped = std::rc::Rc::new(RefCell::new(vec![]));
+    let arena = Arena::zeroizing_with_capacity(1);
+    arena.alloc(Secret(1, dropped.clone()));
+    let checkpoint = arena.checkpoint();
+    arena.alloc(Secret(2, dropped.clone()));
+    arena.alloc(Secret(3, dropped.clone()));
+    arena.rollback(checkpoint).unwrap();
+    assert_eq!(*dropped.borrow(), [0, 0]);
+
+    let held = arena.alloc(Secret(4, dropped.clone()));
+    assert_eq!(held.0, 4);
+    drop(arena);
+    drop(held);
+    assert_eq!(*dropped.borrow(), [0, 0, 0, 0]);
+}
+
+#[test]
+fn wipes_split_off() {
+    let arena = Arena::zeroizing_with_capacity(1);
+    arena.append_vec(vec![[7u8; 8]; 3]);
+    let chunk = arena.chunks().find(|chunk| !chunk.is_empty()).unwrap().as_ptr();
+
+    // The objects which are moved out leave nothing behind, though poisoning
+    // may write over the zeroes.
+    let batch = arena.split_off(1).unwrap();
+    assert_eq!(batch.to_vec(), [[7; 8]; 2]);
+    let left = unsafe { [*chunk, *chunk.add(1), *chunk.add(2)] };
+    assert!(left[0] == [7; 8] && left[1..].iter().flatten().all(|&byte| byte != 7));
+    assert_eq!(arena.len(), 1);
+}